@@ -9,8 +9,6 @@ pub struct DemoApp {
     #[serde(skip)]
     task_pool: Option<task_simple::TaskPool<crate::FileSizeFunction>>,
     #[serde(skip)]
-    ongoing: Vec<task_simple::Ticket>,
-    #[serde(skip)]
     files: Vec<crate::FileSize>,
 }
 
@@ -21,7 +19,6 @@ impl Default for DemoApp {
             value: 2.7,
             task: Default::default(),
             task_pool: Default::default(),
-            ongoing: Default::default(),
             files: Default::default(),
         }
     }
@@ -35,12 +32,16 @@ impl DemoApp {
             Self::default()
         };
 
+        let mut task = task_simple::Task::new("doubling_worker");
+        let ctx = cc.egui_ctx.clone();
+        task.set_notifier(move || ctx.request_repaint());
+        let mut task_pool = task_simple::TaskPool::new("file_worker", previous.task_pool_size);
+        let ctx = cc.egui_ctx.clone();
+        task_pool.set_notifier(move || ctx.request_repaint());
+
         Self {
-            task: Some(task_simple::Task::new("doubling_worker")),
-            task_pool: Some(task_simple::TaskPool::new(
-                "file_worker",
-                previous.task_pool_size,
-            )),
+            task: Some(task),
+            task_pool: Some(task_pool),
             ..previous
         }
     }
@@ -53,22 +54,18 @@ impl eframe::App for DemoApp {
 
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         for file in ctx.input_mut(|x| std::mem::take(&mut x.raw.dropped_files)) {
-            self.ongoing
-                .push(self.task_pool.as_mut().unwrap().enqueue(file));
+            let _ticket = self.task_pool.as_mut().unwrap().enqueue(file);
         }
         if let Some(update) = self.task.as_mut().unwrap().check() {
             log::debug!("Received update: {update:?}");
             self.value = update as _;
         }
-        for ticket in std::mem::take(&mut self.ongoing) {
-            match self.task_pool.as_mut().unwrap().check(ticket) {
-                task_simple::JobState::Ongoing(ticket) => self.ongoing.push(ticket),
-                task_simple::JobState::Done(file_size) => {
-                    log::debug!("Received update: {file_size:?}");
-                    self.files.push(file_size);
-                }
-            }
+        for (_ticket, file_size) in self.task_pool.as_mut().unwrap().drain_completed_in_order() {
+            log::debug!("Received update: {file_size:?}");
+            self.files.push(file_size);
         }
+        // this includes jobs which crashed, unlike counting the drained outputs
+        let ongoing = self.task_pool.as_ref().unwrap().ongoing_jobs();
 
         egui::CentralPanel::default().show(ctx, |ui| {
             // Task pool size
@@ -89,10 +86,7 @@ impl eframe::App for DemoApp {
             // Files - task pool
             {
                 ui.label("You can drop files here");
-                ui.label(&format!(
-                    "Files being processed in background: {}",
-                    self.ongoing.len()
-                ));
+                ui.label(&format!("Files being processed in background: {ongoing}"));
                 egui::Grid::new("files").num_columns(3).show(ui, |ui| {
                     {
                         ui.heading("File");
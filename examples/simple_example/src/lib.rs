@@ -3,15 +3,7 @@
 mod app;
 pub use app::DemoApp;
 
-#[derive(Default)]
-pub struct DoublingFunction {}
-impl task_simple::Function for DoublingFunction {
-    type Input = f32;
-    type Output = f64;
-    fn call(&mut self, input: Self::Input) -> Self::Output {
-        doubling(input)
-    }
-}
+#[task_simple::function]
 pub fn doubling(x: f32) -> f64 {
     x as f64 * 0.92345
 }
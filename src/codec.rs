@@ -0,0 +1,213 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+/// This trait abstracts how messages are serialized whenever they cross the worker boundary
+/// (web worker bridge, worker processes, ...).
+/// The owner and the worker side have to use the same codec.
+pub trait Codec: 'static {
+    /// Serialize a message
+    fn encode<T: Serialize>(message: &T) -> Result<Vec<u8>, CodecError>;
+    /// Deserialize a message
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CodecError>;
+}
+
+/// Error of a [`Codec`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodecError(String);
+impl CodecError {
+    /// Create a new error from a message
+    pub fn new(message: impl std::fmt::Display) -> Self {
+        Self(message.to_string())
+    }
+}
+impl std::fmt::Display for CodecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+impl std::error::Error for CodecError {}
+
+/// Codec using [bincode](https://docs.rs/bincode). This is the default codec.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Bincode;
+impl Codec for Bincode {
+    fn encode<T: Serialize>(message: &T) -> Result<Vec<u8>, CodecError> {
+        bincode::serialize(message).map_err(CodecError::new)
+    }
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CodecError> {
+        bincode::deserialize(bytes).map_err(CodecError::new)
+    }
+}
+
+/// Codec using JSON, which is useful for debugging
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json;
+#[cfg(feature = "json")]
+impl Codec for Json {
+    fn encode<T: Serialize>(message: &T) -> Result<Vec<u8>, CodecError> {
+        serde_json::to_vec(message).map_err(CodecError::new)
+    }
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CodecError> {
+        serde_json::from_slice(bytes).map_err(CodecError::new)
+    }
+}
+
+/// Codec using [postcard](https://docs.rs/postcard), which yields small messages
+#[cfg(feature = "postcard")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Postcard;
+#[cfg(feature = "postcard")]
+impl Codec for Postcard {
+    fn encode<T: Serialize>(message: &T) -> Result<Vec<u8>, CodecError> {
+        postcard::to_allocvec(message).map_err(CodecError::new)
+    }
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CodecError> {
+        postcard::from_bytes(bytes).map_err(CodecError::new)
+    }
+}
+
+/// Largest message which [`Compressed`] decompresses, like the frames of the process backend
+#[cfg(feature = "compression")]
+const MAX_DECOMPRESSED_SIZE: usize = 1 << 30;
+
+/// Codec wrapping another codec: messages larger than `THRESHOLD` bytes are compressed (using lz4)
+#[cfg(feature = "compression")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Compressed<C, const THRESHOLD: usize = 1024>(std::marker::PhantomData<C>);
+#[cfg(feature = "compression")]
+impl<C: Codec, const THRESHOLD: usize> Codec for Compressed<C, THRESHOLD> {
+    fn encode<T: Serialize>(message: &T) -> Result<Vec<u8>, CodecError> {
+        let bytes = C::encode(message)?;
+        Ok(if bytes.len() > THRESHOLD {
+            let mut compressed = vec![1];
+            compressed.extend(lz4_flex::compress_prepend_size(&bytes));
+            compressed
+        } else {
+            let mut uncompressed = Vec::with_capacity(bytes.len() + 1);
+            uncompressed.push(0);
+            uncompressed.extend(bytes);
+            uncompressed
+        })
+    }
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CodecError> {
+        match bytes.split_first() {
+            Some((0, bytes)) => C::decode(bytes),
+            Some((1, bytes)) => {
+                // the size is checked first, so a corrupted frame cannot force a large allocation
+                let size = bytes
+                    .first_chunk::<4>()
+                    .map(|size| u32::from_le_bytes(*size) as usize)
+                    .ok_or_else(|| CodecError::new("Missing decompressed size"))?;
+                if size > MAX_DECOMPRESSED_SIZE {
+                    return Err(CodecError::new(format!(
+                        "Decompressed size {size} exceeds the limit"
+                    )));
+                }
+                C::decode(&lz4_flex::decompress_size_prepended(bytes).map_err(CodecError::new)?)
+            }
+            _ => Err(CodecError::new("Invalid compression marker")),
+        }
+    }
+}
+
+/// Adapter, so a [`Codec`] can be used for the web worker bridge
+/// (see `WorkerRegistrar::encoding` of `gloo_worker`).
+/// Workers of tasks using a codec other than [`Bincode`] have to be registered using
+/// `WebWorker::<F>::registrar().encoding::<GlooCodec<C>>()`.
+#[cfg(target_arch = "wasm32")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GlooCodec<C>(std::marker::PhantomData<C>);
+#[cfg(target_arch = "wasm32")]
+impl<C: Codec> gloo_worker::Codec for GlooCodec<C> {
+    fn encode<I>(input: I) -> wasm_bindgen::JsValue
+    where
+        I: Serialize,
+    {
+        let bytes = C::encode(&input).expect("Failed to serialize worker message");
+        js_sys::Uint8Array::from(bytes.as_slice()).into()
+    }
+
+    fn decode<O>(input: wasm_bindgen::JsValue) -> O
+    where
+        O: for<'de> serde::Deserialize<'de>,
+    {
+        let bytes = js_sys::Uint8Array::from(input).to_vec();
+        C::decode(&bytes).expect("Failed to deserialize worker message")
+    }
+}
+
+/// Encoding and decoding of a single message type, with the codec chosen at construction
+pub(crate) struct Coder<T> {
+    encode: fn(&T) -> Result<Vec<u8>, CodecError>,
+    decode: fn(&[u8]) -> Result<T, CodecError>,
+}
+impl<T> Clone for Coder<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<T> Copy for Coder<T> {}
+impl<T: Serialize + DeserializeOwned> Coder<T> {
+    pub(crate) fn new<C: Codec>() -> Self {
+        Self {
+            encode: C::encode::<T>,
+            decode: C::decode::<T>,
+        }
+    }
+}
+impl<T> Coder<T> {
+    pub(crate) fn encode(&self, message: &T, what: &str) -> Vec<u8> {
+        (self.encode)(message).unwrap_or_else(|e| panic!("Failed to serialize {what}: {e}"))
+    }
+    pub(crate) fn decode(&self, bytes: &[u8], what: &str) -> T {
+        (self.decode)(bytes).unwrap_or_else(|e| panic!("Failed to deserialize {what}: {e}"))
+    }
+    /// Encode a message, reporting a failure to the peer instead of panicking
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn try_encode(&self, message: &T) -> Result<Vec<u8>, CodecError> {
+        (self.encode)(message)
+    }
+    /// Decode a message, which may come from a misbehaving peer
+    #[cfg(any(target_arch = "wasm32", feature = "process"))]
+    pub(crate) fn try_decode(&self, bytes: &[u8]) -> Result<T, CodecError> {
+        (self.decode)(bytes)
+    }
+}
+
+#[test]
+fn test_codecs() {
+    #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    struct Message {
+        name: String,
+        blob: Vec<u8>,
+    }
+    fn round_trip<C: Codec>() -> usize {
+        let message = Message {
+            name: "blob".into(),
+            blob: vec![7; 4096],
+        };
+        let bytes = C::encode(&message).unwrap();
+        assert_eq!(C::decode::<Message>(&bytes).unwrap(), message);
+        bytes.len()
+    }
+    round_trip::<Bincode>();
+    #[cfg(feature = "json")]
+    round_trip::<Json>();
+    #[cfg(feature = "postcard")]
+    round_trip::<Postcard>();
+    #[cfg(feature = "compression")]
+    {
+        assert!(round_trip::<Compressed<Bincode>>() < 1024);
+        // a corrupted size is rejected before decompressing
+        let mut bytes = vec![1];
+        bytes.extend(u32::MAX.to_le_bytes());
+        bytes.extend([0; 16]);
+        assert_eq!(
+            Compressed::<Bincode>::decode::<Message>(&bytes),
+            Err(CodecError::new(
+                "Decompressed size 4294967295 exceeds the limit"
+            ))
+        );
+        assert!(Compressed::<Bincode>::decode::<Message>(&[1, 0]).is_err());
+    }
+}
@@ -16,11 +16,60 @@
 //! This is a basic implementation of a task which can be used but with Standard (Linux,Windows, MacOs) and Wasm (Web).
 //! A task means here a function which can be run in the background (Standard:Thread, Wasm: WebWorker).
 mod task;
+#[cfg(all(not(target_arch = "wasm32"), feature = "process"))]
+pub use task::ProcessWorker;
 #[cfg(target_arch = "wasm32")]
 pub use task::{gloo_worker, WebWorker};
-pub use task::{Function, JobState, Task, TaskPool, Ticket};
+pub use task::{
+    Backend, Envelope, Function, FunctionSet, Interaction, Interactive, InteractiveFunction,
+    InteractiveStep, InteractiveTask, JobCrashed, JobState, JobStatus, MultiFunction, Pipeline,
+    Retention, Task, TaskPool, Ticket,
+};
+#[cfg(not(target_arch = "wasm32"))]
+pub use task::{
+    Blocking, BlockingFunction, Closure, ClosuresUnsupported, JobHandle, NativeFunction, Requester,
+};
+
+mod codec;
+#[cfg(feature = "compression")]
+pub use codec::Compressed;
+#[cfg(target_arch = "wasm32")]
+pub use codec::GlooCodec;
+#[cfg(feature = "json")]
+pub use codec::Json;
+#[cfg(feature = "postcard")]
+pub use codec::Postcard;
+pub use codec::{Bincode, Codec, CodecError};
+#[cfg(feature = "macros")]
+pub use task_simple_macros::function;
+mod notifier;
+#[cfg(all(not(target_arch = "wasm32"), feature = "serde-parity"))]
+mod parity;
 
 mod ongoing_task;
 #[cfg(target_arch = "wasm32")]
-pub use ongoing_task::WebWorkerBackground;
-pub use ongoing_task::{BackgroundFunction, BackgroundTask, StateProgress, StateTrait};
+pub use ongoing_task::{AnswerQueries, Queries, WebWorkerBackground};
+pub use ongoing_task::{
+    BackgroundFunction, BackgroundStatus, BackgroundTask, BackgroundTaskPool, Checkpoint, Crash,
+    EventBacklog, EventPolicy, Fault, Overflow, PoolInput, PoolState, PooledFunction, QueryState,
+    QueryTicket, QueryableFunction, Restart, RestartFrom, ScheduleId, Simulation, StateProgress,
+    StateTrait, Supervisor, TriggerContext, TriggerId, TriggerPolicy,
+};
+
+/// Define the `main` of a web worker binary, which runs the [`Function`] `F`. On native, `main` does nothing.
+/// Optionally, a block run before registering the worker can be passed, e.g. to install a panic hook.
+///
+/// `task_simple::worker_main!(DoublingFunction);` registers the worker `WebWorker::<DoublingFunction>`.
+#[macro_export]
+macro_rules! worker_main {
+    ($function:ty $(, $setup:block)?) => {
+        fn main() {
+            #[cfg(target_arch = "wasm32")]
+            {
+                use $crate::gloo_worker::Registrable;
+                $($setup)?
+                $crate::WebWorker::<$function>::registrar().register();
+            }
+        }
+    };
+}
@@ -0,0 +1,68 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::Condvar;
+use std::sync::{Arc, Mutex};
+
+/// A callback which is invoked whenever something happened in the background
+pub(crate) type Callback = Arc<dyn Fn() + Send + Sync>;
+
+/// Shared slot for a notification callback, which can be set after the background part is started
+#[derive(Clone, Default)]
+pub(crate) struct Notifier(Arc<Mutex<Slot>>);
+#[derive(Default)]
+struct Slot {
+    callback: Option<Callback>,
+    #[cfg(not(target_arch = "wasm32"))]
+    wake: Arc<Wake>,
+}
+impl Notifier {
+    pub(crate) fn set(&self, callback: Option<Callback>) {
+        self.0.lock().unwrap().callback = callback;
+    }
+    pub(crate) fn notify(&self) {
+        // the lock is released before the callback is invoked
+        let slot = self.0.lock().unwrap();
+        let callback = slot.callback.clone();
+        #[cfg(not(target_arch = "wasm32"))]
+        let wake = slot.wake.clone();
+        drop(slot);
+        #[cfg(not(target_arch = "wasm32"))]
+        wake.wake();
+        if let Some(callback) = callback {
+            callback();
+        }
+    }
+    /// Standard only: Let this notifier wake the given waiters, e.g. shared by all tasks of a pool
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn set_wake(&self, wake: Arc<Wake>) {
+        self.0.lock().unwrap().wake = wake;
+    }
+}
+
+/// Standard only: Lets the owner block until a notifier is invoked, independent of the callback
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Default)]
+pub(crate) struct Wake {
+    /// Number of notifications so far
+    notified: Mutex<u64>,
+    condvar: Condvar,
+}
+#[cfg(not(target_arch = "wasm32"))]
+impl Wake {
+    /// Number of notifications so far, pass it to [`Wake::wait`] after checking for progress
+    pub(crate) fn notified(&self) -> u64 {
+        *self.notified.lock().unwrap()
+    }
+    /// Block until there was a notification since `seen` was taken
+    pub(crate) fn wait(&self, seen: u64) {
+        let notified = self.notified.lock().unwrap();
+        let _notified = self
+            .condvar
+            .wait_while(notified, |notified| *notified == seen)
+            .unwrap();
+    }
+    fn wake(&self) {
+        let mut notified = self.notified.lock().unwrap();
+        *notified = notified.wrapping_add(1);
+        self.condvar.notify_all();
+    }
+}
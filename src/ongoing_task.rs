@@ -1,10 +1,32 @@
+mod checkpoint;
+mod events;
+mod pool;
+mod query;
+mod schedule;
+mod simulation;
+mod status;
 #[cfg(not(target_arch = "wasm32"))]
 mod std_task;
+mod supervisor;
+mod triggers;
 #[cfg(target_arch = "wasm32")]
 mod wasm_task;
+mod worker;
+mod worker_loop;
 
+use worker::Worker;
+
+pub use checkpoint::Checkpoint;
+pub use events::{EventBacklog, EventPolicy, Overflow};
+pub use pool::{BackgroundTaskPool, PoolInput, PoolState, PooledFunction};
+pub use query::{QueryState, QueryTicket};
+pub use schedule::{ScheduleId, TriggerContext};
+pub use simulation::{Fault, Simulation};
+pub use status::BackgroundStatus;
+pub use supervisor::{Crash, Restart, RestartFrom, Supervisor};
+pub use triggers::{TriggerId, TriggerPolicy};
 #[cfg(target_arch = "wasm32")]
-pub use wasm_task::WebWorkerBackground;
+pub use wasm_task::{AnswerQueries, Queries, WebWorkerBackground};
 
 /// This trait abstracts a function, which can be run independently
 pub trait BackgroundFunction: 'static + Default + Sized {
@@ -17,6 +39,10 @@ pub trait BackgroundFunction: 'static + Default + Sized {
     type Trigger: serde::Serialize + serde::de::DeserializeOwned + Send;
     /// An event produced by the background task
     type Event: serde::Serialize + serde::de::DeserializeOwned + Send;
+    /// How pending events are collected until they are fetched
+    const EVENT_POLICY: EventPolicy = EventPolicy::Merge;
+    /// How queued triggers are handled, by default every trigger is run
+    const TRIGGER_POLICY: TriggerPolicy = TriggerPolicy::Queue;
     /// Function to initialize state
     /// Event sender sends 'None' once this is finished
     fn initial_state<EventSender: Fn(Self::Event)>(
@@ -30,8 +56,81 @@ pub trait BackgroundFunction: 'static + Default + Sized {
         trigger: Self::Trigger,
         event_sender: EventSender,
     );
-    /// Merge two events
-    fn event_merge(event: &mut Self::Event, other: Self::Event);
+    /// Same as [`BackgroundFunction::trigger`], with a context which can also schedule further triggers.
+    /// This is what the background task calls, by default it calls [`BackgroundFunction::trigger`].
+    fn trigger_with_context(
+        state: &mut Self::State,
+        trigger: Self::Trigger,
+        context: &mut TriggerContext<'_, Self>,
+    ) {
+        Self::trigger(state, trigger, |event| context.send(event))
+    }
+    /// Merge two events, this is only used with [`EventPolicy::Merge`].
+    /// It has to be implemented for that policy (which is the default): a task of a function using it
+    /// without implementing this fails to build. Other policies do not need it.
+    fn event_merge(_event: &mut Self::Event, _other: Self::Event) {
+        const {
+            assert!(
+                !matches!(Self::EVENT_POLICY, EventPolicy::Merge),
+                "EventPolicy::Merge requires BackgroundFunction::event_merge"
+            );
+        }
+        unreachable!("Events are only merged with EventPolicy::Merge")
+    }
+    /// Merge two queued triggers, this is only used with [`TriggerPolicy::Merge`].
+    /// By default, the later trigger is kept.
+    fn trigger_merge(trigger: &mut Self::Trigger, other: Self::Trigger) {
+        *trigger = other;
+    }
+    /// Check if a trigger may be merged with (or replaced by) other queued triggers, see [`TriggerPolicy`].
+    /// Triggers which are not coalescible are run strictly in order. By default, all triggers are coalescible.
+    fn is_coalescible(_trigger: &Self::Trigger) -> bool {
+        true
+    }
+    /// Serialize the state for a [`Checkpoint`]. By default, the state does not support checkpoints.
+    /// If the state implements `serde::Serialize`, implement this as `state.serialize(serializer)`.
+    fn serialize_state<S: serde::Serializer>(
+        _state: &Self::State,
+        _serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        Err(serde::ser::Error::custom(
+            "State does not support checkpoints",
+        ))
+    }
+    /// Deserialize the state from a [`Checkpoint`], the counterpart of [`BackgroundFunction::serialize_state`].
+    /// If the state implements `serde::Deserialize`, implement this as `Self::State::deserialize(deserializer)`.
+    fn deserialize_state<'de, D: serde::Deserializer<'de>>(
+        _deserializer: D,
+    ) -> Result<Self::State, D::Error> {
+        Err(serde::de::Error::custom(
+            "State does not support checkpoints",
+        ))
+    }
+}
+
+/// This trait extends a [`BackgroundFunction`], whose state can be asked questions, see [`BackgroundTask::query`]
+pub trait QueryableFunction: BackgroundFunction {
+    /// A question about the state
+    type Query: serde::Serialize + serde::de::DeserializeOwned + Send + 'static;
+    /// The answer to a query
+    type QueryResponse: serde::Serialize + serde::de::DeserializeOwned + Send + 'static;
+    /// Answer a query, reading the state
+    fn query(state: &Self::State, query: Self::Query) -> Self::QueryResponse;
+}
+
+/// The message of a caught panic
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> String {
+    panic
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "Unknown panic".into())
+}
+
+/// How the worker obtains its first state
+enum Start<InitialState> {
+    Initial(InitialState),
+    Resume(Checkpoint),
 }
 
 #[derive(Debug)]
@@ -39,8 +138,12 @@ pub trait BackgroundFunction: 'static + Default + Sized {
 pub enum StateProgress<T> {
     /// The background task is waiting for external input
     NothingOngoing,
-    /// The backgronud task is computing something
+    /// The backgronud task is computing something. Progress is checked again immediately.
     Ongoing,
+    /// The background task is computing something, but no progress is expected within the given duration.
+    /// Progress is checked again once the duration elapsed or some trigger arrived,
+    /// so the background thread does not spin while waiting.
+    WaitFor(std::time::Duration),
     /// The background task has a message for the external world
     Event(T),
 }
@@ -51,70 +154,358 @@ pub trait StateTrait {
     type Event;
     /// Check the progress of the background task
     fn progress(&mut self) -> StateProgress<Self::Event>;
+    /// Same as [`StateTrait::progress`], with the time since the worker started (see [`TriggerContext::now`]).
+    /// This is what the background task calls, by default it calls [`StateTrait::progress`].
+    /// In a [`Simulation`], this is the virtual clock, so deadlines can be tested without waiting.
+    fn progress_at(&mut self, _now: std::time::Duration) -> StateProgress<Self::Event> {
+        self.progress()
+    }
 }
 
 /// This is a long running background task
 pub struct BackgroundTask<F: BackgroundFunction> {
-    task_ongoing: Ongoing,
-    #[cfg(not(target_arch = "wasm32"))]
-    background_task: std_task::BackgroundTaskStd<F>,
-    #[cfg(target_arch = "wasm32")]
-    background_task: wasm_task::BackgroundTaskWasm<F>,
+    status: BackgroundStatus,
+    status_notifier: Option<StatusNotifier>,
+    schedule_count: u64,
+    /// Number of triggers sent, i.e. the index of the latest trigger
+    trigger_count: u64,
+    /// All triggers up to this index are done
+    triggers_done: u64,
+    query_count: u64,
+    /// Queries which are not answered yet (`None`) or whose response is not fetched yet
+    queries: std::collections::HashMap<QueryTicket, Option<query::Answer>>,
+    events: events::EventQueue<F>,
+    notifier: crate::notifier::Notifier,
+    supervision: supervisor::Supervision<F>,
+    background_task: Worker<F>,
+    /// See [`BackgroundTask::set_parity_warn_size`], applied to restarted workers as well
+    #[cfg(all(not(target_arch = "wasm32"), feature = "serde-parity"))]
+    parity_warn_size: Option<usize>,
 }
 impl<F: BackgroundFunction> std::fmt::Debug for BackgroundTask<F> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("BackgroundTask").finish()
     }
 }
-#[derive(PartialEq, Debug)]
-enum Ongoing {
-    NotOnging,
-    Ongoing,
+type StatusNotifier = Box<dyn FnMut(&BackgroundStatus) + Send>;
+/// Sent by the worker to the owner, in order
+enum Report<F: BackgroundFunction> {
+    /// An event, tagged with the trigger which sent it
+    Event(Option<TriggerId>, F::Event),
+    /// The trigger ran, as did all triggers sent before
+    TriggerDone(TriggerId),
+    /// The response to a query
+    QueryResponse(QueryTicket, query::Answer),
 }
 impl<F: BackgroundFunction> BackgroundTask<F> {
     /// Start a new long running backround task in the background.
+    /// If the worker cannot be spawned, this is reported like a crash, see [`BackgroundStatus::Failed`].
     #[must_use]
     pub fn new(task_name: &str, initial_state: F::InitialState) -> Self {
+        Self::with_codec::<crate::Bincode>(task_name, initial_state)
+    }
+    /// Start a new long running backround task in the background, using the given codec.
+    /// Note that the web worker has to use the same codec, see [`crate::Codec`].
+    #[must_use]
+    pub fn with_codec<C: crate::Codec>(task_name: &str, initial_state: F::InitialState) -> Self {
+        Self::supervised_with_codec::<C>(task_name, initial_state, Supervisor::default())
+    }
+    /// Start a new long running background task, which is restarted according to `supervisor` if it crashes.
+    /// To be able to restart, the initial state is kept in serialized form.
+    #[must_use]
+    pub fn supervised(
+        task_name: &str,
+        initial_state: F::InitialState,
+        supervisor: Supervisor,
+    ) -> Self {
+        Self::supervised_with_codec::<crate::Bincode>(task_name, initial_state, supervisor)
+    }
+    /// Same as [`BackgroundTask::supervised`], using the given codec
+    #[must_use]
+    pub fn supervised_with_codec<C: crate::Codec>(
+        task_name: &str,
+        initial_state: F::InitialState,
+        supervisor: Supervisor,
+    ) -> Self {
+        let supervision =
+            supervisor::Supervision::new::<C>(task_name, supervisor, Some(&initial_state));
+        Self::start(supervision, Start::Initial(initial_state))
+    }
+    /// Start a background task from a snapshot taken by [`BackgroundTask::checkpoint`],
+    /// instead of calling [`BackgroundFunction::initial_state`].
+    ///
+    /// The worker panics if the snapshot cannot be deserialized.
+    #[must_use]
+    pub fn resume(task_name: &str, checkpoint: Checkpoint) -> Self {
+        Self::resume_with_codec::<crate::Bincode>(task_name, checkpoint)
+    }
+    /// Same as [`BackgroundTask::resume`], for snapshots of tasks created using [`BackgroundTask::with_codec`]
+    #[must_use]
+    pub fn resume_with_codec<C: crate::Codec>(task_name: &str, checkpoint: Checkpoint) -> Self {
+        let supervision = supervisor::Supervision::new::<C>(task_name, Supervisor::default(), None);
+        Self::start(supervision, Start::Resume(checkpoint))
+    }
+    fn start(supervision: supervisor::Supervision<F>, start: Start<F::InitialState>) -> Self {
+        let notifier = crate::notifier::Notifier::default();
+        let events = events::EventQueue::default();
         Self {
-            task_ongoing: Ongoing::Ongoing,
-            background_task: {
-                #[cfg(not(target_arch = "wasm32"))]
-                {
-                    std_task::BackgroundTaskStd::new(task_name, initial_state)
-                }
-                #[cfg(target_arch = "wasm32")]
-                {
-                    wasm_task::BackgroundTaskWasm::new(task_name, initial_state)
-                }
-            },
+            status: BackgroundStatus::Initializing,
+            status_notifier: None,
+            schedule_count: 0,
+            trigger_count: 0,
+            triggers_done: 0,
+            query_count: 0,
+            queries: Default::default(),
+            background_task: supervision.spawn(start, notifier.clone(), &events),
+            events,
+            supervision,
+            notifier,
+            #[cfg(all(not(target_arch = "wasm32"), feature = "serde-parity"))]
+            parity_warn_size: None,
+        }
+    }
+    /// Set a callback which is invoked (from the background) whenever an event is emitted
+    /// or the status of the background task changes, see [`BackgroundTask::status`].
+    /// Typically, this is used to request a repaint of the GUI instead of polling continuously.
+    pub fn set_notifier(&mut self, notifier: impl Fn() + Send + Sync + 'static) {
+        self.notifier.set(Some(std::sync::Arc::new(notifier)));
+    }
+    /// Remove the callback set by [`BackgroundTask::set_notifier`]
+    pub fn clear_notifier(&mut self) {
+        self.notifier.set(None);
+    }
+    /// Set a callback which is invoked whenever the status changes, see [`BackgroundTask::status`].
+    /// Changes are observed (and the callback is invoked) while using the background task,
+    /// so to be woken up by the background, set a notifier as well, see [`BackgroundTask::set_notifier`].
+    pub fn set_status_notifier(
+        &mut self,
+        status_notifier: impl FnMut(&BackgroundStatus) + Send + 'static,
+    ) {
+        self.status_notifier = Some(Box::new(status_notifier));
+    }
+    /// Remove the callback set by [`BackgroundTask::set_status_notifier`]
+    pub fn clear_status_notifier(&mut self) {
+        self.status_notifier = None;
+    }
+    /// Warn (using the `log` crate) about each trigger, event, query and response which is larger than `bytes`
+    /// once serialized, see [`crate::Task::set_parity_warn_size`].
+    #[cfg(all(not(target_arch = "wasm32"), feature = "serde-parity"))]
+    pub fn set_parity_warn_size(&mut self, bytes: Option<usize>) {
+        self.parity_warn_size = bytes;
+        self.background_task.set_parity_warn_size(bytes);
+    }
+    /// Limit the number of pending events, which is only relevant for [`EventPolicy::KeepAll`]
+    pub fn set_event_backlog(&mut self, backlog: EventBacklog) {
+        self.events.set_backlog(backlog);
+    }
+    /// Trigger a new action. The returned id tags the events sent by this trigger, see [`BackgroundTask::tagged_event`].
+    /// If the background task crashed, the trigger is sent once it is restarted, or dropped if it is not restarted.
+    /// Triggers sent to a crashed background task before its crash was detected are lost.
+    pub fn trigger(&mut self, trigger: F::Trigger) -> TriggerId {
+        self.trigger_count += 1;
+        let id = TriggerId::new(self.trigger_count);
+        self.supervise();
+        if self.supervision.is_restarting() {
+            self.supervision.hold(id, trigger);
+        } else if self.supervision.is_stopped() {
+            // dropped triggers are done
+            self.triggers_done = id.index();
+        } else {
+            self.mark_busy();
+            self.background_task.trigger(id, trigger);
+        }
+        id
+    }
+    /// Check if a trigger ran, so all its events are available.
+    /// Coalesced triggers (see [`TriggerPolicy`]) are done once the merged trigger ran.
+    /// Triggers lost in a crash are done once the crash is detected.
+    #[must_use]
+    pub fn is_trigger_done(&mut self, id: TriggerId) -> bool {
+        self.supervise();
+        self.fetch_events();
+        id.index() <= self.triggers_done
+    }
+
+    /// Run `trigger` once, after `delay`.
+    /// Triggers can also be scheduled by the background task itself, see [`TriggerContext`].
+    /// Note that schedules are lost if the background task is restarted, see [`Supervisor`].
+    pub fn schedule(&mut self, delay: std::time::Duration, trigger: F::Trigger) -> ScheduleId {
+        self.schedule_internal(delay, None, trigger)
+    }
+    /// Run `trigger` every `interval`, starting after `interval`. Missed runs are skipped.
+    ///
+    /// Panics if `interval` is zero.
+    pub fn schedule_every(
+        &mut self,
+        interval: std::time::Duration,
+        trigger: F::Trigger,
+    ) -> ScheduleId {
+        assert!(!interval.is_zero(), "Interval must not be zero");
+        self.schedule_internal(interval, Some(interval), trigger)
+    }
+    /// Cancel a scheduled trigger. Unknown (or finished) schedules are ignored.
+    pub fn cancel_schedule(&mut self, id: ScheduleId) {
+        self.mark_busy();
+        self.background_task.cancel_schedule(id);
+    }
+    fn schedule_internal(
+        &mut self,
+        delay: std::time::Duration,
+        interval: Option<std::time::Duration>,
+        trigger: F::Trigger,
+    ) -> ScheduleId {
+        self.schedule_count += 1;
+        let id = ScheduleId::owner(self.schedule_count);
+        self.supervise();
+        self.mark_busy();
+        self.background_task.schedule(id, delay, interval, trigger);
+        id
+    }
+
+    /// Request a snapshot of the state, which is taken once all previous triggers ran.
+    /// Fetch it using [`BackgroundTask::take_checkpoint`].
+    pub fn checkpoint(&mut self) {
+        self.supervise();
+        self.mark_busy();
+        self.background_task.checkpoint();
+    }
+    /// Fetch a snapshot requested by [`BackgroundTask::checkpoint`], if it is ready.
+    /// Fails if the state does not support checkpoints, see [`BackgroundFunction::serialize_state`].
+    #[must_use]
+    pub fn take_checkpoint(&mut self) -> Option<Result<Checkpoint, crate::CodecError>> {
+        let checkpoint = self.background_task.take_checkpoint()?;
+        let checkpoint = checkpoint
+            .map(Checkpoint::from_bytes)
+            .map_err(crate::CodecError::new);
+        if let Ok(checkpoint) = &checkpoint {
+            self.supervision.checkpoint_taken(checkpoint);
         }
+        Some(checkpoint)
     }
-    /// Trigger a new action.
-    pub fn trigger(&mut self, trigger: F::Trigger) {
-        self.task_ongoing = Ongoing::Ongoing;
-        self.background_task.trigger(trigger);
+    /// Fetch the next crash of the background task, if any.
+    /// A background task crashes if it panics, see [`Supervisor`].
+    #[must_use]
+    pub fn take_crash(&mut self) -> Option<Crash> {
+        self.supervise();
+        self.supervision.take_crash()
     }
 
-    /// Check if some action is ongoing
+    /// Check if some action is ongoing, i.e. the status is neither idle nor stopped.
+    /// A crashed background task is ongoing until it is restarted.
     #[must_use]
     pub fn is_ongoing(&mut self) -> bool {
-        while let Some(ongoing) = self.background_task.check_done() {
-            self.task_ongoing = ongoing;
+        matches!(
+            self.status(),
+            BackgroundStatus::Initializing | BackgroundStatus::Busy | BackgroundStatus::Failed(_)
+        )
+    }
+    /// The current status of the background task.
+    /// The native and the web backend go through the same transitions.
+    #[must_use]
+    pub fn status(&mut self) -> BackgroundStatus {
+        self.supervise();
+        self.fetch_events();
+        while let Some(activity) = self.background_task.check_activity() {
+            let sent = self.background_task.inputs_sent();
+            if let Some(status) = status::next_status(&self.status, activity, sent) {
+                self.set_status(status);
+            }
+        }
+        self.status.clone()
+    }
+    fn set_status(&mut self, status: BackgroundStatus) {
+        if self.status != status {
+            self.status = status;
+            if let Some(status_notifier) = &mut self.status_notifier {
+                status_notifier(&self.status);
+            }
+        }
+    }
+    /// An input was sent to the worker
+    fn mark_busy(&mut self) {
+        if self.status == BackgroundStatus::Idle {
+            self.set_status(BackgroundStatus::Busy);
         }
-        self.task_ongoing == Ongoing::Ongoing
     }
 
-    /// Fetch collected events
+    /// Fetch collected events, according to [`BackgroundFunction::EVENT_POLICY`]:
+    /// the merged event, the latest event or the oldest pending event.
     #[must_use]
     pub fn event(&mut self) -> Option<F::Event> {
-        match self.background_task.event() {
-            Some(mut event) => {
-                while let Some(other) = self.background_task.event() {
-                    F::event_merge(&mut event, other);
+        self.tagged_event().map(|(_, event)| event)
+    }
+    /// Fetch all pending events, oldest first.
+    /// Unless [`EventPolicy::KeepAll`] is used, there is at most one event.
+    pub fn events(&mut self) -> impl Iterator<Item = F::Event> + '_ {
+        self.tagged_events().map(|(_, event)| event)
+    }
+    /// Same as [`BackgroundTask::event`], together with the id of the trigger which sent the event.
+    /// Events sent by [`BackgroundFunction::initial_state`], [`StateTrait::progress`] or scheduled triggers have no id.
+    /// Merged events (see [`EventPolicy::Merge`]) have the id of the latest trigger involved.
+    #[must_use]
+    pub fn tagged_event(&mut self) -> Option<(Option<TriggerId>, F::Event)> {
+        self.fetch_events();
+        let (trigger, event) = self.events.pop()?;
+        Some((trigger, self.parity_event(event)))
+    }
+    /// Same as [`BackgroundTask::events`], together with the ids of the triggers which sent the events
+    pub fn tagged_events(&mut self) -> impl Iterator<Item = (Option<TriggerId>, F::Event)> + '_ {
+        self.fetch_events();
+        self.events
+            .drain()
+            .into_iter()
+            .map(|(trigger, event)| (trigger, self.parity_event(event)))
+    }
+    /// Events are sent to the shared queue directly, so their serde round trip is done once they are fetched
+    #[cfg(all(not(target_arch = "wasm32"), feature = "serde-parity"))]
+    fn parity_event(&self, event: F::Event) -> F::Event {
+        self.background_task.parity_event(event)
+    }
+    #[cfg(not(all(not(target_arch = "wasm32"), feature = "serde-parity")))]
+    fn parity_event(&self, event: F::Event) -> F::Event {
+        event
+    }
+    /// Detect crashes and restart if due
+    fn supervise(&mut self) {
+        if let Some(message) = self.background_task.crashed() {
+            self.fetch_events();
+            while self.background_task.check_activity().is_some() {}
+            // all triggers sent so far either ran or are lost, and so are unanswered queries
+            self.triggers_done = self.trigger_count;
+            self.queries.retain(|_, response| response.is_some());
+            self.supervision.crashed(message.clone(), &self.notifier);
+            self.set_status(if self.supervision.is_restarting() {
+                BackgroundStatus::Failed(message)
+            } else {
+                BackgroundStatus::Stopped
+            });
+            self.notifier.notify();
+        }
+        if let Some((worker, triggers)) = self.supervision.restart(&self.notifier, &self.events) {
+            self.background_task = worker;
+            #[cfg(all(not(target_arch = "wasm32"), feature = "serde-parity"))]
+            self.background_task
+                .set_parity_warn_size(self.parity_warn_size);
+            self.set_status(BackgroundStatus::Initializing);
+            for (id, trigger) in triggers {
+                self.background_task.trigger(id, trigger);
+            }
+        }
+    }
+    fn fetch_events(&mut self) {
+        while let Some(report) = self.background_task.report() {
+            self.handle_report(report);
+        }
+    }
+    fn handle_report(&mut self, report: Report<F>) {
+        match report {
+            Report::Event(trigger, event) => self.events.push(trigger, event),
+            Report::TriggerDone(id) => self.triggers_done = self.triggers_done.max(id.index()),
+            Report::QueryResponse(ticket, response) => {
+                if let Some(pending) = self.queries.get_mut(&ticket) {
+                    *pending = Some(response);
                 }
-                Some(event)
             }
-            None => None,
         }
     }
 }
@@ -256,3 +647,234 @@ fn test_background_task_std_complex() {
     let event: Option<f64> = dbg!(task.event());
     assert_eq!(event, Some(3.));
 }
+
+#[test]
+fn test_background_task_simple() {
+    struct State(f32);
+    impl StateTrait for State {
+        type Event = f64;
+        fn progress(&mut self) -> StateProgress<Self::Event> {
+            StateProgress::NothingOngoing
+        }
+    }
+    #[derive(Default)]
+    struct DummyFunction;
+    impl BackgroundFunction for DummyFunction {
+        type InitialState = ();
+        type State = State;
+        type Trigger = f32;
+        type Event = f64;
+
+        fn initial_state<EventSender: Fn(Self::Event)>(
+            self,
+            (): Self::InitialState,
+            event_sender: EventSender,
+        ) -> Self::State {
+            event_sender(1.);
+            State(42.)
+        }
+
+        fn trigger<EventSender: Fn(Self::Event)>(
+            state: &mut Self::State,
+            trigger: Self::Trigger,
+            event_sender: EventSender,
+        ) {
+            (0..trigger.abs().ceil() as usize).for_each(|x| event_sender(x as _));
+            state.0 += trigger;
+        }
+
+        fn event_merge(event: &mut Self::Event, other: Self::Event) {
+            *event = event.max(other)
+        }
+    }
+
+    let mut simulation = Simulation::<DummyFunction>::new(());
+    simulation.run_until_idle();
+    assert!(!simulation.is_ongoing());
+    assert_eq!(simulation.event(), Some(1.));
+    simulation.trigger(2.4);
+    simulation.run_until_idle();
+    assert_eq!(simulation.event(), Some(2.));
+    simulation.trigger(3.4);
+    simulation.trigger(2.4);
+    simulation.run_until_idle();
+    assert_eq!(simulation.event(), Some(3.));
+}
+
+#[test]
+fn test_background_task_complex() {
+    use std::time::Duration;
+    /// Jobs which take two seconds of the clock, which is only known once progress is checked
+    struct State(Vec<(Option<Duration>, f32)>);
+    const JOB_DURATION: Duration = Duration::from_secs(2);
+    impl StateTrait for State {
+        type Event = f64;
+        fn progress(&mut self) -> StateProgress<Self::Event> {
+            unreachable!("The worker calls progress_at")
+        }
+        fn progress_at(&mut self, now: Duration) -> StateProgress<Self::Event> {
+            for (started, _) in &mut self.0 {
+                started.get_or_insert(now);
+            }
+            if let Some(i) = self.0.iter().position(|(started, _)| {
+                started.is_some_and(|started| now >= started + JOB_DURATION)
+            }) {
+                return StateProgress::Event(self.0.remove(i).1 as f64);
+            }
+            match self.0.iter().filter_map(|(started, _)| *started).min() {
+                Some(started) => StateProgress::WaitFor(started + JOB_DURATION - now),
+                None => StateProgress::NothingOngoing,
+            }
+        }
+    }
+    #[derive(Default)]
+    struct DummyFunction;
+    impl BackgroundFunction for DummyFunction {
+        type InitialState = ();
+        type State = State;
+        type Trigger = f32;
+        type Event = f64;
+
+        fn initial_state<EventSender: Fn(Self::Event)>(
+            self,
+            (): Self::InitialState,
+            event_sender: EventSender,
+        ) -> Self::State {
+            event_sender(1.);
+            State(vec![(None, 42.)])
+        }
+
+        fn trigger<EventSender: Fn(Self::Event)>(
+            state: &mut Self::State,
+            trigger: Self::Trigger,
+            event_sender: EventSender,
+        ) {
+            (0..trigger.abs().ceil() as usize).for_each(|x| event_sender(x as _));
+            let n = state.0.len();
+            state.0.push((None, trigger + n as f32));
+        }
+
+        fn event_merge(event: &mut Self::Event, other: Self::Event) {
+            *event = event.max(other)
+        }
+    }
+    /// Advance the clock second by second, until the jobs are done
+    fn settle(simulation: &mut Simulation<DummyFunction>) -> Duration {
+        let start = simulation.now();
+        loop {
+            simulation.run_until_idle();
+            if !simulation.is_ongoing() {
+                return simulation.now() - start;
+            }
+            simulation.advance(Duration::from_secs(1));
+        }
+    }
+
+    let mut simulation = Simulation::<DummyFunction>::new(());
+    assert_eq!(settle(&mut simulation), JOB_DURATION);
+    assert_eq!(simulation.event(), Some(42.));
+    simulation.trigger(2.);
+    assert_eq!(settle(&mut simulation), JOB_DURATION);
+    assert_eq!(simulation.event(), Some(2.));
+    simulation.trigger(3.);
+    simulation.trigger(2.);
+    settle(&mut simulation);
+    assert_eq!(simulation.event(), Some(3.));
+}
+
+#[test]
+fn test_background_task_checkpoint() {
+    use serde::{Deserialize, Serialize};
+    #[derive(Serialize, Deserialize)]
+    struct State(u32);
+    impl StateTrait for State {
+        type Event = u32;
+        fn progress(&mut self) -> StateProgress<Self::Event> {
+            StateProgress::NothingOngoing
+        }
+    }
+    #[derive(Default)]
+    struct Counter;
+    impl BackgroundFunction for Counter {
+        type InitialState = u32;
+        type State = State;
+        type Trigger = u32;
+        type Event = u32;
+        const EVENT_POLICY: EventPolicy = EventPolicy::Latest;
+
+        fn initial_state<EventSender: Fn(Self::Event)>(
+            self,
+            initial_state: Self::InitialState,
+            _event_sender: EventSender,
+        ) -> Self::State {
+            State(initial_state)
+        }
+        fn trigger<EventSender: Fn(Self::Event)>(
+            state: &mut Self::State,
+            trigger: Self::Trigger,
+            event_sender: EventSender,
+        ) {
+            state.0 += trigger;
+            event_sender(state.0);
+        }
+        fn serialize_state<S: serde::Serializer>(
+            state: &Self::State,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            state.serialize(serializer)
+        }
+        fn deserialize_state<'de, D: serde::Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Self::State, D::Error> {
+            State::deserialize(deserializer)
+        }
+    }
+    fn take_checkpoint<F: BackgroundFunction>(
+        simulation: &mut Simulation<F>,
+    ) -> Result<Checkpoint, crate::CodecError> {
+        simulation.task().checkpoint();
+        // the snapshot is taken once the worker gets to it
+        assert!(simulation.task().take_checkpoint().is_none());
+        simulation.run_until_idle();
+        simulation.task().take_checkpoint().unwrap()
+    }
+
+    let mut simulation = Simulation::<Counter>::new(40);
+    simulation.run_until_idle();
+    simulation.trigger(1);
+    let checkpoint = take_checkpoint(&mut simulation).unwrap();
+    assert_eq!(simulation.event(), Some(41));
+    let mut simulation = Simulation::<Counter>::resume(checkpoint);
+    simulation.trigger(1);
+    simulation.run_until_idle();
+    assert!(!simulation.is_ongoing());
+    assert_eq!(simulation.event(), Some(42));
+
+    // states do not support checkpoints by default
+    #[derive(Default)]
+    struct Plain;
+    impl BackgroundFunction for Plain {
+        type InitialState = u32;
+        type State = State;
+        type Trigger = u32;
+        type Event = u32;
+        const EVENT_POLICY: EventPolicy = EventPolicy::Latest;
+
+        fn initial_state<EventSender: Fn(Self::Event)>(
+            self,
+            initial_state: Self::InitialState,
+            event_sender: EventSender,
+        ) -> Self::State {
+            Counter.initial_state(initial_state, event_sender)
+        }
+        fn trigger<EventSender: Fn(Self::Event)>(
+            state: &mut Self::State,
+            trigger: Self::Trigger,
+            event_sender: EventSender,
+        ) {
+            Counter::trigger(state, trigger, event_sender)
+        }
+    }
+    let mut simulation = Simulation::<Plain>::new(0);
+    assert!(take_checkpoint(&mut simulation).is_err());
+}
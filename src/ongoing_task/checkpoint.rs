@@ -0,0 +1,53 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::BackgroundFunction;
+use crate::Codec;
+
+/// A snapshot of the state of a background task, see [`super::BackgroundTask::checkpoint`].
+/// The snapshot is encoded using the codec of the task, so it can only be resumed using the same codec.
+/// It is serializable itself, e.g. to store it using eframe's persistence.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint(Vec<u8>);
+impl Checkpoint {
+    /// Create a checkpoint from bytes, which were obtained by [`Checkpoint::as_bytes`]
+    #[must_use]
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+    /// The encoded state
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+    pub(super) fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+/// Serializes the state using [`BackgroundFunction::serialize_state`]
+struct SerializeState<'a, F: BackgroundFunction>(&'a F::State);
+impl<F: BackgroundFunction> Serialize for SerializeState<'_, F> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        F::serialize_state(self.0, serializer)
+    }
+}
+/// Deserializes the state using [`BackgroundFunction::deserialize_state`]
+struct DeserializeState<F: BackgroundFunction>(F::State);
+impl<'de, F: BackgroundFunction> Deserialize<'de> for DeserializeState<F> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        F::deserialize_state(deserializer).map(Self)
+    }
+}
+
+/// Encode the state (worker side). The error is a message, so it can be sent back to the owner.
+pub(super) fn encode_state<F: BackgroundFunction, C: Codec>(
+    state: &F::State,
+) -> Result<Vec<u8>, String> {
+    C::encode(&SerializeState::<F>(state)).map_err(|e| e.to_string())
+}
+/// Decode the state (worker side)
+pub(super) fn decode_state<F: BackgroundFunction, C: Codec>(bytes: &[u8]) -> F::State {
+    C::decode::<DeserializeState<F>>(bytes)
+        .unwrap_or_else(|e| panic!("Failed to deserialize Checkpoint: {e}"))
+        .0
+}
@@ -0,0 +1,280 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use super::{BackgroundFunction, TriggerId};
+
+/// How events of a background task are collected until they are fetched, see [`BackgroundFunction::EVENT_POLICY`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EventPolicy {
+    /// All pending events are merged into a single one, using [`BackgroundFunction::event_merge`], which has to be implemented
+    #[default]
+    Merge,
+    /// Every event is kept, see [`super::BackgroundTask::events`]
+    KeepAll,
+    /// Only the latest event is kept
+    Latest,
+}
+
+/// What happens to an event, which does not fit into the backlog, see [`EventBacklog`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// The oldest pending event is dropped
+    #[default]
+    DropOldest,
+    /// The new event is dropped
+    DropNewest,
+}
+
+/// Limits the number of pending events of [`EventPolicy::KeepAll`].
+/// By default, the backlog is unbounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EventBacklog {
+    /// The maximal number of pending events
+    pub max_events: Option<usize>,
+    /// What happens if there are too many pending events
+    pub overflow: Overflow,
+}
+
+/// An event, together with the trigger which sent it
+pub(super) type Tagged<Event> = (Option<TriggerId>, Event);
+
+/// Events, which are sent by the worker but not yet fetched.
+/// The queue is shared by the worker and the owner, so the policy and the backlog apply as soon as an event is sent:
+/// the pending events are bounded even while the owner does not fetch them.
+pub(super) struct EventQueue<F: BackgroundFunction> {
+    shared: Arc<Mutex<Events<F>>>,
+}
+/// Tells which part of a background function sent an event, e.g. the session of a [`super::PooledFunction`]
+pub(super) type Partition<Event> = fn(&Event) -> u64;
+struct Events<F: BackgroundFunction> {
+    events: VecDeque<Tagged<F::Event>>,
+    backlog: EventBacklog,
+    /// If set, the backlog applies to each part on its own, see [`EventQueue::set_partition`]
+    partition: Option<Partition<F::Event>>,
+    /// Number of pending events of each part, all events are part 0 without a partition
+    counts: HashMap<u64, usize>,
+}
+impl<F: BackgroundFunction> Clone for EventQueue<F> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+impl<F: BackgroundFunction> Default for EventQueue<F> {
+    fn default() -> Self {
+        Self {
+            shared: Arc::new(Mutex::new(Events {
+                events: Default::default(),
+                backlog: Default::default(),
+                partition: None,
+                counts: Default::default(),
+            })),
+        }
+    }
+}
+impl<F: BackgroundFunction> Events<F> {
+    fn part(&self, event: &F::Event) -> u64 {
+        self.partition.map_or(0, |partition| partition(event))
+    }
+    fn push_back(&mut self, tagged: Tagged<F::Event>) {
+        *self.counts.entry(self.part(&tagged.1)).or_default() += 1;
+        self.events.push_back(tagged);
+    }
+    fn remove(&mut self, index: usize) -> Option<Tagged<F::Event>> {
+        let tagged = self.events.remove(index)?;
+        let part = self.part(&tagged.1);
+        if let Some(count) = self.counts.get_mut(&part) {
+            *count -= 1;
+            if *count == 0 {
+                self.counts.remove(&part);
+            }
+        }
+        Some(tagged)
+    }
+    /// Drop the events of each part, which exceed the backlog
+    fn trim(&mut self) {
+        let Some(max_events) = self.backlog.max_events else {
+            return;
+        };
+        let partition = self.partition;
+        let overflow = self.backlog.overflow;
+        let mut excess = self
+            .counts
+            .iter()
+            .map(|(part, count)| (*part, count.saturating_sub(max_events)))
+            .collect::<HashMap<_, _>>();
+        let mut kept = HashMap::<u64, usize>::new();
+        self.events.retain(|(_, event)| {
+            let part = partition.map_or(0, |partition| partition(event));
+            match overflow {
+                Overflow::DropOldest => match excess.get_mut(&part) {
+                    Some(excess) if *excess > 0 => {
+                        *excess -= 1;
+                        false
+                    }
+                    _ => true,
+                },
+                Overflow::DropNewest => {
+                    let kept = kept.entry(part).or_default();
+                    *kept += 1;
+                    *kept <= max_events
+                }
+            }
+        });
+        self.counts.retain(|_, count| {
+            *count = (*count).min(max_events);
+            *count > 0
+        });
+    }
+}
+impl<F: BackgroundFunction> EventQueue<F> {
+    /// A worker which panicked while merging events does not poison the queue
+    fn lock(&self) -> MutexGuard<'_, Events<F>> {
+        self.shared.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+    pub(super) fn set_backlog(&self, backlog: EventBacklog) {
+        let mut shared = self.lock();
+        shared.backlog = backlog;
+        shared.trim();
+    }
+    /// Apply the backlog of [`EventPolicy::KeepAll`] to each part of the events on its own,
+    /// so a part sending many events does not evict the events of other parts
+    pub(super) fn set_partition(&self, partition: Partition<F::Event>) {
+        let mut shared = self.lock();
+        shared.partition = Some(partition);
+        let events = std::mem::take(&mut shared.events);
+        shared.counts.clear();
+        events
+            .into_iter()
+            .for_each(|tagged| shared.push_back(tagged));
+        shared.trim();
+    }
+    /// A merged event is tagged with the later trigger
+    pub(super) fn push(&self, trigger: Option<TriggerId>, event: F::Event) {
+        let shared = &mut *self.lock();
+        match F::EVENT_POLICY {
+            EventPolicy::Merge => match shared.events.back_mut() {
+                Some((previous_trigger, previous)) => {
+                    F::event_merge(previous, event);
+                    *previous_trigger = trigger.or(*previous_trigger);
+                }
+                None => shared.push_back((trigger, event)),
+            },
+            EventPolicy::Latest => {
+                shared.events.clear();
+                shared.counts.clear();
+                shared.push_back((trigger, event));
+            }
+            EventPolicy::KeepAll => {
+                let part = shared.part(&event);
+                let pending = shared.counts.get(&part).copied().unwrap_or(0);
+                if shared.backlog.max_events == Some(0) {
+                    return;
+                }
+                if shared.backlog.max_events == Some(pending) {
+                    match shared.backlog.overflow {
+                        Overflow::DropOldest => {
+                            let oldest = shared
+                                .events
+                                .iter()
+                                .position(|(_, pending)| shared.part(pending) == part);
+                            if let Some(oldest) = oldest {
+                                shared.remove(oldest);
+                            }
+                        }
+                        Overflow::DropNewest => return,
+                    }
+                }
+                shared.push_back((trigger, event));
+            }
+        }
+    }
+    pub(super) fn len(&self) -> usize {
+        self.lock().events.len()
+    }
+    pub(super) fn pop(&self) -> Option<Tagged<F::Event>> {
+        self.lock().remove(0)
+    }
+    pub(super) fn drain(&self) -> VecDeque<Tagged<F::Event>> {
+        let mut shared = self.lock();
+        shared.counts.clear();
+        std::mem::take(&mut shared.events)
+    }
+}
+
+#[test]
+fn test_event_policy() {
+    use super::{Simulation, StateProgress, StateTrait};
+    struct State;
+    impl StateTrait for State {
+        type Event = String;
+        fn progress(&mut self) -> StateProgress<Self::Event> {
+            StateProgress::NothingOngoing
+        }
+    }
+    #[derive(Default)]
+    struct Log<const POLICY: u8>;
+    impl<const POLICY: u8> BackgroundFunction for Log<POLICY> {
+        type InitialState = ();
+        type State = State;
+        type Trigger = u8;
+        type Event = String;
+        const EVENT_POLICY: EventPolicy = match POLICY {
+            0 => EventPolicy::KeepAll,
+            _ => EventPolicy::Latest,
+        };
+
+        fn initial_state<EventSender: Fn(Self::Event)>(
+            self,
+            (): Self::InitialState,
+            _event_sender: EventSender,
+        ) -> Self::State {
+            State
+        }
+
+        fn trigger<EventSender: Fn(Self::Event)>(
+            _state: &mut Self::State,
+            trigger: Self::Trigger,
+            event_sender: EventSender,
+        ) {
+            (0..trigger).for_each(|i| event_sender(format!("line {i}")));
+        }
+    }
+
+    let mut simulation = Simulation::<Log<0>>::new(());
+    simulation.trigger(3);
+    simulation.run_until_idle();
+    assert_eq!(simulation.event().as_deref(), Some("line 0"));
+    assert_eq!(
+        simulation.events().collect::<Vec<_>>(),
+        ["line 1", "line 2"]
+    );
+
+    simulation.set_event_backlog(EventBacklog {
+        max_events: Some(2),
+        overflow: Overflow::DropOldest,
+    });
+    simulation.trigger(4);
+    simulation.run_until_idle();
+    assert_eq!(
+        simulation.events().collect::<Vec<_>>(),
+        ["line 2", "line 3"]
+    );
+    simulation.set_event_backlog(EventBacklog {
+        max_events: Some(2),
+        overflow: Overflow::DropNewest,
+    });
+    simulation.trigger(4);
+    simulation.run_until_idle();
+    assert_eq!(
+        simulation.events().collect::<Vec<_>>(),
+        ["line 0", "line 1"]
+    );
+
+    let mut simulation = Simulation::<Log<1>>::new(());
+    simulation.trigger(3);
+    simulation.run_until_idle();
+    assert_eq!(simulation.event().as_deref(), Some("line 2"));
+    assert_eq!(simulation.event(), None);
+}
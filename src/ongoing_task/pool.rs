@@ -0,0 +1,744 @@
+use std::{collections::HashMap, hash::Hash, marker::PhantomData};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::schedule::Schedule;
+use super::{
+    events::EventQueue, BackgroundFunction, BackgroundTask, Crash, EventBacklog, Overflow,
+    QueryState, QueryTicket, QueryableFunction, Restart, ScheduleId, StateProgress, StateTrait,
+    Supervisor, TriggerContext,
+};
+
+/// This runs many independent states of `F` on a single worker, each identified by a key.
+/// It is the background function of the workers of a [`BackgroundTaskPool`],
+/// so on wasm, the web worker has to run `WebWorkerBackground<PooledFunction<F, K>>`.
+///
+/// Sessions are run using [`BackgroundFunction::trigger_with_context`], so they can schedule triggers.
+/// The worker runs them as [`PoolInput::Scheduled`], they are dropped once the session is closed or replaced.
+pub struct PooledFunction<F, K> {
+    function: PhantomData<fn() -> (F, K)>,
+}
+impl<F, K> Default for PooledFunction<F, K> {
+    fn default() -> Self {
+        Self {
+            function: PhantomData,
+        }
+    }
+}
+impl<F, K> std::fmt::Debug for PooledFunction<F, K> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PooledFunction").finish()
+    }
+}
+
+/// The trigger of a [`PooledFunction`]
+#[derive(Debug, Serialize, Deserialize)]
+pub enum PoolInput<K, InitialState, Trigger> {
+    /// Start a session, replacing a previous session of the same key
+    Open {
+        /// The key of the session
+        key: K,
+        /// Distinguishes sessions of the same key, so late events of a closed session are dropped
+        session: u64,
+        /// The initial state of the session
+        initial_state: InitialState,
+    },
+    /// Trigger a session. Triggers of unknown keys are ignored.
+    Trigger {
+        /// The key of the session
+        key: K,
+        /// The trigger
+        trigger: Trigger,
+    },
+    /// End a session
+    Close {
+        /// The key of the session
+        key: K,
+    },
+    /// A trigger scheduled by a session (see [`TriggerContext`]), which is ignored if the session is not open anymore
+    Scheduled {
+        /// The key of the session
+        key: K,
+        /// The session which scheduled the trigger
+        session: u64,
+        /// The trigger
+        trigger: Trigger,
+    },
+}
+
+/// The state of a [`PooledFunction`], i.e. the open sessions
+pub struct PoolState<F: BackgroundFunction, K> {
+    sessions: Vec<OpenSession<F, K>>,
+    /// The session whose progress is checked first, so every session gets its turn
+    next: usize,
+}
+impl<F: BackgroundFunction, K> std::fmt::Debug for PoolState<F, K> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PoolState")
+            .field("sessions", &self.sessions.len())
+            .finish()
+    }
+}
+/// A session on its worker
+struct OpenSession<F: BackgroundFunction, K> {
+    key: K,
+    id: u64,
+    state: F::State,
+    /// The triggers the session scheduled to repeat, which are cancelled once it is closed
+    every: Vec<ScheduleId>,
+}
+
+impl<F: BackgroundFunction, K: Clone> StateTrait for PoolState<F, K> {
+    type Event = (K, u64, F::Event);
+    fn progress(&mut self) -> StateProgress<Self::Event> {
+        self.progress_sessions(|state| state.progress())
+    }
+    fn progress_at(&mut self, now: std::time::Duration) -> StateProgress<Self::Event> {
+        self.progress_sessions(|state| state.progress_at(now))
+    }
+}
+impl<F: BackgroundFunction, K: Clone> PoolState<F, K> {
+    fn progress_sessions(
+        &mut self,
+        mut progress_of: impl FnMut(&mut F::State) -> StateProgress<F::Event>,
+    ) -> StateProgress<(K, u64, F::Event)> {
+        let mut progress = StateProgress::NothingOngoing;
+        let count = self.sessions.len();
+        for i in 0..count {
+            let index = (self.next + i) % count;
+            let session = &mut self.sessions[index];
+            match (progress_of(&mut session.state), &progress) {
+                (StateProgress::Event(event), _) => {
+                    self.next = index + 1;
+                    return StateProgress::Event((session.key.clone(), session.id, event));
+                }
+                (StateProgress::NothingOngoing, _) | (_, StateProgress::Ongoing) => {}
+                (StateProgress::Ongoing, _) => progress = StateProgress::Ongoing,
+                (StateProgress::WaitFor(duration), StateProgress::WaitFor(previous)) => {
+                    progress = StateProgress::WaitFor(duration.min(*previous))
+                }
+                (StateProgress::WaitFor(duration), _) => {
+                    progress = StateProgress::WaitFor(duration)
+                }
+            }
+        }
+        progress
+    }
+}
+
+impl<F, K> BackgroundFunction for PooledFunction<F, K>
+where
+    F: BackgroundFunction,
+    K: Clone + Eq + Hash + Serialize + DeserializeOwned + Send + 'static,
+{
+    type InitialState = ();
+    type State = PoolState<F, K>;
+    type Trigger = PoolInput<K, F::InitialState, F::Trigger>;
+    type Event = (K, u64, F::Event);
+    const EVENT_POLICY: super::EventPolicy = super::EventPolicy::KeepAll;
+
+    fn initial_state<EventSender: Fn(Self::Event)>(
+        self,
+        (): Self::InitialState,
+        _event_sender: EventSender,
+    ) -> Self::State {
+        PoolState {
+            sessions: Vec::new(),
+            next: 0,
+        }
+    }
+    /// The worker runs [`PooledFunction::trigger_with_context`] instead
+    fn trigger<EventSender: Fn(Self::Event)>(
+        _state: &mut Self::State,
+        _trigger: Self::Trigger,
+        _event_sender: EventSender,
+    ) {
+        unreachable!("Sessions are run using trigger_with_context")
+    }
+    fn trigger_with_context(
+        state: &mut Self::State,
+        trigger: Self::Trigger,
+        context: &mut TriggerContext<'_, Self>,
+    ) {
+        let (event_sender, worker, now) = context.split();
+        match trigger {
+            PoolInput::Open {
+                key,
+                session,
+                initial_state,
+            } => {
+                state.close(&key, worker);
+                let tag = key.clone();
+                let initial_state = F::default().initial_state(initial_state, |event| {
+                    event_sender((tag.clone(), session, event))
+                });
+                state.sessions.push(OpenSession {
+                    key,
+                    id: session,
+                    state: initial_state,
+                    every: Vec::new(),
+                });
+            }
+            PoolInput::Trigger { key, trigger } => {
+                state.run(&key, None, trigger, event_sender, worker, now)
+            }
+            PoolInput::Scheduled {
+                key,
+                session,
+                trigger,
+            } => state.run(&key, Some(session), trigger, event_sender, worker, now),
+            PoolInput::Close { key } => state.close(&key, worker),
+        }
+    }
+}
+
+/// The input of the worker of a [`PooledFunction`]
+type WorkerTrigger<F, K> =
+    PoolInput<K, <F as BackgroundFunction>::InitialState, <F as BackgroundFunction>::Trigger>;
+
+impl<F: BackgroundFunction, K: Clone + Eq> PoolState<F, K> {
+    /// Trigger the open session of `key`, unless it is another session than the given one
+    fn run(
+        &mut self,
+        key: &K,
+        session: Option<u64>,
+        trigger: F::Trigger,
+        event_sender: &dyn Fn((K, u64, F::Event)),
+        worker: &mut dyn Schedule<WorkerTrigger<F, K>>,
+        now: std::time::Duration,
+    ) {
+        let Some(open) = self
+            .sessions
+            .iter_mut()
+            .find(|open| &open.key == key && session.is_none_or(|session| session == open.id))
+        else {
+            return;
+        };
+        let id = open.id;
+        let send_event = |event| event_sender((key.clone(), id, event));
+        let mut schedule = SessionSchedule::<F, K> {
+            worker,
+            key,
+            session: id,
+            every: &mut open.every,
+        };
+        let mut context = TriggerContext::new(&send_event, &mut schedule, now);
+        F::trigger_with_context(&mut open.state, trigger, &mut context);
+    }
+    /// Close the session of `key`, cancelling the triggers it scheduled to repeat
+    fn close(&mut self, key: &K, worker: &mut dyn Schedule<WorkerTrigger<F, K>>) {
+        for open in self.sessions.iter().filter(|open| &open.key == key) {
+            for id in &open.every {
+                worker.cancel(*id);
+            }
+        }
+        self.sessions.retain(|open| &open.key != key);
+    }
+}
+
+/// Schedules the triggers of a session on its worker, tagged with the session
+struct SessionSchedule<'a, F: BackgroundFunction, K> {
+    worker: &'a mut dyn Schedule<WorkerTrigger<F, K>>,
+    key: &'a K,
+    session: u64,
+    every: &'a mut Vec<ScheduleId>,
+}
+impl<F: BackgroundFunction, K: Clone> Schedule<F::Trigger> for SessionSchedule<'_, F, K> {
+    fn schedule(
+        &mut self,
+        now: std::time::Duration,
+        delay: std::time::Duration,
+        interval: Option<std::time::Duration>,
+        trigger: F::Trigger,
+    ) -> ScheduleId {
+        let trigger = PoolInput::Scheduled {
+            key: self.key.clone(),
+            session: self.session,
+            trigger,
+        };
+        let id = self.worker.schedule(now, delay, interval, trigger);
+        if interval.is_some() {
+            self.every.push(id);
+        }
+        id
+    }
+    fn cancel(&mut self, id: ScheduleId) {
+        self.every.retain(|every| *every != id);
+        self.worker.cancel(id);
+    }
+}
+
+/// A query is answered by the session of its key, `None` if the key is not open
+impl<F, K> QueryableFunction for PooledFunction<F, K>
+where
+    F: QueryableFunction,
+    K: Clone + Eq + Hash + Serialize + DeserializeOwned + Send + 'static,
+{
+    type Query = (K, F::Query);
+    type QueryResponse = Option<F::QueryResponse>;
+
+    fn query(state: &Self::State, (key, query): Self::Query) -> Self::QueryResponse {
+        state
+            .sessions
+            .iter()
+            .find(|open| open.key == key)
+            .map(|open| F::query(&open.state, query))
+    }
+}
+
+/// An open session of a [`BackgroundTaskPool`]
+struct Session<F: BackgroundFunction> {
+    /// Index of the worker the session is pinned to
+    worker: usize,
+    id: u64,
+    events: EventQueue<F>,
+}
+
+/// The number of events of each session a worker keeps until they are fetched, see [`BackgroundTaskPool::set_event_backlog`]
+const WORKER_BACKLOG: EventBacklog = EventBacklog {
+    max_events: Some(1024),
+    overflow: Overflow::DropOldest,
+};
+
+/// This hosts many independent states of `F` on a fixed number of workers (threads or web workers).
+/// Each state is opened using a key and pinned to one worker, the worker with the fewest sessions at that time.
+/// Events are collected per key, according to [`BackgroundFunction::EVENT_POLICY`].
+///
+/// If a worker crashes, all its sessions are lost: they are closed, and the crash is reported for each of them
+/// (see [`BackgroundTaskPool::take_crash`]). The worker is restarted right away, without sessions.
+/// Sessions can schedule triggers, see [`PooledFunction`].
+pub struct BackgroundTaskPool<F, K>
+where
+    F: BackgroundFunction,
+    K: Clone + Eq + Hash + Serialize + DeserializeOwned + Send + 'static,
+{
+    workers: Vec<BackgroundTask<PooledFunction<F, K>>>,
+    sessions: HashMap<K, Session<F>>,
+    session_count: u64,
+    /// Crashes which closed sessions, until they are taken or the key is opened again
+    crashes: HashMap<K, Crash>,
+    query_count: u64,
+    /// The worker answering a query, together with its ticket
+    queries: HashMap<QueryTicket, (usize, QueryTicket)>,
+}
+impl<F, K> std::fmt::Debug for BackgroundTaskPool<F, K>
+where
+    F: BackgroundFunction,
+    K: Clone + Eq + Hash + Serialize + DeserializeOwned + Send + 'static,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BackgroundTaskPool")
+            .field("workers", &self.workers.len())
+            .field("sessions", &self.sessions.len())
+            .finish()
+    }
+}
+impl<F, K> BackgroundTaskPool<F, K>
+where
+    F: BackgroundFunction,
+    K: Clone + Eq + Hash + Serialize + DeserializeOwned + Send + 'static,
+{
+    /// Create a new pool of `task_count` workers.
+    ///
+    /// Panics if `task_count` is zero.
+    #[must_use]
+    pub fn new(task_name: &str, task_count: usize) -> Self {
+        Self::with_codec::<crate::Bincode>(task_name, task_count)
+    }
+    /// Create a new pool of `task_count` workers, using the given codec, see [`BackgroundTask::with_codec`].
+    ///
+    /// Panics if `task_count` is zero.
+    #[must_use]
+    pub fn with_codec<C: crate::Codec>(task_name: &str, task_count: usize) -> Self {
+        assert!(task_count > 0, "A pool needs at least one worker");
+        let supervisor = Supervisor {
+            restart: Restart::Always {
+                backoff: std::time::Duration::ZERO,
+                max_backoff: std::time::Duration::ZERO,
+            },
+            ..Default::default()
+        };
+        let workers = (0..task_count)
+            .map(|_| {
+                let mut worker =
+                    BackgroundTask::supervised_with_codec::<C>(task_name, (), supervisor);
+                // a session sending many events does not evict the events of other sessions
+                worker.events.set_partition(|(_, session, _)| *session);
+                worker.set_event_backlog(WORKER_BACKLOG);
+                worker
+            })
+            .collect();
+        Self {
+            workers,
+            sessions: HashMap::new(),
+            session_count: 0,
+            crashes: HashMap::new(),
+            query_count: 0,
+            queries: HashMap::new(),
+        }
+    }
+    /// Set a callback which is invoked (from the background) whenever an event is emitted
+    /// or the status of a worker changes, see [`BackgroundTask::set_notifier`]
+    pub fn set_notifier(&mut self, notifier: impl Fn() + Send + Sync + 'static) {
+        let notifier = std::sync::Arc::new(notifier);
+        for worker in &mut self.workers {
+            let notifier = notifier.clone();
+            worker.set_notifier(move || notifier());
+        }
+    }
+    /// Limit the number of pending events of each session, on its worker until they are fetched, and in the pool.
+    /// By default, a worker keeps at most 1024 events of each session, dropping the oldest ones,
+    /// and the backlog of a session in the pool is unbounded (see [`BackgroundTask::set_event_backlog`]).
+    ///
+    /// Events dropped by a worker are lost for their session, regardless of [`BackgroundFunction::EVENT_POLICY`].
+    pub fn set_event_backlog(&mut self, backlog: EventBacklog) {
+        for worker in &mut self.workers {
+            worker.set_event_backlog(backlog);
+        }
+        for session in self.sessions.values() {
+            session.events.set_backlog(backlog);
+        }
+    }
+    /// Open a session, calling [`BackgroundFunction::initial_state`] on its worker.
+    /// If the key is open already, its session is replaced.
+    pub fn open(&mut self, key: K, initial_state: F::InitialState) {
+        self.close(&key);
+        self.crashes.remove(&key);
+        let mut load = vec![0; self.workers.len()];
+        for session in self.sessions.values() {
+            load[session.worker] += 1;
+        }
+        let worker = (0..load.len())
+            .min_by_key(|worker| load[*worker])
+            .expect("A pool has at least one worker");
+        self.session_count += 1;
+        self.workers[worker].trigger(PoolInput::Open {
+            key: key.clone(),
+            session: self.session_count,
+            initial_state,
+        });
+        self.sessions.insert(
+            key,
+            Session {
+                worker,
+                id: self.session_count,
+                events: Default::default(),
+            },
+        );
+    }
+    /// Close a session, dropping its state and its pending events. Unknown keys are ignored.
+    pub fn close(&mut self, key: &K) {
+        self.supervise();
+        if let Some(session) = self.sessions.remove(key) {
+            self.workers[session.worker].trigger(PoolInput::Close { key: key.clone() });
+        }
+    }
+    /// Check if a session is open. A session is closed if its worker crashed, see [`BackgroundTaskPool::take_crash`].
+    #[must_use]
+    pub fn is_open(&mut self, key: &K) -> bool {
+        self.supervise();
+        self.sessions.contains_key(key)
+    }
+    /// Fetch the crash which closed the session of `key`, if any
+    #[must_use]
+    pub fn take_crash(&mut self, key: &K) -> Option<Crash> {
+        self.supervise();
+        self.crashes.remove(key)
+    }
+    /// Trigger a session. Triggers of keys which are not open are ignored.
+    pub fn trigger(&mut self, key: &K, trigger: F::Trigger) {
+        self.supervise();
+        if let Some(session) = self.sessions.get(key) {
+            self.workers[session.worker].trigger(PoolInput::Trigger {
+                key: key.clone(),
+                trigger,
+            });
+        }
+    }
+    /// Check if some action is ongoing on any worker
+    #[must_use]
+    pub fn is_ongoing(&mut self) -> bool {
+        self.workers.iter_mut().any(|worker| worker.is_ongoing())
+    }
+    /// Fetch collected events of a session, see [`BackgroundTask::event`]
+    #[must_use]
+    pub fn event(&mut self, key: &K) -> Option<F::Event> {
+        self.fetch_events();
+        let (_, event) = self.sessions.get_mut(key)?.events.pop()?;
+        Some(event)
+    }
+    /// Fetch all pending events of a session, oldest first, see [`BackgroundTask::events`]
+    pub fn events(&mut self, key: &K) -> impl Iterator<Item = F::Event> + '_ {
+        self.fetch_events();
+        self.sessions
+            .get_mut(key)
+            .into_iter()
+            .flat_map(|session| session.events.drain().into_iter().map(|(_, event)| event))
+    }
+    /// Detect crashed workers: their sessions are lost, so they are closed
+    fn supervise(&mut self) {
+        for index in 0..self.workers.len() {
+            while let Some(crash) = self.workers[index].take_crash() {
+                let lost = self
+                    .sessions
+                    .iter()
+                    .filter(|(_, session)| session.worker == index)
+                    .map(|(key, _)| key.clone())
+                    .collect::<Vec<_>>();
+                for key in lost {
+                    self.sessions.remove(&key);
+                    self.crashes.insert(key, crash.clone());
+                }
+            }
+        }
+    }
+    fn fetch_events(&mut self) {
+        self.supervise();
+        for worker in &mut self.workers {
+            for (key, id, event) in worker.events() {
+                match self.sessions.get_mut(&key) {
+                    Some(session) if session.id == id => session.events.push(None, event),
+                    // the session was closed
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+impl<F, K> BackgroundTaskPool<F, K>
+where
+    F: QueryableFunction,
+    K: Clone + Eq + Hash + Serialize + DeserializeOwned + Send + 'static,
+{
+    /// Ask something about the state of a session, see [`BackgroundTask::query`].
+    /// Queries of keys which are not open are lost.
+    #[must_use]
+    pub fn query(&mut self, key: &K, query: F::Query) -> QueryTicket {
+        self.query_count += 1;
+        let ticket = QueryTicket::nth(self.query_count);
+        self.supervise();
+        if let Some(session) = self.sessions.get(key) {
+            let pending = self.workers[session.worker].query((key.clone(), query));
+            self.queries
+                .insert(ticket.internal(), (session.worker, pending));
+        }
+        ticket
+    }
+    /// Check if a query is answered, see [`BackgroundTask::check_query`].
+    /// A query is lost if its session was closed before answering it.
+    #[must_use]
+    pub fn check_query(&mut self, ticket: QueryTicket) -> QueryState<F::QueryResponse> {
+        let Some((worker, pending)) = self.queries.remove(&ticket) else {
+            return QueryState::Lost;
+        };
+        match self.workers[worker].check_query(pending) {
+            QueryState::Pending(pending) => {
+                self.queries.insert(ticket.internal(), (worker, pending));
+                QueryState::Pending(ticket)
+            }
+            QueryState::Done(Some(response)) => QueryState::Done(response),
+            QueryState::Done(None) | QueryState::Lost => QueryState::Lost,
+        }
+    }
+    /// Block until a query is answered. Returns `None` if the query is lost, see [`BackgroundTaskPool::check_query`].
+    #[cfg(not(target_arch = "wasm32"))]
+    #[must_use]
+    pub fn wait_for_query(&mut self, ticket: QueryTicket) -> Option<F::QueryResponse> {
+        let (worker, pending) = self.queries.remove(&ticket)?;
+        self.workers[worker].wait_for_query(pending).flatten()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn test_background_task_pool() {
+    use std::collections::HashSet;
+    struct State(u32);
+    impl StateTrait for State {
+        type Event = (u32, String);
+        fn progress(&mut self) -> StateProgress<Self::Event> {
+            StateProgress::NothingOngoing
+        }
+    }
+    /// Sums up the triggers, each event is the sum and the thread computing it. Zero is not a valid trigger.
+    #[derive(Default)]
+    struct Sum;
+    impl BackgroundFunction for Sum {
+        type InitialState = u32;
+        type State = State;
+        type Trigger = u32;
+        type Event = (u32, String);
+        const EVENT_POLICY: super::EventPolicy = super::EventPolicy::Latest;
+
+        fn initial_state<EventSender: Fn(Self::Event)>(
+            self,
+            initial_state: Self::InitialState,
+            _event_sender: EventSender,
+        ) -> Self::State {
+            State(initial_state)
+        }
+        fn trigger<EventSender: Fn(Self::Event)>(
+            state: &mut Self::State,
+            trigger: Self::Trigger,
+            event_sender: EventSender,
+        ) {
+            assert!(trigger != 0, "Invalid trigger");
+            state.0 += trigger;
+            let thread = format!("{:?}", std::thread::current().id());
+            event_sender((state.0, thread));
+        }
+    }
+    impl QueryableFunction for Sum {
+        type Query = ();
+        type QueryResponse = u32;
+
+        fn query(state: &Self::State, (): Self::Query) -> Self::QueryResponse {
+            state.0
+        }
+    }
+    /// The sum of a session, once its previous triggers ran
+    fn sum(pool: &mut BackgroundTaskPool<Sum, String>, key: &String) -> Option<u32> {
+        let ticket = pool.query(key, ());
+        pool.wait_for_query(ticket)
+    }
+
+    let mut pool = BackgroundTaskPool::<Sum, String>::new("pool_thread", 2);
+    let keys = (0..5).map(|i| format!("doc{i}")).collect::<Vec<_>>();
+    for (i, key) in keys.iter().enumerate() {
+        pool.open(key.clone(), 100 * i as u32);
+    }
+    for key in &keys {
+        pool.trigger(key, 1);
+        pool.trigger(key, 2);
+    }
+    let mut threads = HashSet::new();
+    for (i, key) in keys.iter().enumerate() {
+        assert_eq!(sum(&mut pool, key), Some(100 * i as u32 + 3));
+        // only the latest event is kept
+        let events = pool.events(key).collect::<Vec<_>>();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].0, 100 * i as u32 + 3);
+        threads.insert(events[0].1.clone());
+    }
+    assert_eq!(threads.len(), 2);
+
+    // a closed session is gone, a reopened session starts from scratch
+    pool.close(&keys[0]);
+    assert!(!pool.is_open(&keys[0]));
+    assert_eq!(sum(&mut pool, &keys[0]), None);
+    pool.trigger(&keys[0], 1);
+    pool.open(keys[0].clone(), 10);
+    pool.trigger(&keys[0], 1);
+    pool.trigger(&keys[1], 1);
+    assert_eq!(sum(&mut pool, &keys[0]), Some(11));
+    assert_eq!(sum(&mut pool, &keys[1]), Some(104));
+    assert_eq!(pool.event(&keys[0]).map(|(sum, _)| sum), Some(11));
+    assert_eq!(pool.event(&keys[1]).map(|(sum, _)| sum), Some(104));
+    assert_eq!(pool.event(&keys[2]), None);
+
+    // a crash closes all sessions of its worker, i.e. the sessions opened on the first worker
+    pool.trigger(&keys[2], 0);
+    assert_eq!(sum(&mut pool, &keys[2]), None);
+    for key in [&keys[0], &keys[2], &keys[4]] {
+        assert!(!pool.is_open(key));
+        assert_eq!(
+            pool.take_crash(key),
+            Some(Crash {
+                message: "Invalid trigger".into(),
+                restarting: true
+            })
+        );
+    }
+    for key in [&keys[1], &keys[3]] {
+        assert!(pool.is_open(key));
+        assert_eq!(pool.take_crash(key), None);
+    }
+    // the worker is restarted
+    pool.open(keys[2].clone(), 5);
+    pool.trigger(&keys[2], 1);
+    assert_eq!(sum(&mut pool, &keys[2]), Some(6));
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn test_background_task_pool_sessions() {
+    struct Idle;
+    impl StateTrait for Idle {
+        type Event = i32;
+        fn progress(&mut self) -> StateProgress<Self::Event> {
+            StateProgress::NothingOngoing
+        }
+    }
+    /// A positive trigger counts down to one, scheduling each step. A negative trigger sends that many events at once.
+    #[derive(Default)]
+    struct Countdown;
+    impl BackgroundFunction for Countdown {
+        type InitialState = ();
+        type State = Idle;
+        type Trigger = i32;
+        type Event = i32;
+        const EVENT_POLICY: super::EventPolicy = super::EventPolicy::KeepAll;
+
+        fn initial_state<EventSender: Fn(Self::Event)>(
+            self,
+            (): Self::InitialState,
+            _event_sender: EventSender,
+        ) -> Self::State {
+            Idle
+        }
+        fn trigger<EventSender: Fn(Self::Event)>(
+            _state: &mut Self::State,
+            _trigger: Self::Trigger,
+            _event_sender: EventSender,
+        ) {
+            unreachable!("Countdown needs a trigger context")
+        }
+        fn trigger_with_context(
+            _state: &mut Self::State,
+            trigger: Self::Trigger,
+            context: &mut TriggerContext<'_, Self>,
+        ) {
+            if trigger < 0 {
+                for event in 1..=-trigger {
+                    context.send(event);
+                }
+            } else if trigger > 0 {
+                context.send(trigger);
+                if trigger > 1 {
+                    context.schedule(std::time::Duration::from_millis(1), trigger - 1);
+                }
+            }
+        }
+    }
+    impl QueryableFunction for Countdown {
+        type Query = ();
+        type QueryResponse = ();
+
+        fn query(_state: &Self::State, (): Self::Query) -> Self::QueryResponse {}
+    }
+
+    let (chatty, quiet) = (1, 2);
+    let mut pool = BackgroundTaskPool::<Countdown, u32>::new("pool_sessions", 1);
+    pool.set_event_backlog(EventBacklog {
+        max_events: Some(3),
+        overflow: Overflow::DropOldest,
+    });
+    pool.open(chatty, ());
+    pool.open(quiet, ());
+    pool.trigger(&chatty, -10);
+    pool.trigger(&quiet, 3);
+    let ticket = pool.query(&chatty, ());
+    assert_eq!(pool.wait_for_query(ticket), Some(()));
+
+    // the countdown runs on the worker, and the chatty session does not evict its events
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    let mut events = Vec::new();
+    while events.len() < 3 {
+        assert!(std::time::Instant::now() < deadline, "Timeout");
+        events.extend(pool.events(&quiet));
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+    assert_eq!(events, [3, 2, 1]);
+    assert_eq!(pool.events(&chatty).collect::<Vec<_>>(), [8, 9, 10]);
+}
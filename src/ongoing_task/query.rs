@@ -0,0 +1,281 @@
+use super::{BackgroundFunction, BackgroundTask, QueryableFunction};
+use crate::codec::Coder;
+
+/// A query, which the worker answers without knowing the query type, see [`QueryableFunction`]
+pub(super) type Question<F> = Box<dyn FnOnce(&<F as BackgroundFunction>::State) -> Answer + Send>;
+/// The response to a [`Question`]: on native the response itself,
+/// on the web the response encoded by the worker (see [`super::WebWorkerBackground`])
+pub(super) type Answer = Box<dyn std::any::Any + Send>;
+
+/// The codec of the task, which encodes queries and responses on the web (and for the serde round trip).
+/// The query types are only known for a [`QueryableFunction`], so the codec chosen at construction is kept as this trait.
+/// Threads pass queries as they are, so without `serde-parity` only the web uses it.
+#[cfg_attr(
+    all(not(target_arch = "wasm32"), not(feature = "serde-parity")),
+    allow(dead_code)
+)]
+pub(super) trait QueryCodec<F: BackgroundFunction>: Send + Sync {
+    fn query(&self) -> Coder<F::Query>
+    where
+        F: QueryableFunction;
+    fn response(&self) -> Coder<F::QueryResponse>
+    where
+        F: QueryableFunction;
+}
+/// The [`QueryCodec`] using the codec `C`
+pub(super) struct QueryCoders<C>(std::marker::PhantomData<fn() -> C>);
+impl<C> Default for QueryCoders<C> {
+    fn default() -> Self {
+        Self(Default::default())
+    }
+}
+impl<F: BackgroundFunction, C: crate::Codec> QueryCodec<F> for QueryCoders<C> {
+    fn query(&self) -> Coder<F::Query>
+    where
+        F: QueryableFunction,
+    {
+        Coder::new::<C>()
+    }
+    fn response(&self) -> Coder<F::QueryResponse>
+    where
+        F: QueryableFunction,
+    {
+        Coder::new::<C>()
+    }
+}
+
+/// Owner side: pack a query, so any worker can answer it.
+/// On the web, the response is encoded like the one of a web worker, see [`unpack`].
+pub(super) fn ask<F: QueryableFunction>(
+    query: F::Query,
+    _codec: &dyn QueryCodec<F>,
+) -> Question<F> {
+    #[cfg(not(target_arch = "wasm32"))]
+    return Box::new(move |state| Box::new(F::query(state, query)));
+    #[cfg(target_arch = "wasm32")]
+    {
+        let coder = _codec.response();
+        Box::new(move |state| {
+            let response = coder.try_encode(&F::query(state, query));
+            Box::new(response.map_err(|e| e.to_string()))
+        })
+    }
+}
+/// Owner side: the response to a query, or `None` if the worker could not answer it
+fn unpack<F: QueryableFunction>(
+    answer: Answer,
+    _codec: &dyn QueryCodec<F>,
+) -> Option<F::QueryResponse> {
+    #[cfg(not(target_arch = "wasm32"))]
+    return answer.downcast().ok().map(|response| *response);
+    #[cfg(target_arch = "wasm32")]
+    return answer
+        .downcast::<Result<Vec<u8>, String>>()
+        .ok()?
+        .ok()
+        .and_then(|bytes| _codec.response().try_decode(&bytes).ok());
+}
+
+/// Each query yields a ticket, which is used to fetch the response, see [`super::BackgroundTask::query`]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize)]
+pub struct QueryTicket(u64);
+impl QueryTicket {
+    /// The ticket of the i-th query
+    pub(super) fn nth(i: u64) -> Self {
+        Self(i)
+    }
+    /// A copy for internal bookkeeping, the ticket itself is handed to the owner
+    pub(super) fn internal(&self) -> Self {
+        Self(self.0)
+    }
+}
+
+/// This is the current state of a query, see [`super::BackgroundTask::check_query`]
+#[derive(Debug)]
+pub enum QueryState<Response> {
+    /// The query is not answered yet. Use this ticket to check later.
+    Pending(QueryTicket),
+    /// The response to the query
+    Done(Response),
+    /// The background task crashed (or was crashed already) before answering the query,
+    /// or the web worker does not answer queries (see [`super::WebWorkerBackground`])
+    Lost,
+}
+
+impl<F: QueryableFunction> BackgroundTask<F> {
+    /// Ask something about the state. The query is answered once all previous triggers ran,
+    /// fetch the response using [`BackgroundTask::check_query`].
+    /// Queries sent to a crashed background task are lost, even if it is restarted.
+    #[must_use]
+    pub fn query(&mut self, query: F::Query) -> QueryTicket {
+        self.query_count += 1;
+        let ticket = QueryTicket::nth(self.query_count);
+        self.supervise();
+        if !self.supervision.is_restarting() && !self.supervision.is_stopped() {
+            self.queries.insert(ticket.internal(), None);
+            self.mark_busy();
+            let codec = self.supervision.query_codec();
+            self.background_task.query(ticket.internal(), query, codec);
+        }
+        ticket
+    }
+    /// Check if a query is answered. If so, the response is taken.
+    #[must_use]
+    pub fn check_query(&mut self, ticket: QueryTicket) -> QueryState<F::QueryResponse> {
+        self.supervise();
+        self.fetch_events();
+        match self.queries.remove(&ticket) {
+            Some(Some(answer)) => match unpack::<F>(answer, self.supervision.query_codec()) {
+                Some(response) => QueryState::Done(response),
+                None => QueryState::Lost,
+            },
+            Some(None) => {
+                self.queries.insert(ticket.internal(), None);
+                QueryState::Pending(ticket)
+            }
+            None => QueryState::Lost,
+        }
+    }
+    /// Block until a query is answered. Returns `None` if the query is lost, see [`QueryState::Lost`].
+    #[cfg(not(target_arch = "wasm32"))]
+    #[must_use]
+    pub fn wait_for_query(&mut self, mut ticket: QueryTicket) -> Option<F::QueryResponse> {
+        loop {
+            match self.check_query(ticket) {
+                QueryState::Pending(pending) => ticket = pending,
+                QueryState::Done(response) => return Some(response),
+                QueryState::Lost => return None,
+            }
+            if let Some(report) = self.background_task.wait_report() {
+                self.handle_report(report);
+            }
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn test_query() {
+    use super::{StateProgress, StateTrait};
+    use std::collections::HashMap;
+    struct State(HashMap<String, u32>);
+    impl StateTrait for State {
+        type Event = ();
+        fn progress(&mut self) -> StateProgress<Self::Event> {
+            StateProgress::NothingOngoing
+        }
+    }
+    /// Indexes entries, slowly. Zero is not a valid entry.
+    #[derive(Default)]
+    struct Index;
+    impl BackgroundFunction for Index {
+        type InitialState = ();
+        type State = State;
+        type Trigger = (String, u32);
+        type Event = ();
+        const EVENT_POLICY: crate::EventPolicy = crate::EventPolicy::Latest;
+
+        fn initial_state<EventSender: Fn(Self::Event)>(
+            self,
+            (): Self::InitialState,
+            _event_sender: EventSender,
+        ) -> Self::State {
+            State(Default::default())
+        }
+        fn trigger<EventSender: Fn(Self::Event)>(
+            state: &mut Self::State,
+            (key, value): Self::Trigger,
+            _event_sender: EventSender,
+        ) {
+            assert!(value != 0, "Invalid entry");
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            state.0.insert(key, value);
+        }
+    }
+    impl QueryableFunction for Index {
+        type Query = String;
+        type QueryResponse = Option<u32>;
+
+        fn query(state: &Self::State, key: Self::Query) -> Self::QueryResponse {
+            state.0.get(&key).copied()
+        }
+    }
+
+    let mut task = BackgroundTask::<Index>::new("index_thread", ());
+    task.trigger(("a".into(), 1));
+    task.trigger(("b".into(), 2));
+    // the query is answered after the triggers ran
+    let ticket = task.query("b".into());
+    assert_eq!(task.wait_for_query(ticket), Some(Some(2)));
+
+    let mut ticket = task.query("c".into());
+    let response = loop {
+        match task.check_query(ticket) {
+            QueryState::Pending(pending) => ticket = pending,
+            QueryState::Done(response) => break response,
+            QueryState::Lost => panic!("Query lost"),
+        }
+    };
+    assert_eq!(response, None);
+
+    // queries are lost if the task crashes
+    task.trigger(("d".into(), 0));
+    let ticket = task.query("a".into());
+    assert_eq!(task.wait_for_query(ticket), None);
+    let ticket = task.query("a".into());
+    assert!(matches!(task.check_query(ticket), QueryState::Lost));
+}
+
+/// Queries are checked against the codec of the task: bincode cannot decode a JSON value, JSON can
+#[cfg(all(
+    not(target_arch = "wasm32"),
+    feature = "serde-parity",
+    feature = "json"
+))]
+#[test]
+fn test_query_codec() {
+    use super::{StateProgress, StateTrait};
+    struct State;
+    impl StateTrait for State {
+        type Event = ();
+        fn progress(&mut self) -> StateProgress<Self::Event> {
+            StateProgress::NothingOngoing
+        }
+    }
+    #[derive(Default)]
+    struct Echo;
+    impl BackgroundFunction for Echo {
+        type InitialState = ();
+        type State = State;
+        type Trigger = ();
+        type Event = ();
+        const EVENT_POLICY: crate::EventPolicy = crate::EventPolicy::Latest;
+
+        fn initial_state<EventSender: Fn(Self::Event)>(
+            self,
+            (): Self::InitialState,
+            _event_sender: EventSender,
+        ) -> Self::State {
+            State
+        }
+        fn trigger<EventSender: Fn(Self::Event)>(
+            _state: &mut Self::State,
+            (): Self::Trigger,
+            _event_sender: EventSender,
+        ) {
+        }
+    }
+    impl QueryableFunction for Echo {
+        type Query = serde_json::Value;
+        type QueryResponse = serde_json::Value;
+
+        fn query(_state: &Self::State, query: Self::Query) -> Self::QueryResponse {
+            query
+        }
+    }
+
+    let mut task = BackgroundTask::<Echo>::with_codec::<crate::Json>("echo_thread", ());
+    let query = serde_json::json!({"key": [1, 2]});
+    let ticket = task.query(query.clone());
+    assert_eq!(task.wait_for_query(ticket), Some(query));
+}
@@ -0,0 +1,285 @@
+use std::time::Duration;
+
+use super::BackgroundFunction;
+use crate::codec::Coder;
+
+/// Identifies a scheduled trigger, see [`super::BackgroundTask::schedule`] and [`TriggerContext::schedule`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct ScheduleId(u64);
+impl ScheduleId {
+    /// Ids scheduled by the owner are even, ids scheduled by the worker are odd
+    pub(super) fn owner(index: u64) -> Self {
+        Self(2 * index)
+    }
+    fn worker(index: u64) -> Self {
+        Self(2 * index + 1)
+    }
+}
+
+/// This is passed to [`BackgroundFunction::trigger_with_context`].
+/// It sends events to the owner and schedules triggers for the background task itself.
+pub struct TriggerContext<'a, F: BackgroundFunction> {
+    event_sender: &'a dyn Fn(F::Event),
+    scheduler: &'a mut dyn Schedule<F::Trigger>,
+    now: Duration,
+}
+impl<F: BackgroundFunction> std::fmt::Debug for TriggerContext<'_, F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TriggerContext")
+            .field("now", &self.now)
+            .finish_non_exhaustive()
+    }
+}
+impl<'a, F: BackgroundFunction> TriggerContext<'a, F> {
+    pub(super) fn new(
+        event_sender: &'a dyn Fn(F::Event),
+        scheduler: &'a mut dyn Schedule<F::Trigger>,
+        now: Duration,
+    ) -> Self {
+        Self {
+            event_sender,
+            scheduler,
+            now,
+        }
+    }
+    /// The time since the worker started. In a [`super::Simulation`], this is the virtual clock.
+    #[must_use]
+    pub fn now(&self) -> Duration {
+        self.now
+    }
+    /// Send an event to the owner
+    pub fn send(&self, event: F::Event) {
+        (self.event_sender)(event)
+    }
+    /// Run `trigger` once, after `delay`
+    pub fn schedule(&mut self, delay: Duration, trigger: F::Trigger) -> ScheduleId {
+        self.scheduler.schedule(self.now, delay, None, trigger)
+    }
+    /// Run `trigger` every `interval`, starting after `interval`. Missed runs are skipped.
+    ///
+    /// Panics if `interval` is zero.
+    pub fn schedule_every(&mut self, interval: Duration, trigger: F::Trigger) -> ScheduleId {
+        self.scheduler
+            .schedule(self.now, interval, Some(interval), trigger)
+    }
+    /// Cancel a scheduled trigger. Unknown (or finished) schedules are ignored.
+    pub fn cancel(&mut self, id: ScheduleId) {
+        self.scheduler.cancel(id)
+    }
+    /// The parts of this context, to build the context of a part of the background function,
+    /// e.g. of a session of a [`super::PooledFunction`]
+    pub(super) fn split(&mut self) -> ContextParts<'a, '_, F> {
+        (self.event_sender, &mut *self.scheduler, self.now)
+    }
+}
+
+/// The event sender, the scheduler and the current time of a [`TriggerContext`]
+pub(super) type ContextParts<'a, 'b, F> = (
+    &'a dyn Fn(<F as BackgroundFunction>::Event),
+    &'b mut dyn Schedule<<F as BackgroundFunction>::Trigger>,
+    Duration,
+);
+
+/// Schedules the triggers of a [`TriggerContext`]
+pub(super) trait Schedule<Trigger> {
+    /// Schedule a trigger, generating a new id
+    fn schedule(
+        &mut self,
+        now: Duration,
+        delay: Duration,
+        interval: Option<Duration>,
+        trigger: Trigger,
+    ) -> ScheduleId;
+    fn cancel(&mut self, id: ScheduleId);
+}
+impl<F: BackgroundFunction> Schedule<F::Trigger> for Scheduler<F> {
+    fn schedule(
+        &mut self,
+        now: Duration,
+        delay: Duration,
+        interval: Option<Duration>,
+        trigger: F::Trigger,
+    ) -> ScheduleId {
+        self.insert(None, now, delay, interval, trigger)
+    }
+    fn cancel(&mut self, id: ScheduleId) {
+        Scheduler::cancel(self, id)
+    }
+}
+
+enum Payload<Trigger> {
+    Once(Trigger),
+    /// The trigger is encoded, so it can be decoded for each run
+    Every(Duration, Vec<u8>),
+}
+struct Entry<Trigger> {
+    id: ScheduleId,
+    /// Time of the next run, relative to the time base of the scheduler
+    at: Duration,
+    payload: Payload<Trigger>,
+}
+
+/// Scheduled triggers of a worker. The time is passed in by the caller, as duration since some fixed start.
+pub(super) struct Scheduler<F: BackgroundFunction> {
+    entries: Vec<Entry<F::Trigger>>,
+    next_index: u64,
+    coder: Coder<F::Trigger>,
+}
+impl<F: BackgroundFunction> Scheduler<F> {
+    pub(super) fn new<C: crate::Codec>() -> Self {
+        Self {
+            entries: Default::default(),
+            next_index: 0,
+            coder: Coder::new::<C>(),
+        }
+    }
+    /// Schedule a trigger. If no id is given, a new one is generated.
+    pub(super) fn insert(
+        &mut self,
+        id: Option<ScheduleId>,
+        now: Duration,
+        delay: Duration,
+        interval: Option<Duration>,
+        trigger: F::Trigger,
+    ) -> ScheduleId {
+        let id = id.unwrap_or_else(|| {
+            self.next_index += 1;
+            ScheduleId::worker(self.next_index)
+        });
+        let payload = match interval {
+            Some(interval) => {
+                assert!(!interval.is_zero(), "Interval must not be zero");
+                Payload::Every(interval, self.coder.encode(&trigger, "Trigger"))
+            }
+            None => Payload::Once(trigger),
+        };
+        self.entries.push(Entry {
+            id,
+            at: now + delay,
+            payload,
+        });
+        id
+    }
+    pub(super) fn cancel(&mut self, id: ScheduleId) {
+        self.entries.retain(|entry| entry.id != id);
+    }
+    /// The time of the next run, if anything is scheduled
+    pub(super) fn next_deadline(&self) -> Option<Duration> {
+        self.entries.iter().map(|entry| entry.at).min()
+    }
+    /// Take the trigger which is due next, if any. Triggers due at the same time are taken in schedule order.
+    pub(super) fn pop_due(&mut self, now: Duration) -> Option<F::Trigger> {
+        let (index, _) = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.at <= now)
+            .min_by_key(|(_, entry)| entry.at)?;
+        match &self.entries[index].payload {
+            Payload::Once(_) => match self.entries.remove(index).payload {
+                Payload::Once(trigger) => Some(trigger),
+                Payload::Every(..) => unreachable!("Payload was checked to be a one-shot"),
+            },
+            Payload::Every(interval, trigger) => {
+                let trigger = self.coder.decode(trigger, "Trigger");
+                let interval = *interval;
+                let entry = &mut self.entries[index];
+                entry.at += interval;
+                if entry.at <= now {
+                    // skip missed runs
+                    entry.at = now + interval;
+                }
+                Some(trigger)
+            }
+        }
+    }
+}
+
+#[test]
+fn test_schedule() {
+    use super::{EventPolicy, Simulation, StateProgress, StateTrait};
+    struct State;
+    impl StateTrait for State {
+        type Event = u32;
+        fn progress(&mut self) -> StateProgress<Self::Event> {
+            StateProgress::NothingOngoing
+        }
+    }
+    /// Counts down from the trigger to zero, one step every 10ms
+    #[derive(Default)]
+    struct Countdown;
+    impl BackgroundFunction for Countdown {
+        type InitialState = ();
+        type State = State;
+        type Trigger = u32;
+        type Event = u32;
+        const EVENT_POLICY: EventPolicy = EventPolicy::KeepAll;
+
+        fn initial_state<EventSender: Fn(Self::Event)>(
+            self,
+            (): Self::InitialState,
+            _event_sender: EventSender,
+        ) -> Self::State {
+            State
+        }
+        fn trigger<EventSender: Fn(Self::Event)>(
+            _state: &mut Self::State,
+            trigger: Self::Trigger,
+            event_sender: EventSender,
+        ) {
+            event_sender(trigger)
+        }
+        fn trigger_with_context(
+            state: &mut Self::State,
+            trigger: Self::Trigger,
+            context: &mut TriggerContext<'_, Self>,
+        ) {
+            Self::trigger(state, trigger, |event| context.send(event));
+            if trigger > 0 && trigger < 100 {
+                context.schedule(Duration::from_millis(10), trigger - 1);
+            }
+        }
+    }
+    let step = Duration::from_millis(10);
+
+    let mut simulation = Simulation::<Countdown>::new(());
+    simulation.trigger(2);
+    simulation.run_until_idle();
+    for _ in 0..3 {
+        simulation.advance(step);
+        simulation.run_until_idle();
+    }
+    assert_eq!(simulation.events().collect::<Vec<_>>(), [2, 1, 0]);
+
+    let every = simulation.trigger_every(step, 100);
+    let once = simulation.trigger_after(step * 2, 200);
+    simulation.advance(step);
+    simulation.run_until_idle();
+    // missed runs are skipped
+    simulation.advance(step * 3);
+    simulation.run_until_idle();
+    simulation.cancel_schedule(every);
+    simulation.cancel_schedule(once);
+    simulation.advance(step * 3);
+    simulation.run_until_idle();
+    assert_eq!(simulation.events().collect::<Vec<_>>(), [100, 100, 200]);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let mut task = super::BackgroundTask::<Countdown>::new("countdown_thread", ());
+        while task.is_ongoing() {}
+        task.schedule(step, 2);
+        let every = task.schedule_every(step, 100);
+        std::thread::sleep(step * 5 + step / 2);
+        task.cancel_schedule(every);
+        std::thread::sleep(step * 3);
+        while task.is_ongoing() {}
+        let events = task.events().collect::<Vec<_>>();
+        assert_eq!(
+            events.iter().filter(|&&e| e < 100).collect::<Vec<_>>(),
+            [&2, &1, &0]
+        );
+        let periodic = events.iter().filter(|&&e| e == 100).count();
+        assert!((3..=6).contains(&periodic), "{events:?}");
+    }
+}
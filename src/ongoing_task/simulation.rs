@@ -0,0 +1,549 @@
+use std::{cell::RefCell, collections::VecDeque, time::Duration};
+
+use super::events::EventQueue;
+use super::query::{self, QueryTicket};
+use super::schedule::ScheduleId;
+use super::status::Activity;
+use super::supervisor::Supervision;
+use super::worker::Worker;
+use super::worker_loop::{Disconnected, Input, Owner, WorkerLoop};
+use super::{
+    BackgroundFunction, BackgroundTask, Checkpoint, EventBacklog, QueryableFunction, Report, Start,
+    Supervisor, TriggerId,
+};
+use crate::notifier::Notifier;
+
+/// A fault, which can be injected into a [`Simulation`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// The worker panics instead of running the next initial state/trigger
+    PanicOnNextInput,
+    /// The next trigger sent to the worker is lost
+    DropNextTrigger,
+    /// The next event sent by the worker is lost
+    DropNextEvent,
+}
+
+/// This runs a background function step by step on the current thread, using a virtual clock.
+/// Nothing happens unless a step is requested, so tests are deterministic and do not need to wait.
+///
+/// The owner side is a [`BackgroundTask`] (see [`Simulation::task`]), the worker side is the one
+/// of a background thread or a web worker: the inputs (initial state, triggers, scheduled triggers) are run one by one,
+/// and [`super::StateTrait::progress_at`] is ticked explicitly, seeing the virtual clock.
+/// Events emitted by the worker are in flight until they are delivered to the owner side.
+/// A panicking worker is detected by the owner side, which restarts it according to its [`Supervisor`].
+pub struct Simulation<F: BackgroundFunction> {
+    now: Duration,
+    worker: WorkerLoop<F, crate::Bincode>,
+    task: BackgroundTask<F>,
+    wire: Wire<F>,
+    /// The last progress of the state, `None` if nothing is ongoing, see [`super::StateProgress::WaitFor`]
+    wait: Option<Duration>,
+    panic: Option<String>,
+}
+impl<F: BackgroundFunction> std::fmt::Debug for Simulation<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Simulation")
+            .field("now", &self.now)
+            .field("in_flight", &self.wire.in_flight.borrow().len())
+            .field("delivered", &self.task.events.len())
+            .field("faults", &self.wire.faults.borrow())
+            .field("panic", &self.panic)
+            .finish()
+    }
+}
+/// The connection between worker and owner, where faults strike
+struct Wire<F: BackgroundFunction> {
+    in_flight: RefCell<VecDeque<(Option<TriggerId>, F::Event)>>,
+    faults: RefCell<Vec<Fault>>,
+}
+impl<F: BackgroundFunction> Wire<F> {
+    fn take_fault(&self, fault: Fault) -> bool {
+        let mut faults = self.faults.borrow_mut();
+        match faults.iter().position(|f| *f == fault) {
+            Some(position) => {
+                faults.remove(position);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// The worker of a background task in a [`Simulation`]:
+/// inputs wait until the simulation runs them, reports wait until the owner fetches them
+pub(super) struct SimulatedWorker<F: BackgroundFunction> {
+    inputs: VecDeque<Input<F>>,
+    /// Number of inputs sent to the worker, including the initial state
+    sent: usize,
+    reports: Reports<F>,
+    /// Set if the worker panicked, reported once by `crashed`
+    crash: Option<String>,
+    /// Set until the simulation picked up this (re)started worker
+    started: bool,
+}
+/// Reports of the worker, which are not fetched yet. Events are sent over the [`Wire`] instead.
+struct Reports<F: BackgroundFunction> {
+    reports: RefCell<VecDeque<Report<F>>>,
+    activity: RefCell<VecDeque<Activity>>,
+    checkpoints: RefCell<VecDeque<Result<Vec<u8>, String>>>,
+}
+impl<F: BackgroundFunction> SimulatedWorker<F> {
+    /// Start a worker, see [`Supervision::simulated`]
+    fn spawn(
+        _task_name: &str,
+        start: Start<F::InitialState>,
+        _notifier: Notifier,
+        _events: EventQueue<F>,
+    ) -> Worker<F> {
+        let input = match start {
+            Start::Initial(initial_state) => Input::Initial(initial_state),
+            Start::Resume(checkpoint) => Input::Resume(checkpoint.into_bytes()),
+        };
+        Worker::Simulated(Self {
+            inputs: VecDeque::from([input]),
+            sent: 1,
+            reports: Reports {
+                reports: Default::default(),
+                activity: Default::default(),
+                checkpoints: Default::default(),
+            },
+            crash: None,
+            started: true,
+        })
+    }
+    pub(super) fn trigger(&mut self, id: TriggerId, trigger: F::Trigger) {
+        self.send(Input::Trigger(Some(id), trigger));
+    }
+    pub(super) fn schedule(
+        &mut self,
+        id: ScheduleId,
+        delay: Duration,
+        interval: Option<Duration>,
+        trigger: F::Trigger,
+    ) {
+        self.send(Input::Schedule {
+            id,
+            delay,
+            interval,
+            trigger,
+        });
+    }
+    pub(super) fn cancel_schedule(&mut self, id: ScheduleId) {
+        self.send(Input::Cancel(id));
+    }
+    pub(super) fn query(
+        &mut self,
+        ticket: QueryTicket,
+        query: F::Query,
+        codec: &dyn query::QueryCodec<F>,
+    ) where
+        F: QueryableFunction,
+    {
+        self.send(Input::Query(ticket, query::ask::<F>(query, codec)));
+    }
+    pub(super) fn checkpoint(&mut self) {
+        self.send(Input::Checkpoint);
+    }
+    fn send(&mut self, input: Input<F>) {
+        self.sent += 1;
+        self.inputs.push_back(input);
+    }
+    /// The trigger is lost on its way to the worker, see [`Fault::DropNextTrigger`].
+    /// Returns false if the trigger was not sent to the worker.
+    fn lose_trigger(&mut self, id: TriggerId) -> bool {
+        let position = self
+            .inputs
+            .iter()
+            .position(|input| matches!(input, Input::Trigger(Some(sent), _) if *sent == id));
+        match position {
+            Some(position) => {
+                self.inputs.remove(position);
+                self.sent -= 1;
+                true
+            }
+            None => false,
+        }
+    }
+    pub(super) fn take_checkpoint(&self) -> Option<Result<Vec<u8>, String>> {
+        self.reports.checkpoints.borrow_mut().pop_front()
+    }
+    pub(super) fn report(&self) -> Option<Report<F>> {
+        self.reports.reports.borrow_mut().pop_front()
+    }
+    pub(super) fn inputs_sent(&self) -> usize {
+        self.sent
+    }
+    pub(super) fn check_activity(&self) -> Option<Activity> {
+        self.reports.activity.borrow_mut().pop_front()
+    }
+    pub(super) fn crashed(&mut self) -> Option<String> {
+        self.crash.take()
+    }
+}
+/// The owner as seen by the worker: events are sent over the wire, other reports are kept by the worker
+struct Link<'a, F: BackgroundFunction> {
+    wire: &'a Wire<F>,
+    reports: &'a Reports<F>,
+}
+impl<F: BackgroundFunction> Owner<F> for Link<'_, F> {
+    fn report(&self, report: Report<F>) -> Result<(), Disconnected> {
+        match report {
+            Report::Event(id, event) => {
+                if !self.wire.take_fault(Fault::DropNextEvent) {
+                    self.wire.in_flight.borrow_mut().push_back((id, event));
+                }
+            }
+            report => self.reports.reports.borrow_mut().push_back(report),
+        }
+        Ok(())
+    }
+    fn activity(&self, activity: Activity) -> Result<(), Disconnected> {
+        self.reports.activity.borrow_mut().push_back(activity);
+        Ok(())
+    }
+    fn checkpoint(&self, checkpoint: Result<Vec<u8>, String>) -> Result<(), Disconnected> {
+        self.reports.checkpoints.borrow_mut().push_back(checkpoint);
+        Ok(())
+    }
+}
+impl<F: BackgroundFunction> Simulation<F> {
+    /// Create a new simulation. The initial state is the first input of the worker.
+    #[must_use]
+    pub fn new(initial_state: F::InitialState) -> Self {
+        Self::supervised(initial_state, Supervisor::default())
+    }
+    /// Create a new simulation, whose worker is restarted according to `supervisor` if it panics,
+    /// see [`BackgroundTask::supervised`]. The backoff follows the virtual clock.
+    #[must_use]
+    pub fn supervised(initial_state: F::InitialState, supervisor: Supervisor) -> Self {
+        let supervision =
+            Supervision::new::<crate::Bincode>("simulation", supervisor, Some(&initial_state));
+        Self::start(supervision, Start::Initial(initial_state))
+    }
+    /// Create a new simulation from a snapshot, see [`BackgroundTask::resume`]
+    #[must_use]
+    pub fn resume(checkpoint: Checkpoint) -> Self {
+        let supervision =
+            Supervision::new::<crate::Bincode>("simulation", Supervisor::default(), None);
+        Self::start(supervision, Start::Resume(checkpoint))
+    }
+    fn start(supervision: Supervision<F>, start: Start<F::InitialState>) -> Self {
+        let supervision = supervision.simulated(SimulatedWorker::spawn);
+        Self {
+            now: Duration::ZERO,
+            worker: WorkerLoop::new(),
+            task: BackgroundTask::start(supervision, start),
+            wire: Wire {
+                in_flight: Default::default(),
+                faults: Default::default(),
+            },
+            wait: None,
+            panic: None,
+        }
+    }
+
+    /// The current time of the virtual clock, starting at zero
+    #[must_use]
+    pub fn now(&self) -> Duration {
+        self.now
+    }
+    /// Advance the virtual clock. Scheduled triggers which are due are run as the next inputs, in order.
+    /// Periodic triggers are run at most once per call, i.e. missed runs are skipped.
+    pub fn advance(&mut self, duration: Duration) {
+        self.now += duration;
+        self.task.supervision.set_virtual_now(self.now);
+    }
+
+    /// Owner side: the background task, e.g. to check its status, to query it or to take checkpoints.
+    /// Its worker only runs when the simulation is stepped, so it must not be waited for.
+    pub fn task(&mut self) -> &mut BackgroundTask<F> {
+        &mut self.task
+    }
+    /// Owner side: send a trigger to the worker, see [`BackgroundTask::trigger`]
+    pub fn trigger(&mut self, trigger: F::Trigger) -> TriggerId {
+        let id = self.task.trigger(trigger);
+        if self.wire.take_fault(Fault::DropNextTrigger) && !self.simulated().lose_trigger(id) {
+            // the trigger was held or dropped by the owner side, so the fault strikes the next trigger
+            self.inject(Fault::DropNextTrigger);
+        }
+        id
+    }
+    /// Send a trigger to the worker once the virtual clock reached `now() + delay`,
+    /// see [`BackgroundTask::schedule`]
+    pub fn trigger_after(&mut self, delay: Duration, trigger: F::Trigger) -> ScheduleId {
+        self.schedule(delay, None, trigger)
+    }
+    /// Send a trigger to the worker every `interval` of the virtual clock,
+    /// see [`BackgroundTask::schedule_every`]
+    pub fn trigger_every(&mut self, interval: Duration, trigger: F::Trigger) -> ScheduleId {
+        self.schedule(interval, Some(interval), trigger)
+    }
+    /// The schedule is delivered right away, so the delay starts now
+    fn schedule(
+        &mut self,
+        delay: Duration,
+        interval: Option<Duration>,
+        trigger: F::Trigger,
+    ) -> ScheduleId {
+        self.task.schedule_count += 1;
+        let id = ScheduleId::owner(self.task.schedule_count);
+        self.sync();
+        self.worker
+            .schedule(Some(id), self.now, delay, interval, trigger)
+    }
+    /// Cancel a scheduled trigger
+    pub fn cancel_schedule(&mut self, id: ScheduleId) {
+        self.sync();
+        self.worker.cancel_schedule(id)
+    }
+    /// Inject a fault, which strikes the next time it is applicable
+    pub fn inject(&mut self, fault: Fault) {
+        self.wire.faults.borrow_mut().push(fault);
+    }
+    /// If the worker panicked, this is the panic message. It is cleared once the worker is restarted.
+    #[must_use]
+    pub fn panic_message(&self) -> Option<&str> {
+        self.panic.as_deref()
+    }
+
+    /// Worker side: run the next input, i.e. the initial state, the next trigger or a scheduled trigger which is due.
+    /// Queued triggers are coalesced according to [`BackgroundFunction::TRIGGER_POLICY`].
+    /// Returns false if there was no input to run.
+    pub fn run_next_input(&mut self) -> bool {
+        self.sync();
+        if self.panic.is_some() {
+            return false;
+        }
+        let inject_panic = self.wire.take_fault(Fault::PanicOnNextInput);
+        let ran = self.catch(|worker, now, inputs, link| {
+            let Ok(Some(input)) = worker.next_input(now, inputs, link, Some(Duration::ZERO)) else {
+                return false;
+            };
+            if inject_panic {
+                panic!("Injected panic");
+            }
+            let _ = worker.run_input(input, now, inputs, link);
+            true
+        });
+        if ran != Some(false) {
+            // the progress is checked by the next tick
+            self.wait = self.panic.is_none().then_some(Duration::ZERO);
+        } else if inject_panic {
+            // the fault strikes the next input
+            self.inject(Fault::PanicOnNextInput);
+        }
+        ran.unwrap_or(true)
+    }
+    /// Worker side: tick [`super::StateTrait::progress_at`] once.
+    /// If nothing is ongoing and no input is left, the worker reports being idle.
+    /// Returns true if the state reported something ongoing (or an event).
+    pub fn tick(&mut self) -> bool {
+        self.sync();
+        if self.panic.is_some() || !self.worker.is_initialized() {
+            return false;
+        }
+        let wait = self.catch(|worker, now, inputs, link| {
+            let wait = worker.progress(now, link)?;
+            if wait.is_none() && inputs.is_empty() && !worker.has_pending(*now) {
+                worker.idle(link)?;
+            }
+            Ok(wait)
+        });
+        self.wait = match wait {
+            Some(Ok(wait)) => wait,
+            Some(Err(Disconnected)) | None => None,
+        };
+        self.wait.is_some()
+    }
+    /// Deliver the next event in flight to the owner side.
+    /// Returns false if no event was in flight.
+    pub fn deliver_event(&mut self) -> bool {
+        let next = self.wire.in_flight.borrow_mut().pop_front();
+        match next {
+            Some((id, event)) => {
+                self.task.events.push(id, event);
+                self.task.notifier.notify();
+                true
+            }
+            None => false,
+        }
+    }
+    /// Run inputs, ticks and deliveries until the worker is idle (without advancing the clock),
+    /// or until the state waits for the clock (see [`super::StateProgress::WaitFor`]).
+    pub fn run_until_idle(&mut self) {
+        loop {
+            let mut busy = self.run_next_input();
+            busy |= self.tick() && self.wait == Some(Duration::ZERO);
+            while self.deliver_event() {
+                busy = true;
+            }
+            if !busy {
+                break;
+            }
+        }
+    }
+
+    /// Owner side: check if some action is ongoing, see [`BackgroundTask::is_ongoing`]
+    #[must_use]
+    pub fn is_ongoing(&mut self) -> bool {
+        self.task.is_ongoing()
+    }
+    /// Owner side: limit the number of pending events, see [`BackgroundTask::set_event_backlog`]
+    pub fn set_event_backlog(&mut self, backlog: EventBacklog) {
+        self.task.set_event_backlog(backlog);
+    }
+    /// Owner side: fetch delivered events, see [`BackgroundTask::event`]
+    #[must_use]
+    pub fn event(&mut self) -> Option<F::Event> {
+        self.task.event()
+    }
+    /// Owner side: fetch all delivered events, see [`BackgroundTask::events`]
+    pub fn events(&mut self) -> impl Iterator<Item = F::Event> + '_ {
+        self.task.events()
+    }
+
+    fn simulated(&mut self) -> &mut SimulatedWorker<F> {
+        match &mut self.task.background_task {
+            Worker::Simulated(simulated) => simulated,
+            _ => unreachable!("A simulation only spawns simulated workers"),
+        }
+    }
+    /// Pick up a worker (re)started by the owner side, which runs on a new worker loop
+    fn sync(&mut self) {
+        if std::mem::take(&mut self.simulated().started) {
+            self.worker = WorkerLoop::new();
+            self.wait = None;
+            self.panic = None;
+        }
+    }
+    /// Run a step of the worker. If it panics, the worker is dead, which the owner side detects.
+    fn catch<T>(
+        &mut self,
+        f: impl FnOnce(
+            &mut WorkerLoop<F, crate::Bincode>,
+            &Duration,
+            &mut VecDeque<Input<F>>,
+            &Link<'_, F>,
+        ) -> T,
+    ) -> Option<T> {
+        let Worker::Simulated(simulated) = &mut self.task.background_task else {
+            unreachable!("A simulation only spawns simulated workers")
+        };
+        let link = Link {
+            wire: &self.wire,
+            reports: &simulated.reports,
+        };
+        let (worker, now, inputs) = (&mut self.worker, &self.now, &mut simulated.inputs);
+        match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            f(worker, now, inputs, &link)
+        })) {
+            Ok(output) => Some(output),
+            Err(panic) => {
+                let message = super::panic_message(panic.as_ref());
+                simulated.crash = Some(message.clone());
+                self.panic = Some(message);
+                self.wait = None;
+                None
+            }
+        }
+    }
+}
+
+#[test]
+fn test_simulation() {
+    use super::{StateProgress, StateTrait};
+    struct State {
+        value: f32,
+        remaining: Vec<f64>,
+    }
+    impl StateTrait for State {
+        type Event = f64;
+        fn progress(&mut self) -> StateProgress<Self::Event> {
+            match self.remaining.pop() {
+                Some(event) => StateProgress::Event(event),
+                None => StateProgress::NothingOngoing,
+            }
+        }
+    }
+    #[derive(Default)]
+    struct DummyFunction;
+    impl BackgroundFunction for DummyFunction {
+        type InitialState = f32;
+        type State = State;
+        type Trigger = f32;
+        type Event = f64;
+
+        fn initial_state<EventSender: Fn(Self::Event)>(
+            self,
+            initial_state: Self::InitialState,
+            event_sender: EventSender,
+        ) -> Self::State {
+            event_sender(initial_state as _);
+            State {
+                value: initial_state,
+                remaining: Vec::new(),
+            }
+        }
+
+        fn trigger<EventSender: Fn(Self::Event)>(
+            state: &mut Self::State,
+            trigger: Self::Trigger,
+            event_sender: EventSender,
+        ) {
+            assert!(trigger >= 0., "Negative trigger");
+            state.value += trigger;
+            event_sender(state.value as _);
+            state.remaining = vec![-1., -2.];
+        }
+
+        fn event_merge(event: &mut Self::Event, other: Self::Event) {
+            *event = event.max(other)
+        }
+    }
+
+    let mut simulation = Simulation::<DummyFunction>::new(1.);
+    assert!(simulation.is_ongoing());
+    assert!(simulation.run_next_input());
+    assert_eq!(simulation.event(), None);
+    assert!(simulation.deliver_event());
+    assert_eq!(simulation.event(), Some(1.));
+    assert!(!simulation.tick());
+    assert!(!simulation.is_ongoing());
+
+    // single steps: the trigger emits one event immediately, two more while progressing
+    simulation.trigger(2.);
+    assert!(simulation.run_next_input());
+    assert!(simulation.tick());
+    assert!(simulation.tick());
+    assert!(!simulation.tick());
+    assert!(simulation.deliver_event());
+    assert!(simulation.deliver_event());
+    assert_eq!(simulation.event(), Some(3.));
+    assert!(simulation.deliver_event());
+    assert!(!simulation.deliver_event());
+    assert_eq!(simulation.event(), Some(-1.));
+
+    // scheduled triggers follow the virtual clock
+    simulation.trigger_after(Duration::from_secs(10), 4.);
+    simulation.trigger_after(Duration::from_secs(5), 3.);
+    simulation.advance(Duration::from_secs(6));
+    assert_eq!(simulation.now(), Duration::from_secs(6));
+    simulation.run_until_idle();
+    assert_eq!(simulation.event(), Some(6.));
+    simulation.advance(Duration::from_secs(6));
+    simulation.run_until_idle();
+    assert_eq!(simulation.event(), Some(10.));
+
+    // faults
+    simulation.inject(Fault::DropNextTrigger);
+    simulation.trigger(100.);
+    simulation.inject(Fault::DropNextEvent);
+    simulation.trigger(1.);
+    simulation.run_until_idle();
+    assert_eq!(simulation.event(), Some(-1.));
+    simulation.trigger(-1.);
+    simulation.run_until_idle();
+    assert_eq!(simulation.panic_message(), Some("Negative trigger"));
+    assert!(!simulation.is_ongoing());
+}
@@ -0,0 +1,273 @@
+/// The lifecycle of a background task, see [`super::BackgroundTask::status`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackgroundStatus {
+    /// [`super::BackgroundFunction::initial_state`] is running, or the task is resumed from a checkpoint.
+    /// This lasts until the worker gets to the first input, or until it is idle.
+    Initializing,
+    /// A trigger, query or checkpoint is running, or [`super::StateTrait::progress`] reports something ongoing
+    Busy,
+    /// The background task waits for input
+    Idle,
+    /// The background task crashed with the given panic message and is restarted, see [`super::Supervisor`]
+    Failed(String),
+    /// The background task crashed and is not restarted
+    Stopped,
+}
+
+/// Reported by the worker, in order.
+/// Both carry the number of inputs the worker received so far, including the initial state.
+pub(super) enum Activity {
+    /// The initial state is ready
+    Initialized(usize),
+    /// There is no input left
+    Idle(usize),
+}
+
+/// The status after a report of the worker, if it changed.
+/// `sent` is the number of inputs sent to the worker so far, including the initial state.
+pub(super) fn next_status(
+    status: &BackgroundStatus,
+    activity: Activity,
+    sent: usize,
+) -> Option<BackgroundStatus> {
+    match (status, activity) {
+        // otherwise, the initial state may still progress, so wait for the worker to be idle
+        (BackgroundStatus::Initializing, Activity::Initialized(received)) if received < sent => {
+            Some(BackgroundStatus::Busy)
+        }
+        // an idle worker is done with the initial state as well
+        (BackgroundStatus::Initializing | BackgroundStatus::Busy, Activity::Idle(received))
+            if received >= sent =>
+        {
+            Some(BackgroundStatus::Idle)
+        }
+        // reports are outdated: further inputs were sent in the meantime
+        _ => None,
+    }
+}
+
+#[test]
+fn test_status_transitions() {
+    fn transitions(reports: Vec<Activity>, sent: usize) -> Vec<BackgroundStatus> {
+        let mut status = BackgroundStatus::Initializing;
+        let mut transitions = Vec::new();
+        for activity in reports {
+            if let Some(next) = next_status(&status, activity, sent) {
+                status = next;
+                transitions.push(status.clone());
+            }
+        }
+        transitions
+    }
+    use Activity::Initialized;
+    use BackgroundStatus::{Busy, Idle};
+    // the worker reports being initialized first, but the order does not matter
+    assert_eq!(
+        transitions(vec![Initialized(1), Activity::Idle(1)], 1),
+        [Idle]
+    );
+    assert_eq!(
+        transitions(vec![Activity::Idle(1), Initialized(1)], 1),
+        [Idle]
+    );
+    // two triggers were sent during the initialization
+    assert_eq!(
+        transitions(vec![Initialized(1), Activity::Idle(3)], 3),
+        [Busy, Idle]
+    );
+    assert_eq!(
+        transitions(
+            vec![
+                Activity::Idle(1),
+                Initialized(1),
+                Activity::Idle(2),
+                Activity::Idle(3)
+            ],
+            3
+        ),
+        [Busy, Idle]
+    );
+    // an outdated report
+    assert_eq!(next_status(&Busy, Activity::Idle(2), 3), None);
+}
+
+#[test]
+fn test_background_status() {
+    use super::{BackgroundFunction, Restart, Simulation, StateProgress, StateTrait, Supervisor};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    struct State;
+    impl StateTrait for State {
+        type Event = ();
+        fn progress(&mut self) -> StateProgress<Self::Event> {
+            StateProgress::NothingOngoing
+        }
+    }
+    #[derive(Default)]
+    struct Fragile;
+    impl BackgroundFunction for Fragile {
+        type InitialState = ();
+        type State = State;
+        type Trigger = i32;
+        type Event = ();
+        const EVENT_POLICY: crate::EventPolicy = crate::EventPolicy::Latest;
+
+        fn initial_state<EventSender: Fn(Self::Event)>(
+            self,
+            (): Self::InitialState,
+            _event_sender: EventSender,
+        ) -> Self::State {
+            State
+        }
+        fn trigger<EventSender: Fn(Self::Event)>(
+            _state: &mut Self::State,
+            trigger: Self::Trigger,
+            _event_sender: EventSender,
+        ) {
+            assert!(trigger >= 0, "Negative trigger");
+        }
+    }
+    fn start() -> (Simulation<Fragile>, Arc<Mutex<Vec<BackgroundStatus>>>) {
+        let mut simulation = Simulation::<Fragile>::supervised(
+            (),
+            Supervisor {
+                restart: Restart::Limited {
+                    max_restarts: 1,
+                    window: Duration::from_secs(60),
+                },
+                ..Default::default()
+            },
+        );
+        let transitions = Arc::new(Mutex::new(Vec::new()));
+        let observed = transitions.clone();
+        simulation
+            .task()
+            .set_status_notifier(move |status| observed.lock().unwrap().push(status.clone()));
+        (simulation, transitions)
+    }
+    use BackgroundStatus::*;
+
+    let (mut simulation, transitions) = start();
+    assert_eq!(simulation.task().status(), Initializing);
+    simulation.run_next_input();
+    // the initial state may still progress
+    assert_eq!(simulation.task().status(), Initializing);
+    simulation.run_until_idle();
+    assert_eq!(simulation.task().status(), Idle);
+    simulation.trigger(1);
+    assert_eq!(simulation.task().status(), Busy);
+    simulation.run_until_idle();
+    assert_eq!(simulation.task().status(), Idle);
+    simulation.trigger(-1);
+    simulation.run_until_idle();
+    // the crash is detected, and the task is restarted right away
+    assert_eq!(simulation.task().status(), Initializing);
+    simulation.run_until_idle();
+    assert_eq!(simulation.task().status(), Idle);
+    simulation.trigger(-1);
+    simulation.run_until_idle();
+    assert_eq!(simulation.task().status(), Stopped);
+    assert_eq!(
+        *transitions.lock().unwrap(),
+        [
+            Idle,
+            Busy,
+            Idle,
+            Busy,
+            Failed("Negative trigger".into()),
+            Initializing,
+            Idle,
+            Busy,
+            Stopped
+        ]
+    );
+
+    // a trigger sent during the initialization
+    let (mut simulation, transitions) = start();
+    simulation.trigger(1);
+    assert_eq!(simulation.task().status(), Initializing);
+    simulation.run_next_input();
+    assert_eq!(simulation.task().status(), Busy);
+    simulation.run_until_idle();
+    assert_eq!(simulation.task().status(), Idle);
+    assert_eq!(*transitions.lock().unwrap(), [Busy, Idle]);
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn test_background_status_std() {
+    use super::{
+        BackgroundFunction, BackgroundTask, Restart, RestartFrom, StateProgress, StateTrait,
+        Supervisor,
+    };
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    struct State;
+    impl StateTrait for State {
+        type Event = ();
+        fn progress(&mut self) -> StateProgress<Self::Event> {
+            StateProgress::NothingOngoing
+        }
+    }
+    #[derive(Default)]
+    struct Panicking;
+    impl BackgroundFunction for Panicking {
+        type InitialState = ();
+        type State = State;
+        type Trigger = ();
+        type Event = ();
+        const EVENT_POLICY: crate::EventPolicy = crate::EventPolicy::Latest;
+
+        fn initial_state<EventSender: Fn(Self::Event)>(
+            self,
+            _initial_state: Self::InitialState,
+            _event_sender: EventSender,
+        ) -> Self::State {
+            State
+        }
+        fn trigger<EventSender: Fn(Self::Event)>(
+            _state: &mut Self::State,
+            _trigger: Self::Trigger,
+            _event_sender: EventSender,
+        ) {
+            panic!("Trigger panicked");
+        }
+    }
+
+    for (restart, crashed) in [
+        (Restart::Never, BackgroundStatus::Stopped),
+        (
+            // the backoff is long, so the task stays failed
+            Restart::Always {
+                backoff: Duration::from_secs(60),
+                max_backoff: Duration::from_secs(60),
+            },
+            BackgroundStatus::Failed("Trigger panicked".into()),
+        ),
+    ] {
+        let mut task = BackgroundTask::<Panicking>::supervised(
+            "panicking_thread",
+            (),
+            Supervisor {
+                restart,
+                from: RestartFrom::InitialState,
+            },
+        );
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let sender = Mutex::new(sender);
+        task.set_notifier(move || {
+            let _ = sender.lock().unwrap().send(());
+        });
+        let transitions = Arc::new(Mutex::new(Vec::new()));
+        let recorded = transitions.clone();
+        task.set_status_notifier(move |status| recorded.lock().unwrap().push(status.clone()));
+        task.trigger(());
+        // the owner only looks at the status when the notifier woke it up
+        while task.status() != crashed {
+            receiver
+                .recv_timeout(Duration::from_secs(10))
+                .expect("Crash was not notified");
+        }
+        assert_eq!(transitions.lock().unwrap().last(), Some(&crashed));
+    }
+}
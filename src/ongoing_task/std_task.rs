@@ -1,112 +1,289 @@
 use std::{
-    sync::mpsc::{channel, Receiver, Sender},
+    panic::AssertUnwindSafe,
+    sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryRecvError},
     thread::JoinHandle,
+    time::Duration,
 };
 
-use super::BackgroundFunction;
-
-enum Input<Initial, Trigger> {
-    Initial(Initial),
-    Trigger(Trigger),
-}
+use super::events::EventQueue;
+use super::query::{self, QueryTicket};
+use super::schedule::ScheduleId;
+use super::status::Activity;
+use super::worker_loop::{Disconnected, Input, InputSource, Owner, WorkerLoop};
+use super::{BackgroundFunction, QueryableFunction, Report, Start, TriggerId};
+#[cfg(feature = "serde-parity")]
+use crate::codec::Coder;
+use crate::notifier::Notifier;
 
 pub(super) struct BackgroundTaskStd<F: BackgroundFunction> {
-    trigger: Sender<Input<F::InitialState, F::Trigger>>,
-    event: Receiver<F::Event>,
-    done_receiver: Receiver<super::Ongoing>,
-    _thread: JoinHandle<()>,
+    trigger: Sender<Input<F>>,
+    event: Receiver<Report<F>>,
+    done_receiver: Receiver<Activity>,
+    /// Number of inputs sent to the worker, including the initial state
+    sent: usize,
+    checkpoint_receiver: Receiver<Result<Vec<u8>, String>>,
+    /// The worker thread, until its crash is detected
+    thread: Option<JoinHandle<()>>,
+    /// The panic message of the worker thread
+    crash_receiver: Receiver<String>,
+    /// Set if the worker thread could not be spawned, reported once by `crashed`
+    spawn_error: Option<String>,
+    #[cfg(feature = "serde-parity")]
+    parity: Parity<F>,
+}
+/// Coders for the serde round trip of each message type
+#[cfg(feature = "serde-parity")]
+struct Parity<F: BackgroundFunction> {
+    trigger: Coder<F::Trigger>,
+    event: Coder<F::Event>,
+    warn_size: Option<usize>,
 }
 impl<F: BackgroundFunction> BackgroundTaskStd<F> {
-    pub(super) fn new(thread_name: &str, initial_state: F::InitialState) -> Self {
+    pub(super) fn new<C: crate::Codec>(
+        thread_name: &str,
+        start: Start<F::InitialState>,
+        notifier: Notifier,
+        events: EventQueue<F>,
+    ) -> Self {
         let (input_sender, input_receiver) = channel();
         let (event_sender, event_receiver) = channel();
         let (done_sender, done_receiver) = channel();
+        let (checkpoint_sender, checkpoint_receiver) = channel();
+        let (crash_sender, crash_receiver) = channel();
         let thread = std::thread::Builder::new()
             .name(thread_name.into())
             .spawn(move || {
-                let mut state: Option<<F as BackgroundFunction>::State> = None;
-                loop {
-                    let input = {
-                        use super::StateProgress::*;
-                        use super::StateTrait;
-                        let ongoing = match state
-                            .as_mut()
-                            .map(|state| state.progress())
-                            .unwrap_or(NothingOngoing)
-                        {
-                            NothingOngoing => false,
-                            Ongoing => true,
-                            Event(event) => {
-                                let r = event_sender.send(event);
-                                if r.is_err() {
-                                    break;
-                                }
-                                true
-                            }
-                        };
-                        if ongoing {
-                            match input_receiver.try_recv() {
-                                Ok(input) => Some(input),
-                                Err(std::sync::mpsc::TryRecvError::Empty) => None,
-                                Err(std::sync::mpsc::TryRecvError::Disconnected) => break,
-                            }
-                        } else {
-                            if state.is_some() {
-                                if done_sender.send(super::Ongoing::NotOnging).is_err() {
-                                    break;
-                                }
-                            }
-                            let input = match input_receiver.recv() {
-                                Ok(input) => Some(input),
-                                Err(std::sync::mpsc::RecvError) => break,
-                            };
-                            if done_sender.send(super::Ongoing::Ongoing).is_err() {
-                                break;
-                            }
-                            input
-                        }
-                    };
-
-                    match input {
-                        Some(Input::Initial(initial)) => {
-                            state = Some(F::initial_state(Default::default(), initial, |e| {
-                                let _ = event_sender.send(e);
-                            }))
-                        }
-                        Some(Input::Trigger(trigger)) => {
-                            if let Some(initial_state) = &mut state {
-                                F::trigger(initial_state, trigger, |e| {
-                                    let _ = event_sender.send(e);
-                                })
-                            } else {
-                                unreachable!(
-                                    "Initial State not yet initialized - \
-                                        this is set already inside this function"
-                                );
-                            }
-                        }
-                        None => {}
-                    }
+                let owner = Channels {
+                    events,
+                    event: event_sender,
+                    done: done_sender,
+                    checkpoint: checkpoint_sender,
+                    notifier,
+                };
+                let clock = web_time::Instant::now();
+                let run = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                    WorkerLoop::<F, C>::new().run(&clock, &mut { input_receiver }, &owner)
+                }));
+                // the owner is woken, so it detects the crash without waiting for its next call
+                if let Err(panic) = run {
+                    let _ = crash_sender.send(super::panic_message(panic.as_ref()));
+                    owner.notifier.notify();
                 }
-            })
-            .unwrap();
-        let r = input_sender.send(Input::Initial(initial_state));
-        assert!(r.is_ok());
+            });
+        let (thread, spawn_error) = match thread {
+            Ok(thread) => (Some(thread), None),
+            Err(e) => (
+                None,
+                Some(format!("Failed to spawn background thread: {e}")),
+            ),
+        };
+        let input = match start {
+            Start::Initial(initial_state) => {
+                #[cfg(feature = "serde-parity")]
+                let initial_state = crate::parity::round_trip(
+                    &Coder::new::<C>(),
+                    initial_state,
+                    "InitialState",
+                    None,
+                );
+                Input::Initial(initial_state)
+            }
+            Start::Resume(checkpoint) => Input::Resume(checkpoint.into_bytes()),
+        };
+        // if the thread was not spawned, the input is lost - this is reported by `crashed`
+        let _ = input_sender.send(input);
         Self {
             trigger: input_sender,
             event: event_receiver,
             done_receiver,
-            _thread: thread,
+            sent: 1,
+            checkpoint_receiver,
+            thread,
+            crash_receiver,
+            spawn_error,
+            #[cfg(feature = "serde-parity")]
+            parity: Parity {
+                trigger: Coder::new::<C>(),
+                event: Coder::new::<C>(),
+                warn_size: None,
+            },
         }
     }
-    pub(super) fn trigger(&self, trigger: F::Trigger) {
-        let r = self.trigger.send(Input::Trigger(trigger));
-        assert!(r.is_ok());
+    pub(super) fn trigger(&mut self, id: TriggerId, trigger: F::Trigger) {
+        #[cfg(feature = "serde-parity")]
+        let trigger = crate::parity::round_trip(
+            &self.parity.trigger,
+            trigger,
+            "Trigger",
+            self.parity.warn_size,
+        );
+        self.send(Input::Trigger(Some(id), trigger));
+    }
+    pub(super) fn schedule(
+        &mut self,
+        id: ScheduleId,
+        delay: Duration,
+        interval: Option<Duration>,
+        trigger: F::Trigger,
+    ) {
+        #[cfg(feature = "serde-parity")]
+        let trigger = crate::parity::round_trip(
+            &self.parity.trigger,
+            trigger,
+            "Trigger",
+            self.parity.warn_size,
+        );
+        self.send(Input::Schedule {
+            id,
+            delay,
+            interval,
+            trigger,
+        });
+    }
+    pub(super) fn cancel_schedule(&mut self, id: ScheduleId) {
+        self.send(Input::Cancel(id));
+    }
+    pub(super) fn query(
+        &mut self,
+        ticket: QueryTicket,
+        query: F::Query,
+        codec: &dyn query::QueryCodec<F>,
+    ) where
+        F: QueryableFunction,
+    {
+        #[cfg(not(feature = "serde-parity"))]
+        let question = query::ask::<F>(query, codec);
+        #[cfg(feature = "serde-parity")]
+        let question = self.parity_question(query, codec);
+        self.send(Input::Query(ticket, question));
+    }
+    /// Queries are encoded using the codec of the task on the web, see [`super::WebWorkerBackground`]
+    #[cfg(feature = "serde-parity")]
+    fn parity_question(
+        &self,
+        query: F::Query,
+        codec: &dyn query::QueryCodec<F>,
+    ) -> query::Question<F>
+    where
+        F: QueryableFunction,
+    {
+        let warn_size = self.parity.warn_size;
+        let query = crate::parity::round_trip(&codec.query(), query, "Query", warn_size);
+        let response = codec.response();
+        Box::new(move |state| {
+            Box::new(crate::parity::round_trip(
+                &response,
+                F::query(state, query),
+                "QueryResponse",
+                warn_size,
+            ))
+        })
     }
-    pub(super) fn event(&self) -> Option<F::Event> {
+    pub(super) fn report(&self) -> Option<Report<F>> {
         self.event.try_recv().ok()
     }
-    pub(super) fn check_done(&self) -> Option<super::Ongoing> {
+    /// Block until the worker reports something. Returns `None` if the worker stopped.
+    pub(super) fn wait_report(&self) -> Option<Report<F>> {
+        self.event.recv().ok()
+    }
+    /// Round trip of an event fetched from the shared queue, see [`EventQueue`]
+    #[cfg(feature = "serde-parity")]
+    pub(super) fn parity_event(&self, event: F::Event) -> F::Event {
+        crate::parity::round_trip(&self.parity.event, event, "Event", self.parity.warn_size)
+    }
+    #[cfg(feature = "serde-parity")]
+    pub(super) fn set_parity_warn_size(&mut self, bytes: Option<usize>) {
+        self.parity.warn_size = bytes;
+    }
+    pub(super) fn checkpoint(&mut self) {
+        self.send(Input::Checkpoint);
+    }
+    pub(super) fn take_checkpoint(&self) -> Option<Result<Vec<u8>, String>> {
+        self.checkpoint_receiver.try_recv().ok()
+    }
+    fn send(&mut self, input: Input<F>) {
+        self.sent += 1;
+        // if the worker crashed, the input is lost - the crash is detected by `crashed`
+        let _ = self.trigger.send(input);
+    }
+    pub(super) fn inputs_sent(&self) -> usize {
+        self.sent
+    }
+    pub(super) fn check_activity(&self) -> Option<Activity> {
         self.done_receiver.try_recv().ok()
     }
+    /// If the worker thread died, this is its panic message. A crash is reported only once.
+    /// A thread which could not be spawned is reported as crashed as well.
+    pub(super) fn crashed(&mut self) -> Option<String> {
+        if let Some(spawn_error) = self.spawn_error.take() {
+            return Some(spawn_error);
+        }
+        self.thread.as_ref()?;
+        // the panic is reported before the thread finished, so the owner is not woken too early
+        if let Ok(message) = self.crash_receiver.try_recv() {
+            self.thread = None;
+            return Some(message);
+        }
+        if !self.thread.as_ref()?.is_finished() {
+            return None;
+        }
+        let _ = self.thread.take()?.join();
+        Some(
+            self.crash_receiver
+                .try_recv()
+                .unwrap_or_else(|_| "Background thread stopped".into()),
+        )
+    }
+}
+
+impl<F: BackgroundFunction> InputSource<F> for Receiver<Input<F>> {
+    fn receive(&mut self, timeout: Option<Duration>) -> Result<Option<Input<F>>, Disconnected> {
+        match timeout {
+            Some(Duration::ZERO) => match self.try_recv() {
+                Ok(input) => Ok(Some(input)),
+                Err(TryRecvError::Empty) => Ok(None),
+                Err(TryRecvError::Disconnected) => Err(Disconnected),
+            },
+            Some(timeout) => match self.recv_timeout(timeout) {
+                Ok(input) => Ok(Some(input)),
+                Err(RecvTimeoutError::Timeout) => Ok(None),
+                Err(RecvTimeoutError::Disconnected) => Err(Disconnected),
+            },
+            None => self.recv().map(Some).map_err(|_| Disconnected),
+        }
+    }
+}
+/// The channels to the owner, which is notified after each report.
+/// Events are pushed to the queue shared with the owner, so they are bounded by the event policy right away.
+struct Channels<F: BackgroundFunction> {
+    events: EventQueue<F>,
+    event: Sender<Report<F>>,
+    done: Sender<Activity>,
+    checkpoint: Sender<Result<Vec<u8>, String>>,
+    notifier: Notifier,
+}
+impl<F: BackgroundFunction> Channels<F> {
+    fn notify<T>(&self, sent: Result<(), T>) -> Result<(), Disconnected> {
+        sent.map_err(|_| Disconnected)?;
+        self.notifier.notify();
+        Ok(())
+    }
+}
+impl<F: BackgroundFunction> Owner<F> for Channels<F> {
+    fn report(&self, report: Report<F>) -> Result<(), Disconnected> {
+        match report {
+            Report::Event(id, event) => {
+                self.events.push(id, event);
+                self.notifier.notify();
+                Ok(())
+            }
+            report => self.notify(self.event.send(report)),
+        }
+    }
+    fn activity(&self, activity: Activity) -> Result<(), Disconnected> {
+        self.notify(self.done.send(activity))
+    }
+    fn checkpoint(&self, checkpoint: Result<Vec<u8>, String>) -> Result<(), Disconnected> {
+        self.notify(self.checkpoint.send(checkpoint))
+    }
 }
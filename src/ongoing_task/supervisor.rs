@@ -0,0 +1,516 @@
+use std::{collections::VecDeque, time::Duration};
+
+use super::query::{QueryCodec, QueryCoders};
+use super::worker_loop::Clock;
+use super::{events::EventQueue, BackgroundFunction, Checkpoint, Start, TriggerId, Worker};
+use crate::{notifier::Notifier, CodecError};
+
+/// When a crashed background task is restarted, see [`Supervisor`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Restart {
+    /// A crashed background task is not restarted
+    #[default]
+    Never,
+    /// A crashed background task is always restarted, after waiting `backoff`.
+    /// The delay doubles with each consecutive crash, up to `max_backoff`.
+    /// Crashes are consecutive unless the task ran for longer than `max_backoff` in between.
+    Always {
+        /// The delay before the first restart
+        backoff: Duration,
+        /// The maximal delay before a restart
+        max_backoff: Duration,
+    },
+    /// A crashed background task is restarted immediately,
+    /// unless it was already restarted `max_restarts` times within `window`
+    Limited {
+        /// The maximal number of restarts within `window`
+        max_restarts: usize,
+        /// The time window
+        window: Duration,
+    },
+}
+
+/// Which state a restarted background task starts from, see [`Supervisor`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RestartFrom {
+    /// Call [`BackgroundFunction::initial_state`] again, with the original initial state
+    #[default]
+    InitialState,
+    /// Resume from the last snapshot fetched using [`super::BackgroundTask::take_checkpoint`].
+    /// If there is none, the original initial state is used.
+    LastCheckpoint,
+}
+
+/// Supervision of a background task, see [`super::BackgroundTask::supervised`].
+/// A background task crashes if [`BackgroundFunction::trigger`] or [`super::StateTrait::progress`] panics.
+///
+/// The notifier (see [`super::BackgroundTask::set_notifier`]) is invoked when a crash is detected,
+/// and again when a delayed restart is due.
+///
+/// On wasm, a panic aborts the web worker. Its panic hook reports the crash to the owner before,
+/// so crashes are detected like on native (see [`super::WebWorkerBackground`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Supervisor {
+    /// When to restart
+    pub restart: Restart,
+    /// Which state to restart from
+    pub from: RestartFrom,
+}
+
+/// A crash of a background task, see [`super::BackgroundTask::take_crash`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Crash {
+    /// The panic message
+    pub message: String,
+    /// True if the background task is restarted, according to its [`Supervisor`]
+    pub restarting: bool,
+}
+
+type Decode<T> = fn(&[u8]) -> Result<T, CodecError>;
+/// Starts a worker, which sends its events to the given queue
+pub(super) type Spawn<F> =
+    fn(&str, Start<<F as BackgroundFunction>::InitialState>, Notifier, EventQueue<F>) -> Worker<F>;
+/// Triggers held while the task is restarting
+type Pending<F> = Vec<(TriggerId, <F as BackgroundFunction>::Trigger)>;
+
+/// The time base of restarts
+enum OwnerClock {
+    /// The time since the task was created
+    Real(web_time::Instant),
+    /// The virtual clock of a [`super::Simulation`]
+    Virtual(Duration),
+}
+impl Clock for OwnerClock {
+    fn now(&self) -> Duration {
+        match self {
+            OwnerClock::Real(start) => start.now(),
+            OwnerClock::Virtual(now) => *now,
+        }
+    }
+}
+
+/// Decides about restarts and keeps what is needed for them
+pub(super) struct Supervision<F: BackgroundFunction> {
+    supervisor: Supervisor,
+    task_name: String,
+    spawn: Spawn<F>,
+    clock: OwnerClock,
+    /// The encoded initial state, only kept if restarts are possible
+    initial_state: Option<(Vec<u8>, Decode<F::InitialState>)>,
+    last_checkpoint: Option<Checkpoint>,
+    /// Times of recent restarts
+    restarts: VecDeque<Duration>,
+    /// If the task crashed, this is the time it is restarted
+    restart_at: Option<Duration>,
+    /// The task crashed and is not restarted
+    stopped: bool,
+    /// Triggers, which are sent once the task is restarted
+    pending: Pending<F>,
+    crashes: VecDeque<Crash>,
+    /// The codec of the task, for queries
+    query_codec: Box<dyn QueryCodec<F>>,
+}
+impl<F: BackgroundFunction> Supervision<F> {
+    pub(super) fn new<C: crate::Codec>(
+        task_name: &str,
+        supervisor: Supervisor,
+        initial_state: Option<&F::InitialState>,
+    ) -> Self {
+        let initial_state = initial_state
+            .filter(|_| supervisor.restart != Restart::Never)
+            .map(|initial_state| {
+                let bytes = C::encode(initial_state)
+                    .unwrap_or_else(|e| panic!("Failed to serialize InitialState: {e}"));
+                let decode: Decode<F::InitialState> = C::decode::<F::InitialState>;
+                (bytes, decode)
+            });
+        Self {
+            supervisor,
+            task_name: task_name.into(),
+            spawn: Worker::<F>::spawn::<C>,
+            clock: OwnerClock::Real(web_time::Instant::now()),
+            initial_state,
+            last_checkpoint: None,
+            restarts: Default::default(),
+            restart_at: None,
+            stopped: false,
+            pending: Default::default(),
+            crashes: Default::default(),
+            query_codec: Box::new(QueryCoders::<C>::default()),
+        }
+    }
+    /// The codec the task was created with, see [`QueryCodec`]
+    pub(super) fn query_codec(&self) -> &dyn QueryCodec<F> {
+        self.query_codec.as_ref()
+    }
+    /// Spawn simulated workers instead, with restarts following the virtual clock, see [`super::Simulation`]
+    pub(super) fn simulated(self, spawn: Spawn<F>) -> Self {
+        Self {
+            spawn,
+            clock: OwnerClock::Virtual(Duration::ZERO),
+            ..self
+        }
+    }
+    /// Advance the virtual clock of a simulation
+    pub(super) fn set_virtual_now(&mut self, now: Duration) {
+        self.clock = OwnerClock::Virtual(now);
+    }
+    pub(super) fn spawn(
+        &self,
+        start: Start<F::InitialState>,
+        notifier: Notifier,
+        events: &EventQueue<F>,
+    ) -> Worker<F> {
+        (self.spawn)(&self.task_name, start, notifier, events.clone())
+    }
+    /// Record a snapshot fetched by the owner, if it is needed for restarts
+    pub(super) fn checkpoint_taken(&mut self, checkpoint: &Checkpoint) {
+        if self.supervisor.from == RestartFrom::LastCheckpoint {
+            self.last_checkpoint = Some(checkpoint.clone());
+        }
+    }
+    /// Check if the task crashed and waits for its restart
+    pub(super) fn is_restarting(&self) -> bool {
+        self.restart_at.is_some()
+    }
+    /// Check if the task crashed and is not restarted
+    pub(super) fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+    /// Keep a trigger until the task is restarted
+    pub(super) fn hold(&mut self, id: TriggerId, trigger: F::Trigger) {
+        self.pending.push((id, trigger));
+    }
+    pub(super) fn take_crash(&mut self) -> Option<Crash> {
+        self.crashes.pop_front()
+    }
+    /// The task crashed, decide about its restart.
+    /// The notifier is invoked once a delayed restart is due, so the owner restarts the task in time.
+    pub(super) fn crashed(&mut self, message: String, notifier: &Notifier) {
+        let now = self.clock.now();
+        let delay = match self.supervisor.restart {
+            Restart::Never => None,
+            _ if self.initial_state.is_none() && self.last_checkpoint.is_none() => None,
+            Restart::Always {
+                backoff,
+                max_backoff,
+            } => {
+                if self
+                    .restarts
+                    .back()
+                    .is_some_and(|last| now.saturating_sub(*last) > max_backoff)
+                {
+                    self.restarts.clear();
+                }
+                let consecutive = self.restarts.len().min(31) as u32;
+                Some(
+                    backoff
+                        .saturating_mul(2u32.saturating_pow(consecutive))
+                        .min(max_backoff),
+                )
+            }
+            Restart::Limited {
+                max_restarts,
+                window,
+            } => {
+                while self
+                    .restarts
+                    .front()
+                    .is_some_and(|restart| now.saturating_sub(*restart) > window)
+                {
+                    self.restarts.pop_front();
+                }
+                (self.restarts.len() < max_restarts).then_some(Duration::ZERO)
+            }
+        };
+        if let (Some(delay), OwnerClock::Real(_)) = (delay, &self.clock) {
+            if !delay.is_zero() {
+                wake_after(notifier.clone(), delay);
+            }
+        }
+        self.restart_at = delay.map(|delay| now + delay);
+        self.stopped = delay.is_none();
+        self.crashes.push_back(Crash {
+            message,
+            restarting: delay.is_some(),
+        });
+    }
+    /// If a restart is due, start the task again. The returned triggers have to be sent to it.
+    /// The restarted task sends its events to the same queue, so pending events are kept.
+    pub(super) fn restart(
+        &mut self,
+        notifier: &Notifier,
+        events: &EventQueue<F>,
+    ) -> Option<(Worker<F>, Pending<F>)> {
+        let now = self.clock.now();
+        if self.restart_at.is_some_and(|restart_at| restart_at <= now) {
+            self.restart_at = None;
+            self.restarts.push_back(now);
+            if self.restarts.len() > 32 {
+                self.restarts.pop_front();
+            }
+            let start = match (&self.last_checkpoint, &self.initial_state) {
+                (Some(checkpoint), _) => Start::Resume(checkpoint.clone()),
+                (None, Some((bytes, decode))) => Start::Initial(
+                    decode(bytes)
+                        .unwrap_or_else(|e| panic!("Failed to deserialize InitialState: {e}")),
+                ),
+                (None, None) => unreachable!("Restarts are only scheduled if a start is available"),
+            };
+            let worker = self.spawn(start, notifier.clone(), events);
+            Some((worker, std::mem::take(&mut self.pending)))
+        } else {
+            None
+        }
+    }
+}
+
+/// Invoke the notifier once `delay` elapsed
+#[cfg(not(target_arch = "wasm32"))]
+fn wake_after(notifier: Notifier, delay: Duration) {
+    // if no thread can be spawned, the restart is only noticed with the next call of the owner
+    let _ = std::thread::Builder::new()
+        .name("task_simple_restart".into())
+        .spawn(move || {
+            std::thread::sleep(delay);
+            notifier.notify();
+        });
+}
+/// Invoke the notifier once `delay` elapsed
+#[cfg(target_arch = "wasm32")]
+fn wake_after(notifier: Notifier, delay: Duration) {
+    super::wasm_task::set_timeout(move || notifier.notify(), delay);
+}
+
+#[test]
+fn test_supervisor() {
+    use super::{BackgroundStatus, Simulation, StateProgress, StateTrait};
+    use serde::{Deserialize, Serialize};
+    #[derive(Serialize, Deserialize)]
+    struct State(i32);
+    impl StateTrait for State {
+        type Event = i32;
+        fn progress(&mut self) -> StateProgress<Self::Event> {
+            StateProgress::NothingOngoing
+        }
+    }
+    #[derive(Default)]
+    struct Fragile;
+    impl BackgroundFunction for Fragile {
+        type InitialState = i32;
+        type State = State;
+        type Trigger = i32;
+        type Event = i32;
+        const EVENT_POLICY: crate::EventPolicy = crate::EventPolicy::Latest;
+
+        fn initial_state<EventSender: Fn(Self::Event)>(
+            self,
+            initial_state: Self::InitialState,
+            _event_sender: EventSender,
+        ) -> Self::State {
+            State(initial_state)
+        }
+        fn trigger<EventSender: Fn(Self::Event)>(
+            state: &mut Self::State,
+            trigger: Self::Trigger,
+            event_sender: EventSender,
+        ) {
+            assert!(trigger >= 0, "Negative trigger");
+            state.0 += trigger;
+            event_sender(state.0);
+        }
+        fn serialize_state<S: serde::Serializer>(
+            state: &Self::State,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            state.serialize(serializer)
+        }
+        fn deserialize_state<'de, D: serde::Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Self::State, D::Error> {
+            State::deserialize(deserializer)
+        }
+    }
+    fn run(simulation: &mut Simulation<Fragile>, trigger: i32) -> Option<i32> {
+        simulation.trigger(trigger);
+        simulation.run_until_idle();
+        simulation.event()
+    }
+    let crashed = || BackgroundStatus::Failed("Negative trigger".into());
+
+    // restart from the initial state, at most once per minute
+    let mut simulation = Simulation::<Fragile>::supervised(
+        10,
+        Supervisor {
+            restart: Restart::Limited {
+                max_restarts: 1,
+                window: Duration::from_secs(60),
+            },
+            from: RestartFrom::InitialState,
+        },
+    );
+    simulation.run_until_idle();
+    assert_eq!(run(&mut simulation, 1), Some(11));
+    assert_eq!(run(&mut simulation, -1), None);
+    assert_eq!(
+        simulation.task().take_crash(),
+        Some(Crash {
+            message: "Negative trigger".into(),
+            restarting: true
+        })
+    );
+    assert_eq!(run(&mut simulation, 2), Some(12));
+    simulation.advance(Duration::from_secs(61));
+    assert_eq!(run(&mut simulation, -1), None);
+    assert!(simulation
+        .task()
+        .take_crash()
+        .is_some_and(|crash| crash.restarting));
+    assert_eq!(run(&mut simulation, 3), Some(13));
+    assert_eq!(run(&mut simulation, -1), None);
+    assert!(simulation
+        .task()
+        .take_crash()
+        .is_some_and(|crash| !crash.restarting));
+    // triggers of a stopped task are dropped, so they are done immediately
+    let dropped = simulation.trigger(2);
+    assert!(simulation.task().is_trigger_done(dropped));
+    assert_eq!(run(&mut simulation, 2), None);
+    assert_eq!(simulation.task().take_crash(), None);
+
+    // restart from the last checkpoint, after a backoff which doubles with each consecutive crash
+    let mut simulation = Simulation::<Fragile>::supervised(
+        10,
+        Supervisor {
+            restart: Restart::Always {
+                backoff: Duration::from_millis(50),
+                max_backoff: Duration::from_secs(1),
+            },
+            from: RestartFrom::LastCheckpoint,
+        },
+    );
+    simulation.run_until_idle();
+    assert_eq!(run(&mut simulation, 5), Some(15));
+    simulation.task().checkpoint();
+    simulation.run_until_idle();
+    assert!(simulation
+        .task()
+        .take_checkpoint()
+        .is_some_and(|c| c.is_ok()));
+    assert_eq!(run(&mut simulation, 5), Some(20));
+    for backoff in [50, 100, 50] {
+        assert_eq!(run(&mut simulation, -1), None);
+        assert_eq!(simulation.task().status(), crashed());
+        simulation.advance(Duration::from_millis(backoff - 1));
+        // the trigger is held until the task is restarted
+        assert_eq!(run(&mut simulation, 1), None);
+        assert_eq!(simulation.task().status(), crashed());
+        simulation.advance(Duration::from_millis(1));
+        assert_eq!(simulation.task().status(), BackgroundStatus::Initializing);
+        simulation.run_until_idle();
+        assert_eq!(simulation.event(), Some(16));
+        assert!(simulation
+            .task()
+            .take_crash()
+            .is_some_and(|crash| crash.restarting));
+        if backoff == 100 {
+            // the task ran for longer than the maximal backoff, so the next crash is not consecutive
+            simulation.advance(Duration::from_secs(2));
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn test_supervisor_std() {
+    use super::{BackgroundStatus, BackgroundTask, StateProgress, StateTrait};
+    use std::sync::mpsc::Receiver;
+    struct State;
+    impl StateTrait for State {
+        type Event = i32;
+        fn progress(&mut self) -> StateProgress<Self::Event> {
+            StateProgress::NothingOngoing
+        }
+    }
+    #[derive(Default)]
+    struct Fragile;
+    impl BackgroundFunction for Fragile {
+        type InitialState = ();
+        type State = State;
+        type Trigger = i32;
+        type Event = i32;
+        const EVENT_POLICY: crate::EventPolicy = crate::EventPolicy::Latest;
+
+        fn initial_state<EventSender: Fn(Self::Event)>(
+            self,
+            _initial_state: Self::InitialState,
+            _event_sender: EventSender,
+        ) -> Self::State {
+            State
+        }
+        fn trigger<EventSender: Fn(Self::Event)>(
+            _state: &mut Self::State,
+            trigger: Self::Trigger,
+            event_sender: EventSender,
+        ) {
+            assert!(trigger >= 0, "Negative trigger");
+            event_sender(trigger);
+        }
+    }
+    /// Wait for the status, only woken by the notifier
+    fn wait_until(
+        task: &mut BackgroundTask<Fragile>,
+        receiver: &Receiver<()>,
+        expected: impl Fn(&BackgroundStatus) -> bool,
+    ) -> BackgroundStatus {
+        loop {
+            let status = task.status();
+            if expected(&status) {
+                return status;
+            }
+            receiver
+                .recv_timeout(Duration::from_secs(10))
+                .expect("Notifier was not invoked");
+        }
+    }
+
+    let mut task = BackgroundTask::<Fragile>::supervised(
+        "fragile_thread",
+        (),
+        Supervisor {
+            restart: Restart::Always {
+                backoff: Duration::from_millis(50),
+                max_backoff: Duration::from_secs(1),
+            },
+            from: RestartFrom::InitialState,
+        },
+    );
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let sender = std::sync::Mutex::new(sender);
+    task.set_notifier(move || {
+        let _ = sender.lock().unwrap().send(());
+    });
+    wait_until(&mut task, &receiver, |status| {
+        *status == BackgroundStatus::Idle
+    });
+    task.trigger(-1);
+    // the crash wakes the owner, and so does the end of the backoff
+    wait_until(&mut task, &receiver, |status| {
+        matches!(status, BackgroundStatus::Failed(_))
+    });
+    let restarted = wait_until(&mut task, &receiver, |status| {
+        !matches!(status, BackgroundStatus::Failed(_))
+    });
+    assert!(matches!(
+        restarted,
+        BackgroundStatus::Initializing | BackgroundStatus::Idle
+    ));
+    assert!(task
+        .take_crash()
+        .is_some_and(|crash| crash.restarting && crash.message == "Negative trigger"));
+    task.trigger(1);
+    wait_until(&mut task, &receiver, |status| {
+        *status == BackgroundStatus::Idle
+    });
+    assert_eq!(task.event(), Some(1));
+}
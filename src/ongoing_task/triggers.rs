@@ -0,0 +1,189 @@
+use super::BackgroundFunction;
+
+/// How triggers, which are still queued when the worker gets to them, are handled,
+/// see [`BackgroundFunction::TRIGGER_POLICY`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TriggerPolicy {
+    /// Every trigger is run, in order
+    #[default]
+    Queue,
+    /// Queued triggers are merged into a single one, using [`BackgroundFunction::trigger_merge`]
+    Merge,
+    /// Only the newest of the queued triggers is run
+    Latest,
+}
+
+/// Identifies a trigger sent by [`super::BackgroundTask::trigger`].
+/// Events sent while the trigger runs are tagged with it, see [`super::BackgroundTask::tagged_event`].
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub struct TriggerId(u64);
+impl TriggerId {
+    /// Ids are numbered from one, in the order the triggers are sent
+    pub(super) fn new(index: u64) -> Self {
+        Self(index)
+    }
+    pub(super) fn index(self) -> u64 {
+        self.0
+    }
+}
+
+/// Merge `next` into `trigger`, according to [`BackgroundFunction::TRIGGER_POLICY`].
+/// If both have to be run, `next` is handed back.
+pub(super) fn coalesce<F: BackgroundFunction>(
+    trigger: &mut F::Trigger,
+    next: F::Trigger,
+) -> Result<(), F::Trigger> {
+    if !F::is_coalescible(trigger) || !F::is_coalescible(&next) {
+        return Err(next);
+    }
+    match F::TRIGGER_POLICY {
+        TriggerPolicy::Queue => Err(next),
+        TriggerPolicy::Merge => {
+            F::trigger_merge(trigger, next);
+            Ok(())
+        }
+        TriggerPolicy::Latest => {
+            *trigger = next;
+            Ok(())
+        }
+    }
+}
+
+#[test]
+fn test_trigger_policy() {
+    use super::{EventPolicy, Simulation, StateProgress, StateTrait};
+    struct State;
+    impl StateTrait for State {
+        type Event = u32;
+        fn progress(&mut self) -> StateProgress<Self::Event> {
+            StateProgress::NothingOngoing
+        }
+    }
+    /// Triggers are summed up, except for zero, which is a barrier
+    #[derive(Default)]
+    struct Sum<const POLICY: u8>;
+    impl<const POLICY: u8> BackgroundFunction for Sum<POLICY> {
+        type InitialState = ();
+        type State = State;
+        type Trigger = u32;
+        type Event = u32;
+        const EVENT_POLICY: EventPolicy = EventPolicy::KeepAll;
+        const TRIGGER_POLICY: TriggerPolicy = match POLICY {
+            0 => TriggerPolicy::Merge,
+            _ => TriggerPolicy::Latest,
+        };
+
+        fn initial_state<EventSender: Fn(Self::Event)>(
+            self,
+            (): Self::InitialState,
+            _event_sender: EventSender,
+        ) -> Self::State {
+            State
+        }
+        fn trigger<EventSender: Fn(Self::Event)>(
+            _state: &mut Self::State,
+            trigger: Self::Trigger,
+            event_sender: EventSender,
+        ) {
+            event_sender(trigger)
+        }
+        fn trigger_merge(trigger: &mut Self::Trigger, other: Self::Trigger) {
+            *trigger += other;
+        }
+        fn is_coalescible(trigger: &Self::Trigger) -> bool {
+            *trigger != 0
+        }
+    }
+
+    // the triggers are queued while the initial state is computed
+    let mut simulation = Simulation::<Sum<0>>::new(());
+    for trigger in [1, 2, 0, 3, 4] {
+        simulation.trigger(trigger);
+    }
+    simulation.run_until_idle();
+    assert_eq!(simulation.events().collect::<Vec<_>>(), [3, 0, 7]);
+
+    let mut simulation = Simulation::<Sum<1>>::new(());
+    simulation.run_until_idle();
+    for trigger in [1, 2, 0, 3, 4] {
+        simulation.trigger(trigger);
+    }
+    simulation.run_until_idle();
+    assert_eq!(simulation.events().collect::<Vec<_>>(), [2, 0, 4]);
+
+    // the triggers are queued while the worker is busy, and the owner only fetches events
+    simulation.trigger(1);
+    simulation.run_next_input();
+    simulation.trigger(2);
+    simulation.trigger(3);
+    simulation.run_until_idle();
+    let events = std::iter::from_fn(|| simulation.event()).collect::<Vec<_>>();
+    assert_eq!(events, [1, 3]);
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn test_trigger_id() {
+    use super::{BackgroundTask, EventPolicy, StateProgress, StateTrait};
+    struct State;
+    impl StateTrait for State {
+        type Event = u32;
+        fn progress(&mut self) -> StateProgress<Self::Event> {
+            StateProgress::NothingOngoing
+        }
+    }
+    /// Sends the numbers below the trigger, slowly
+    #[derive(Default)]
+    struct Count;
+    impl BackgroundFunction for Count {
+        type InitialState = ();
+        type State = State;
+        type Trigger = u32;
+        type Event = u32;
+        const EVENT_POLICY: EventPolicy = EventPolicy::KeepAll;
+
+        fn initial_state<EventSender: Fn(Self::Event)>(
+            self,
+            (): Self::InitialState,
+            event_sender: EventSender,
+        ) -> Self::State {
+            event_sender(100);
+            State
+        }
+        fn trigger<EventSender: Fn(Self::Event)>(
+            _state: &mut Self::State,
+            trigger: Self::Trigger,
+            event_sender: EventSender,
+        ) {
+            for i in 0..trigger {
+                std::thread::sleep(std::time::Duration::from_millis(20));
+                event_sender(i);
+            }
+        }
+    }
+
+    let mut task = BackgroundTask::<Count>::new("count_thread", ());
+    let first = task.trigger(2);
+    let second = task.trigger(3);
+    assert!(first < second);
+    assert!(!task.is_trigger_done(second));
+    while !task.is_trigger_done(first) {}
+    let events = task.tagged_events().collect::<Vec<_>>();
+    assert_eq!(
+        events[..3],
+        [(None, 100), (Some(first), 0), (Some(first), 1)]
+    );
+    assert!(events[3..].iter().all(|(id, _)| *id == Some(second)));
+    while !task.is_trigger_done(second) {}
+    let events = events[3..]
+        .iter()
+        .copied()
+        .chain(task.tagged_events())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        events,
+        [(Some(second), 0), (Some(second), 1), (Some(second), 2)]
+    );
+}
@@ -1,85 +1,411 @@
-use std::collections::VecDeque;
+use std::{collections::VecDeque, time::Duration};
+
+use super::events::EventQueue;
+use super::status::Activity;
+use super::worker_loop::{self, Disconnected, Owner, WorkerLoop};
+use super::{
+    BackgroundFunction, QueryTicket, QueryableFunction, Report, ScheduleId, Start, TriggerId,
+};
+use crate::codec::{Bincode, Codec, GlooCodec};
+use crate::notifier::Notifier;
+
+/// The input of the worker running `F`
+type WorkerInput<F> =
+    Input<<F as BackgroundFunction>::InitialState, <F as BackgroundFunction>::Trigger>;
+type Bridge<F> = Box<dyn Fn(WorkerInput<F>)>;
+/// Messages received from the worker, not yet fetched
+type Queue<T> = std::rc::Rc<std::cell::Cell<VecDeque<T>>>;
 
-use super::BackgroundFunction;
 pub(super) struct BackgroundTaskWasm<F: BackgroundFunction> {
-    event_update: std::rc::Rc<std::cell::Cell<VecDeque<F::Event>>>,
-    done_update: std::rc::Rc<std::cell::Cell<VecDeque<super::Ongoing>>>,
-    bridge: gloo_worker::WorkerBridge<WebWorkerBackground<F>>,
+    event_update: Queue<Report<F>>,
+    done_update: Queue<Activity>,
+    checkpoint_update: Queue<Result<Vec<u8>, String>>,
+    /// Number of inputs sent to the worker, including the initial state
+    sent: usize,
+    /// Sends an input to the worker (the bridge type depends on the codec)
+    bridge: Bridge<F>,
+    /// Set if the worker could not be spawned or panicked, reported once by `crashed`
+    crash: std::rc::Rc<std::cell::Cell<Option<String>>>,
 }
 impl<F: BackgroundFunction> BackgroundTaskWasm<F> {
-    pub(super) fn new(javascript_name: &str, initial_state: F::InitialState) -> Self {
+    pub(super) fn new<C: Codec>(
+        javascript_name: &str,
+        start: Start<F::InitialState>,
+        notifier: Notifier,
+        events: EventQueue<F>,
+    ) -> Self {
         let event_update = std::rc::Rc::new(std::cell::Cell::new(VecDeque::default()));
         let done_update = std::rc::Rc::new(std::cell::Cell::new(VecDeque::default()));
+        let checkpoint_update = std::rc::Rc::new(std::cell::Cell::new(VecDeque::default()));
         let event_sender = event_update.clone();
         let done_sender = done_update.clone();
-        let bridge = <WebWorkerBackground<F> as gloo_worker::Spawnable>::spawner()
+        let checkpoint_sender = checkpoint_update.clone();
+        let crash = std::rc::Rc::new(std::cell::Cell::new(None));
+        let crash_sender = crash.clone();
+        if !crate::task::workers_available() {
+            crash.set(Some("Web workers are not available".into()));
+            return Self {
+                event_update,
+                done_update,
+                checkpoint_update,
+                sent: 1,
+                bridge: Box::new(|_| {}),
+                crash,
+            };
+        }
+        let bridge = <WebWorkerBackground<F, C> as gloo_worker::Spawnable>::spawner()
             .callback(move |response| {
                 // TODO: this seems to be a data-race issue
-                if let Some(event) = response {
-                    let mut previous = done_sender.take();
-                    previous.push_back(super::Ongoing::Ongoing);
-                    done_sender.set(previous);
-                    let mut previous = event_sender.take();
-                    previous.push_back(event);
-                    event_sender.set(previous);
-                } else {
-                    let mut previous = done_sender.take();
-                    previous.push_back(super::Ongoing::NotOnging);
-                    done_sender.set(previous);
+                match response {
+                    // events are sent to the queue shared with the owner, see `EventQueue`
+                    Output::Event(id, event) => events.push(id, event),
+                    Output::TriggerDone(id) => push(&event_sender, Report::TriggerDone(id)),
+                    Output::QueryResponse(ticket, response) => push(
+                        &event_sender,
+                        Report::QueryResponse(ticket, Box::new(response)),
+                    ),
+                    Output::Checkpoint(checkpoint) => push(&checkpoint_sender, checkpoint),
+                    Output::Initialized(received) => {
+                        push(&done_sender, Activity::Initialized(received))
+                    }
+                    Output::Idle(received) => push(&done_sender, Activity::Idle(received)),
+                    Output::Crashed(message) => crash_sender.set(Some(message)),
                 }
+                notifier.notify();
             })
+            .encoding::<GlooCodec<C>>()
             .spawn(&format!("./{javascript_name}.js"));
-        bridge.send(Input::Initial(initial_state));
+        bridge.send(match start {
+            Start::Initial(initial_state) => Input::Initial(initial_state),
+            Start::Resume(checkpoint) => Input::Resume(checkpoint.into_bytes()),
+        });
         Self {
             event_update,
             done_update,
-            bridge,
+            checkpoint_update,
+            sent: 1,
+            bridge: Box::new(move |input| bridge.send(input)),
+            crash,
         }
     }
-    pub(super) fn trigger(&mut self, trigger: F::Trigger) {
-        self.bridge.send(Input::Trigger(trigger));
+    pub(super) fn trigger(&mut self, id: TriggerId, trigger: F::Trigger) {
+        self.send(Input::Trigger(id, trigger));
+    }
+    pub(super) fn schedule(
+        &mut self,
+        id: ScheduleId,
+        delay: Duration,
+        interval: Option<Duration>,
+        trigger: F::Trigger,
+    ) {
+        self.send(Input::Schedule {
+            id,
+            delay,
+            interval,
+            trigger,
+        });
     }
-    pub(super) fn event(&self) -> Option<F::Event> {
-        let d = self.event_update.as_ref();
-        d.take().pop_front()
+    pub(super) fn cancel_schedule(&mut self, id: ScheduleId) {
+        self.send(Input::Cancel(id));
+    }
+    /// Queries are encoded on their own, as the worker does not know the query type (see [`AnswerQueries`])
+    pub(super) fn query(
+        &mut self,
+        ticket: QueryTicket,
+        query: F::Query,
+        codec: &dyn super::query::QueryCodec<F>,
+    ) where
+        F: QueryableFunction,
+    {
+        let query = codec.query().encode(&query, "Query");
+        self.send(Input::Query(ticket, query));
     }
-    pub(super) fn check_done(&self) -> Option<super::Ongoing> {
-        let d = self.done_update.as_ref();
-        d.take().pop_front()
+    pub(super) fn checkpoint(&mut self) {
+        self.send(Input::Checkpoint);
+    }
+    /// Inputs are sent right away, the worker coalesces the triggers it received (see [`super::TriggerPolicy`])
+    fn send(&mut self, input: WorkerInput<F>) {
+        self.sent += 1;
+        (self.bridge)(input);
+    }
+    pub(super) fn take_checkpoint(&self) -> Option<Result<Vec<u8>, String>> {
+        pop(&self.checkpoint_update)
+    }
+    pub(super) fn report(&self) -> Option<Report<F>> {
+        pop(&self.event_update)
+    }
+    pub(super) fn inputs_sent(&self) -> usize {
+        self.sent
+    }
+    pub(super) fn check_activity(&self) -> Option<Activity> {
+        pop(&self.done_update)
+    }
+    /// The panic message of the web worker, reported by its panic hook before the worker aborted.
+    /// A worker which could not be spawned is reported as well, once.
+    pub(super) fn crashed(&mut self) -> Option<String> {
+        self.crash.take()
     }
 }
-/// This is a webworker running the Function F::call
-#[derive(Debug)]
-pub struct WebWorkerBackground<F: BackgroundFunction> {
-    function: std::marker::PhantomData<F>,
-    state: Option<F::State>,
+fn push<T>(queue: &std::cell::Cell<VecDeque<T>>, item: T) {
+    let mut previous = queue.take();
+    previous.push_back(item);
+    queue.set(previous);
 }
-impl<F: BackgroundFunction> Default for WebWorkerBackground<F> {
+fn pop<T>(queue: &std::cell::Cell<VecDeque<T>>) -> Option<T> {
+    let mut previous = queue.take();
+    let item = previous.pop_front();
+    queue.set(previous);
+    item
+}
+
+/// Worker side: answers the queries of the owner, see [`WebWorkerBackground`]
+pub trait AnswerQueries<F: BackgroundFunction> {
+    /// Answer a query encoded by the codec `C` of the worker, yielding the encoded response
+    fn answer<C: Codec>(state: &F::State, query: &[u8]) -> Result<Vec<u8>, String>;
+}
+/// The web worker does not answer queries, so they are lost (see [`super::QueryState::Lost`])
+impl<F: BackgroundFunction> AnswerQueries<F> for () {
+    fn answer<C: Codec>(_state: &F::State, _query: &[u8]) -> Result<Vec<u8>, String> {
+        Err("The web worker does not answer queries, see WebWorkerBackground".into())
+    }
+}
+/// The web worker answers the queries of a [`QueryableFunction`], see [`WebWorkerBackground`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Queries;
+impl<F: QueryableFunction> AnswerQueries<F> for Queries {
+    fn answer<C: Codec>(state: &F::State, query: &[u8]) -> Result<Vec<u8>, String> {
+        let query = C::decode(query).map_err(|e| e.to_string())?;
+        C::encode(&F::query(state, query)).map_err(|e| e.to_string())
+    }
+}
+
+/// This is a webworker running the background function F.
+/// It runs the same worker loop as a background thread, see [`super::Simulation`].
+/// It installs a panic hook, which reports a panic to the owner before the worker aborts (see [`super::Supervisor`]).
+/// The codec `C` is used for messages, checkpoints and queries, and has to match the codec of the [`super::BackgroundTask`].
+/// To answer queries of a [`QueryableFunction`] (see [`super::BackgroundTask::query`]),
+/// register `WebWorkerBackground<F, C, Queries>` instead.
+pub struct WebWorkerBackground<F: BackgroundFunction, C = Bincode, Q = ()> {
+    queries: std::marker::PhantomData<Q>,
+    worker: WorkerLoop<F, C>,
+    /// Inputs received, which are not run yet
+    inputs: VecDeque<worker_loop::Input<F>>,
+    /// Time base of the worker loop
+    start: web_time::Instant,
+    /// Receives the reports
+    owner: Option<gloo_worker::HandlerId>,
+    /// The pending timeout, which wakes the worker to check progress or to run the next scheduled trigger
+    timeout: Option<wasm_bindgen::JsValue>,
+}
+impl<F: BackgroundFunction, C: Codec, Q> Default for WebWorkerBackground<F, C, Q> {
     fn default() -> Self {
         Self {
-            function: Default::default(),
-            state: None,
+            queries: Default::default(),
+            worker: WorkerLoop::new(),
+            inputs: Default::default(),
+            start: web_time::Instant::now(),
+            owner: None,
+            timeout: None,
         }
     }
 }
+impl<F: BackgroundFunction, C, Q> std::fmt::Debug for WebWorkerBackground<F, C, Q> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebWorkerBackground")
+            .field("inputs", &self.inputs.len())
+            .field("owner", &self.owner)
+            .finish()
+    }
+}
+impl<F: BackgroundFunction, C: Codec, Q: AnswerQueries<F> + 'static> WebWorkerBackground<F, C, Q> {
+    /// Run the received inputs and check progress, like the loop of a background thread.
+    /// Instead of blocking, a timeout is set for the next check.
+    fn pump(&mut self, scope: &gloo_worker::WorkerScope<Self>) {
+        let Some(id) = self.owner else {
+            return;
+        };
+        let owner = Respond { scope, id };
+        let clock = self.start;
+        loop {
+            let Ok(wait) = self.worker.progress(&clock, &owner) else {
+                return;
+            };
+            match self
+                .worker
+                .next_input(&clock, &mut self.inputs, &owner, wait)
+            {
+                Ok(Some(input)) => {
+                    let _ = self
+                        .worker
+                        .run_input(input, &clock, &mut self.inputs, &owner);
+                }
+                _ => {
+                    let until_due = self.worker.until_due(clock.elapsed());
+                    let sleep = match (wait, until_due) {
+                        (Some(wait), Some(until_due)) => Some(wait.min(until_due)),
+                        (wait, until_due) => wait.or(until_due),
+                    };
+                    self.arm(scope, sleep);
+                    return;
+                }
+            }
+        }
+    }
+    /// Set a timeout, replacing the previous one
+    fn arm(&mut self, scope: &gloo_worker::WorkerScope<Self>, delay: Option<Duration>) {
+        if let Some(timeout) = self.timeout.take() {
+            clear_timeout(&timeout);
+        }
+        if let Some(delay) = delay {
+            let tick = scope.callback(|()| Tick);
+            self.timeout = set_timeout(move || tick(()), delay);
+        }
+    }
+}
+
+/// A timer function of the global scope, i.e. of the window or of the worker
+fn timer(name: &str) -> Result<js_sys::Function, wasm_bindgen::JsValue> {
+    use wasm_bindgen::JsCast;
+    js_sys::Reflect::get(&js_sys::global(), &wasm_bindgen::JsValue::from_str(name))
+        .map(|function| function.unchecked_into::<js_sys::Function>())
+}
+/// Run `callback` once after `delay`. Returns the id of the timeout, unless it could not be set.
+pub(super) fn set_timeout(
+    callback: impl FnOnce() + 'static,
+    delay: Duration,
+) -> Option<wasm_bindgen::JsValue> {
+    let callback = wasm_bindgen::closure::Closure::once_into_js(callback);
+    timer("setTimeout")
+        .and_then(|set_timeout| {
+            set_timeout.call2(
+                &js_sys::global(),
+                &callback,
+                &(delay.as_secs_f64() * 1000.).into(),
+            )
+        })
+        .ok()
+}
+fn clear_timeout(timeout: &wasm_bindgen::JsValue) {
+    if let Ok(clear_timeout) = timer("clearTimeout") {
+        let _ = clear_timeout.call1(&js_sys::global(), timeout);
+    }
+}
+
+/// Sends the panic message of the worker to its owner
+type ReportPanic = Box<dyn Fn(String)>;
+thread_local! {
+    /// See [`install_panic_hook`]
+    static REPORT_PANIC: std::cell::RefCell<Option<ReportPanic>> =
+        const { std::cell::RefCell::new(None) };
+}
+/// A panic aborts the web worker, so the panic hook reports it to the owner before
+fn install_panic_hook() {
+    static INSTALLED: std::sync::Once = std::sync::Once::new();
+    INSTALLED.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let message = super::panic_message(info.payload());
+            REPORT_PANIC.with(|report| {
+                if let Ok(report) = report.try_borrow() {
+                    if let Some(report) = report.as_ref() {
+                        report(message);
+                    }
+                }
+            });
+            previous(info);
+        }));
+    });
+}
+/// Sends the reports of the worker loop to the owner
+struct Respond<'a, W: gloo_worker::Worker> {
+    scope: &'a gloo_worker::WorkerScope<W>,
+    id: gloo_worker::HandlerId,
+}
+impl<F: BackgroundFunction, C: Codec, Q: AnswerQueries<F> + 'static> Owner<F>
+    for Respond<'_, WebWorkerBackground<F, C, Q>>
+{
+    fn report(&self, report: Report<F>) -> Result<(), Disconnected> {
+        let output = match report {
+            Report::Event(id, event) => Output::Event(id, event),
+            Report::TriggerDone(id) => Output::TriggerDone(id),
+            Report::QueryResponse(ticket, answer) => Output::QueryResponse(
+                ticket,
+                answer
+                    .downcast()
+                    .map(|response| *response)
+                    .unwrap_or_else(|_| Err("Unexpected query response".into())),
+            ),
+        };
+        self.scope.respond(self.id, output);
+        Ok(())
+    }
+    fn activity(&self, activity: Activity) -> Result<(), Disconnected> {
+        self.scope.respond(
+            self.id,
+            match activity {
+                Activity::Initialized(received) => Output::Initialized(received),
+                Activity::Idle(received) => Output::Idle(received),
+            },
+        );
+        Ok(())
+    }
+    fn checkpoint(&self, checkpoint: Result<Vec<u8>, String>) -> Result<(), Disconnected> {
+        self.scope.respond(self.id, Output::Checkpoint(checkpoint));
+        Ok(())
+    }
+}
+
+/// Wakes the worker, to check progress or to run a scheduled trigger which is due
+#[derive(Debug)]
+pub struct Tick;
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub enum Input<Initial, Trigger> {
     Initial(Initial),
-    Trigger(Trigger),
+    Resume(Vec<u8>),
+    Trigger(TriggerId, Trigger),
+    Schedule {
+        id: ScheduleId,
+        delay: Duration,
+        interval: Option<Duration>,
+        trigger: Trigger,
+    },
+    Cancel(ScheduleId),
+    Checkpoint,
+    /// The query, encoded using bincode
+    Query(QueryTicket, Vec<u8>),
+}
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub enum Output<Event> {
+    Event(Option<TriggerId>, Event),
+    /// The trigger ran
+    TriggerDone(TriggerId),
+    /// The response, encoded using bincode
+    QueryResponse(QueryTicket, Result<Vec<u8>, String>),
+    Checkpoint(Result<Vec<u8>, String>),
+    /// The initial state is ready, see [`Activity`]
+    Initialized(usize),
+    /// There is no input left, see [`Activity`]
+    Idle(usize),
+    /// The worker panicked, with this message. It is aborted right afterwards.
+    Crashed(String),
 }
 
-impl<F: BackgroundFunction> gloo_worker::Worker for WebWorkerBackground<F> {
-    type Message = std::convert::Infallible;
-    type Input = Input<F::InitialState, F::Trigger>;
-    type Output = Option<F::Event>;
+impl<F: BackgroundFunction, C: Codec, Q: AnswerQueries<F> + 'static> gloo_worker::Worker
+    for WebWorkerBackground<F, C, Q>
+{
+    type Message = Tick;
+    type Input = WorkerInput<F>;
+    type Output = Output<F::Event>;
 
     fn create(_scope: &gloo_worker::WorkerScope<Self>) -> Self {
+        install_panic_hook();
         Default::default()
     }
 
-    fn update(&mut self, _scope: &gloo_worker::WorkerScope<Self>, msg: Self::Message) {
-        match msg {}
+    fn update(&mut self, scope: &gloo_worker::WorkerScope<Self>, Tick: Self::Message) {
+        self.timeout = None;
+        self.pump(scope);
     }
 
     fn received(
@@ -88,20 +414,42 @@ impl<F: BackgroundFunction> gloo_worker::Worker for WebWorkerBackground<F> {
         msg: Self::Input,
         id: gloo_worker::HandlerId,
     ) {
-        match msg {
-            Input::Initial(initial_state) => {
-                self.state = Some(F::initial_state(F::default(), initial_state, |e| {
-                    scope.respond(id, Some(e))
+        if self.owner.is_none() {
+            let scope = scope.clone();
+            REPORT_PANIC.with(|report| {
+                *report.borrow_mut() = Some(Box::new(move |message| {
+                    scope.respond(id, Output::Crashed(message));
                 }));
-                scope.respond(id, None);
-            }
-            Input::Trigger(trigger) => {
-                if let Some(state) = &mut self.state {
-                    F::trigger(state, trigger, |e| scope.respond(id, Some(e)))
-                } else {
-                    unreachable!("Initial State not yet initialized - this is set already inside this function");
-                }
-            }
+            });
+        }
+        self.owner = Some(id);
+        let queued = !self.inputs.is_empty();
+        self.inputs.push_back(match msg {
+            Input::Initial(initial_state) => worker_loop::Input::Initial(initial_state),
+            Input::Resume(bytes) => worker_loop::Input::Resume(bytes),
+            Input::Trigger(id, trigger) => worker_loop::Input::Trigger(Some(id), trigger),
+            Input::Schedule {
+                id,
+                delay,
+                interval,
+                trigger,
+            } => worker_loop::Input::Schedule {
+                id,
+                delay,
+                interval,
+                trigger,
+            },
+            Input::Cancel(id) => worker_loop::Input::Cancel(id),
+            Input::Checkpoint => worker_loop::Input::Checkpoint,
+            Input::Query(ticket, query) => worker_loop::Input::Query(
+                ticket,
+                Box::new(move |state| Box::new(Q::answer::<C>(state, &query))),
+            ),
+        });
+        // inputs are run once the messages which arrived in the meantime are received,
+        // so queued triggers are coalesced, see [`super::TriggerPolicy`]
+        if !queued {
+            self.arm(scope, Some(Duration::ZERO));
         }
     }
 }
@@ -0,0 +1,119 @@
+use std::time::Duration;
+
+use super::events::EventQueue;
+use super::query::QueryCodec;
+use super::simulation::SimulatedWorker;
+use super::status::Activity;
+use super::{
+    BackgroundFunction, QueryTicket, QueryableFunction, Report, ScheduleId, Start, TriggerId,
+};
+use crate::notifier::Notifier;
+
+/// The worker of a background task, as seen by the owner
+pub(super) enum Worker<F: BackgroundFunction> {
+    /// A background thread
+    #[cfg(not(target_arch = "wasm32"))]
+    Native(super::std_task::BackgroundTaskStd<F>),
+    /// A web worker
+    #[cfg(target_arch = "wasm32")]
+    Web(super::wasm_task::BackgroundTaskWasm<F>),
+    /// A worker stepped by a [`super::Simulation`]
+    Simulated(SimulatedWorker<F>),
+}
+/// Calls the method of the backend
+macro_rules! dispatch {
+    ($worker:expr, $backend:ident => $call:expr) => {
+        match $worker {
+            #[cfg(not(target_arch = "wasm32"))]
+            Worker::Native($backend) => $call,
+            #[cfg(target_arch = "wasm32")]
+            Worker::Web($backend) => $call,
+            Worker::Simulated($backend) => $call,
+        }
+    };
+}
+impl<F: BackgroundFunction> Worker<F> {
+    /// Start a background thread (or a web worker), see [`super::supervisor::Supervision`]
+    pub(super) fn spawn<C: crate::Codec>(
+        task_name: &str,
+        start: Start<F::InitialState>,
+        notifier: Notifier,
+        events: EventQueue<F>,
+    ) -> Self {
+        #[cfg(not(target_arch = "wasm32"))]
+        return Self::Native(super::std_task::BackgroundTaskStd::new::<C>(
+            task_name, start, notifier, events,
+        ));
+        #[cfg(target_arch = "wasm32")]
+        return Self::Web(super::wasm_task::BackgroundTaskWasm::new::<C>(
+            task_name, start, notifier, events,
+        ));
+    }
+    pub(super) fn trigger(&mut self, id: TriggerId, trigger: F::Trigger) {
+        dispatch!(self, worker => worker.trigger(id, trigger))
+    }
+    pub(super) fn schedule(
+        &mut self,
+        id: ScheduleId,
+        delay: Duration,
+        interval: Option<Duration>,
+        trigger: F::Trigger,
+    ) {
+        dispatch!(self, worker => worker.schedule(id, delay, interval, trigger))
+    }
+    pub(super) fn cancel_schedule(&mut self, id: ScheduleId) {
+        dispatch!(self, worker => worker.cancel_schedule(id))
+    }
+    pub(super) fn query(&mut self, ticket: QueryTicket, query: F::Query, codec: &dyn QueryCodec<F>)
+    where
+        F: QueryableFunction,
+    {
+        dispatch!(self, worker => worker.query(ticket, query, codec))
+    }
+    pub(super) fn checkpoint(&mut self) {
+        dispatch!(self, worker => worker.checkpoint())
+    }
+    pub(super) fn take_checkpoint(&self) -> Option<Result<Vec<u8>, String>> {
+        dispatch!(self, worker => worker.take_checkpoint())
+    }
+    pub(super) fn report(&self) -> Option<Report<F>> {
+        dispatch!(self, worker => worker.report())
+    }
+    /// Block until the worker reports something. Returns `None` if the worker stopped.
+    ///
+    /// Panics for a simulated worker, which only runs when it is stepped.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(super) fn wait_report(&self) -> Option<Report<F>> {
+        match self {
+            Worker::Native(worker) => worker.wait_report(),
+            Worker::Simulated(_) => {
+                panic!("A simulation only runs when it is stepped, so it cannot be waited for")
+            }
+        }
+    }
+    pub(super) fn inputs_sent(&self) -> usize {
+        dispatch!(self, worker => worker.inputs_sent())
+    }
+    pub(super) fn check_activity(&mut self) -> Option<Activity> {
+        dispatch!(self, worker => worker.check_activity())
+    }
+    /// The panic message of a crashed worker, reported once
+    pub(super) fn crashed(&mut self) -> Option<String> {
+        dispatch!(self, worker => worker.crashed())
+    }
+    /// Round trip of an event fetched from the shared queue, see [`EventQueue`].
+    /// Simulated workers do not check serde parity.
+    #[cfg(all(not(target_arch = "wasm32"), feature = "serde-parity"))]
+    pub(super) fn parity_event(&self, event: F::Event) -> F::Event {
+        match self {
+            Worker::Native(worker) => worker.parity_event(event),
+            Worker::Simulated(_) => event,
+        }
+    }
+    #[cfg(all(not(target_arch = "wasm32"), feature = "serde-parity"))]
+    pub(super) fn set_parity_warn_size(&mut self, bytes: Option<usize>) {
+        if let Worker::Native(worker) = self {
+            worker.set_parity_warn_size(bytes);
+        }
+    }
+}
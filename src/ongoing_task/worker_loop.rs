@@ -0,0 +1,288 @@
+use std::time::Duration;
+
+use super::query::Question;
+use super::schedule::{ScheduleId, Scheduler, TriggerContext};
+use super::status::Activity;
+use super::{
+    checkpoint, triggers, BackgroundFunction, QueryTicket, Report, StateProgress, StateTrait,
+    TriggerId, TriggerPolicy,
+};
+
+/// An input sent by the owner to the worker
+pub(super) enum Input<F: BackgroundFunction> {
+    Initial(F::InitialState),
+    Resume(Vec<u8>),
+    /// Scheduled triggers have no id
+    Trigger(Option<TriggerId>, F::Trigger),
+    Checkpoint,
+    Query(QueryTicket, Question<F>),
+    Schedule {
+        id: ScheduleId,
+        delay: Duration,
+        interval: Option<Duration>,
+        trigger: F::Trigger,
+    },
+    Cancel(ScheduleId),
+}
+
+/// The owner is gone, so the worker stops
+pub(super) struct Disconnected;
+
+/// The time base of the worker, i.e. the time since the worker started
+pub(super) trait Clock {
+    fn now(&self) -> Duration;
+}
+impl Clock for web_time::Instant {
+    fn now(&self) -> Duration {
+        self.elapsed()
+    }
+}
+/// A virtual clock, see [`super::Simulation`]
+impl Clock for Duration {
+    fn now(&self) -> Duration {
+        *self
+    }
+}
+
+/// Inputs sent by the owner, in order
+pub(super) trait InputSource<F: BackgroundFunction> {
+    /// The next input, waiting at most `timeout` (or until an input arrives if there is no timeout).
+    /// Returns `Ok(None)` if no input arrived in time.
+    fn receive(&mut self, timeout: Option<Duration>) -> Result<Option<Input<F>>, Disconnected>;
+}
+
+/// Inputs which arrived already are never waited for:
+/// a [`super::Simulation`] only advances its clock on request, and a web worker must not block
+impl<F: BackgroundFunction> InputSource<F> for std::collections::VecDeque<Input<F>> {
+    fn receive(&mut self, _timeout: Option<Duration>) -> Result<Option<Input<F>>, Disconnected> {
+        Ok(self.pop_front())
+    }
+}
+
+/// Receives the reports of the worker
+pub(super) trait Owner<F: BackgroundFunction> {
+    fn report(&self, report: Report<F>) -> Result<(), Disconnected>;
+    fn activity(&self, activity: Activity) -> Result<(), Disconnected>;
+    fn checkpoint(&self, checkpoint: Result<Vec<u8>, String>) -> Result<(), Disconnected>;
+}
+
+/// The worker side of a background task: it runs the inputs of the owner, scheduled triggers
+/// and [`StateTrait::progress_at`]. The native worker runs it on a thread, a [`super::Simulation`] steps it.
+/// The codec `C` is used for checkpoints and periodic triggers.
+pub(super) struct WorkerLoop<F: BackgroundFunction, C> {
+    state: Option<F::State>,
+    /// An input received while coalescing triggers, which is run next
+    held: Option<Input<F>>,
+    /// Number of inputs received, see `Activity`
+    received: usize,
+    scheduler: Scheduler<F>,
+    codec: std::marker::PhantomData<C>,
+}
+impl<F: BackgroundFunction, C: crate::Codec> WorkerLoop<F, C> {
+    pub(super) fn new() -> Self {
+        Self {
+            state: None,
+            held: None,
+            received: 0,
+            scheduler: Scheduler::new::<C>(),
+            codec: std::marker::PhantomData,
+        }
+    }
+    /// Run until the owner is gone
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(super) fn run(
+        mut self,
+        clock: &impl Clock,
+        inputs: &mut impl InputSource<F>,
+        owner: &impl Owner<F>,
+    ) {
+        while self.step(clock, inputs, owner).is_ok() {}
+    }
+    /// Check the progress of the state, then wait for the next input and run it
+    #[cfg(not(target_arch = "wasm32"))]
+    fn step(
+        &mut self,
+        clock: &impl Clock,
+        inputs: &mut impl InputSource<F>,
+        owner: &impl Owner<F>,
+    ) -> Result<(), Disconnected> {
+        let wait = self.progress(clock, owner)?;
+        if let Some(input) = self.next_input(clock, inputs, owner, wait)? {
+            self.run_input(input, clock, inputs, owner)?;
+        }
+        Ok(())
+    }
+
+    /// Check the progress of the state.
+    /// Returns how long to wait for input before checking again, or `None` if nothing is ongoing.
+    pub(super) fn progress(
+        &mut self,
+        clock: &impl Clock,
+        owner: &impl Owner<F>,
+    ) -> Result<Option<Duration>, Disconnected> {
+        let progress = match &mut self.state {
+            Some(state) => state.progress_at(clock.now()),
+            None => StateProgress::NothingOngoing,
+        };
+        Ok(match progress {
+            StateProgress::NothingOngoing => None,
+            StateProgress::Ongoing => Some(Duration::ZERO),
+            StateProgress::WaitFor(duration) => Some(duration),
+            StateProgress::Event(event) => {
+                owner.report(Report::Event(None, event))?;
+                Some(Duration::ZERO)
+            }
+        })
+    }
+    /// The next input: a held input, a scheduled trigger which is due, or an input of the owner.
+    /// While something is ongoing, this waits at most `wait`.
+    /// Otherwise, the worker reports being idle and waits until the next scheduled trigger is due.
+    pub(super) fn next_input(
+        &mut self,
+        clock: &impl Clock,
+        inputs: &mut impl InputSource<F>,
+        owner: &impl Owner<F>,
+        wait: Option<Duration>,
+    ) -> Result<Option<Input<F>>, Disconnected> {
+        if let Some(held) = self.held.take() {
+            return Ok(Some(held));
+        }
+        let now = clock.now();
+        if let Some(trigger) = self.scheduler.pop_due(now) {
+            return Ok(Some(Input::Trigger(None, trigger)));
+        }
+        let until_due = self.until_due(now);
+        let input = match wait {
+            Some(wait) => inputs.receive(Some(
+                until_due.map_or(wait, |until_due| wait.min(until_due)),
+            ))?,
+            None => match inputs.receive(Some(Duration::ZERO))? {
+                // more inputs are queued, so the task stays busy
+                Some(input) => Some(input),
+                None => {
+                    self.idle(owner)?;
+                    inputs.receive(until_due)?
+                }
+            },
+        };
+        self.received += usize::from(input.is_some());
+        Ok(input)
+    }
+    /// Run an input. Triggers are coalesced with further queued triggers, see [`TriggerPolicy`].
+    pub(super) fn run_input(
+        &mut self,
+        input: Input<F>,
+        clock: &impl Clock,
+        inputs: &mut impl InputSource<F>,
+        owner: &impl Owner<F>,
+    ) -> Result<(), Disconnected> {
+        let send_event = |event| {
+            // if the owner is gone, this is detected by the next report
+            let _ = owner.report(Report::Event(None, event));
+        };
+        match input {
+            Input::Initial(initial) => {
+                self.state = Some(F::initial_state(Default::default(), initial, send_event));
+                owner.activity(Activity::Initialized(self.received))?;
+            }
+            Input::Resume(bytes) => {
+                self.state = Some(checkpoint::decode_state::<F, C>(&bytes));
+                owner.activity(Activity::Initialized(self.received))?;
+            }
+            Input::Trigger(mut id, mut trigger) => {
+                while F::TRIGGER_POLICY != TriggerPolicy::Queue && self.held.is_none() {
+                    let next = match inputs.receive(Some(Duration::ZERO)) {
+                        Ok(Some(next)) => next,
+                        _ => break,
+                    };
+                    self.received += 1;
+                    match next {
+                        Input::Trigger(next_id, next) => {
+                            match triggers::coalesce::<F>(&mut trigger, next) {
+                                Ok(()) => id = next_id.or(id),
+                                Err(next) => self.held = Some(Input::Trigger(next_id, next)),
+                            }
+                        }
+                        input => self.held = Some(input),
+                    }
+                }
+                let state = self.state.as_mut().expect(
+                    "Initial State not yet initialized - \
+                        this is the first input of the worker",
+                );
+                let send_event = |event| {
+                    let _ = owner.report(Report::Event(id, event));
+                };
+                let mut context =
+                    TriggerContext::new(&send_event, &mut self.scheduler, clock.now());
+                F::trigger_with_context(state, trigger, &mut context);
+                if let Some(id) = id {
+                    owner.report(Report::TriggerDone(id))?;
+                }
+            }
+            Input::Checkpoint => {
+                let state = self.state.as_ref().expect(
+                    "Initial State not yet initialized - \
+                        this is the first input of the worker",
+                );
+                owner.checkpoint(checkpoint::encode_state::<F, C>(state))?;
+            }
+            Input::Query(ticket, question) => {
+                let state = self.state.as_ref().expect(
+                    "Initial State not yet initialized - \
+                        this is the first input of the worker",
+                );
+                owner.report(Report::QueryResponse(ticket, question(state)))?;
+            }
+            Input::Schedule {
+                id,
+                delay,
+                interval,
+                trigger,
+            } => {
+                self.schedule(Some(id), clock.now(), delay, interval, trigger);
+            }
+            Input::Cancel(id) => self.scheduler.cancel(id),
+        }
+        Ok(())
+    }
+    /// Schedule a trigger, see [`Input::Schedule`]
+    pub(super) fn schedule(
+        &mut self,
+        id: Option<ScheduleId>,
+        now: Duration,
+        delay: Duration,
+        interval: Option<Duration>,
+        trigger: F::Trigger,
+    ) -> ScheduleId {
+        self.scheduler.insert(id, now, delay, interval, trigger)
+    }
+    pub(super) fn cancel_schedule(&mut self, id: ScheduleId) {
+        self.scheduler.cancel(id)
+    }
+    /// The time until the next scheduled trigger is due
+    pub(super) fn until_due(&self, now: Duration) -> Option<Duration> {
+        self.scheduler
+            .next_deadline()
+            .map(|at| at.saturating_sub(now))
+    }
+    /// Report that no input is left, once the state is initialized
+    pub(super) fn idle(&self, owner: &impl Owner<F>) -> Result<(), Disconnected> {
+        if self.state.is_some() {
+            owner.activity(Activity::Idle(self.received))?;
+        }
+        Ok(())
+    }
+    /// Check if the state is initialized
+    pub(super) fn is_initialized(&self) -> bool {
+        self.state.is_some()
+    }
+    /// Check if an input is held, or a scheduled trigger is due
+    pub(super) fn has_pending(&self, now: Duration) -> bool {
+        self.held.is_some()
+            || self
+                .scheduler
+                .next_deadline()
+                .is_some_and(|deadline| deadline <= now)
+    }
+}
@@ -0,0 +1,70 @@
+//! Native serde round trip, so serialization bugs show up without running in the browser
+use crate::codec::Coder;
+
+/// Serialize and deserialize a message, as if it was sent to/from a web worker.
+/// Messages larger than `warn_size` bytes are reported using the `log` crate, see [`crate::Task::set_parity_warn_size`].
+pub(crate) fn round_trip<T>(
+    coder: &Coder<T>,
+    message: T,
+    what: &str,
+    warn_size: Option<usize>,
+) -> T {
+    let bytes = coder.encode(&message, what);
+    if let Some(warn_size) = warn_size.filter(|warn_size| bytes.len() > *warn_size) {
+        log::warn!(
+            "{what} of type {} has {} bytes, which exceeds {warn_size} bytes",
+            std::any::type_name::<T>(),
+            bytes.len()
+        );
+    }
+    coder.decode(&bytes, what)
+}
+
+#[test]
+fn test_round_trip() {
+    #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    struct Message {
+        value: f64,
+        #[serde(skip)]
+        skipped: u8,
+    }
+    /// Collects the warnings
+    struct Logger(std::sync::Mutex<Vec<String>>);
+    impl log::Log for Logger {
+        fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+            metadata.level() <= log::Level::Warn
+        }
+        fn log(&self, record: &log::Record<'_>) {
+            self.0.lock().unwrap().push(record.args().to_string());
+        }
+        fn flush(&self) {}
+    }
+    static LOGGER: Logger = Logger(std::sync::Mutex::new(Vec::new()));
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(log::LevelFilter::Warn);
+
+    let coder = Coder::new::<crate::Bincode>();
+    let message = |value| Message { value, skipped: 7 };
+    // skipped fields are lost, as they would be on the web
+    let expected = Message {
+        value: 0.1,
+        skipped: 0,
+    };
+    assert_eq!(round_trip(&coder, message(0.1), "Message", None), expected);
+    assert_eq!(
+        round_trip(&coder, message(0.1), "Message", Some(8)),
+        expected
+    );
+    assert!(LOGGER.0.lock().unwrap().is_empty());
+    assert_eq!(
+        round_trip(&coder, message(0.1), "Message", Some(7)),
+        expected
+    );
+    assert_eq!(
+        *LOGGER.0.lock().unwrap(),
+        [format!(
+            "Message of type {} has 8 bytes, which exceeds 7 bytes",
+            std::any::type_name::<Message>()
+        )]
+    );
+}
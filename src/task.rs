@@ -1,15 +1,56 @@
 #[cfg(not(target_arch = "wasm32"))]
+mod blocking;
+mod inline_task;
+mod interactive;
+mod multi_function;
+mod pipeline;
+#[cfg(all(not(target_arch = "wasm32"), feature = "process"))]
+mod process_task;
+mod retention;
+#[cfg(not(target_arch = "wasm32"))]
+mod spawn;
+#[cfg(not(target_arch = "wasm32"))]
 mod std_task;
 mod ticket;
 #[cfg(target_arch = "wasm32")]
 mod wasm_task;
+#[cfg(target_arch = "wasm32")]
+pub(crate) use wasm_task::workers_available;
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "serde-parity"))]
+use crate::codec::Coder;
+use crate::codec::{Bincode, Codec};
 
+/// Coders for input and output, used for the serde round trip
+#[cfg(all(not(target_arch = "wasm32"), feature = "serde-parity"))]
+type Coders<F> = (
+    Coder<<F as NativeFunction>::Input>,
+    Coder<<F as NativeFunction>::Output>,
+);
+#[cfg(not(target_arch = "wasm32"))]
+pub use blocking::{Blocking, BlockingFunction, Requester};
 #[cfg(target_arch = "wasm32")]
 pub use gloo_worker;
-use std::collections::{HashMap, VecDeque};
+pub use interactive::{
+    Interaction, Interactive, InteractiveFunction, InteractiveStep, InteractiveTask,
+};
+pub use multi_function::{Envelope, FunctionSet, MultiFunction};
+pub use pipeline::Pipeline;
+#[cfg(all(not(target_arch = "wasm32"), feature = "process"))]
+pub use process_task::ProcessWorker;
+pub use retention::Retention;
+#[cfg(not(target_arch = "wasm32"))]
+pub use spawn::{Closure, ClosuresUnsupported, JobHandle};
+use std::collections::{HashMap, HashSet, VecDeque};
 pub use ticket::Ticket;
 #[cfg(target_arch = "wasm32")]
 pub use wasm_task::WebWorker;
+use web_time::Instant;
+#[cfg(target_arch = "wasm32")]
+use Function as AnyFunction;
+/// Standard: any [`NativeFunction`], Wasm: [`Function`] only
+#[cfg(not(target_arch = "wasm32"))]
+use NativeFunction as AnyFunction;
 
 /// This trait abstracts a function, which can be run independently
 pub trait Function: 'static + Default + Sized {
@@ -17,22 +58,188 @@ pub trait Function: 'static + Default + Sized {
     type Input: serde::Serialize + serde::de::DeserializeOwned + Send;
     /// Output type of function
     type Output: serde::Serialize + serde::de::DeserializeOwned + Send;
+    /// Name of the function, which tags its messages to a worker hosting several functions (see [`MultiFunction`]).
+    /// It has to be unique among the functions of that worker, and it is required for [`Backend::SharedWorker`].
+    const NAME: &'static str = "";
     /// Function to run
     fn call(&mut self, input: Self::Input) -> Self::Output;
 }
 
-/// This is a single task
-pub struct Task<F: Function> {
-    task_count: usize,
+/// Standard only: This trait abstracts a function, whose input and output are moved to the thread as they are.
+/// So they need not be serializable, e.g. an `Arc`, a file handle or a channel can be passed.
+/// Such a function cannot be run in a web worker or in a child process, use [`Task::new_native`] or [`TaskPool::new_native`].
+///
+/// Each [`Function`] is a [`NativeFunction`] as well.
+#[cfg(not(target_arch = "wasm32"))]
+pub trait NativeFunction: 'static + Default + Sized {
+    /// Input type of function
+    type Input: Send;
+    /// Output type of function
+    type Output: Send;
+    /// Function to run
+    fn call(&mut self, input: Self::Input) -> Self::Output;
+}
+#[cfg(not(target_arch = "wasm32"))]
+impl<F: Function> NativeFunction for F {
+    type Input = <F as Function>::Input;
+    type Output = <F as Function>::Output;
+    fn call(&mut self, input: Self::Input) -> Self::Output {
+        Function::call(self, input)
+    }
+}
+
+/// This selects where the jobs of a task are run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// Standard: Thread, Wasm: WebWorker
+    /// If the thread cannot be spawned, or if web workers are not available, [`Backend::Inline`] is used instead,
+    /// see [`Task::backend`].
+    #[default]
+    Worker,
+    /// Standard: Thread, same as [`Backend::Worker`]
+    /// Wasm: WebWorker hosting several functions, i.e. the script registers `WebWorker<MultiFunction<(A, B, ...)>>`
+    /// (see [`MultiFunction`]). So a single worker binary can be used for several tasks.
+    /// The function needs a name, see [`Function::NAME`]. If the worker cannot run the job, it is reported as crashed.
+    /// Falls back to [`Backend::Inline`] like [`Backend::Worker`].
+    SharedWorker,
+    /// Standard only: a child process, which is the current executable started again in worker mode.
+    /// The executable has to call [`ProcessWorker::serve_if_requested`] at the beginning of `main`.
+    /// If the child process crashes, it is restarted and the job it was running is reported as [`JobState::Crashed`]
+    /// (or as [`JobCrashed`] by [`Task::check_job`]). If it cannot be restarted, jobs crash until a restart succeeds.
+    /// If the child process cannot be spawned, [`Backend::Inline`] is used instead.
+    /// Messages are exchanged over stdin/stdout of the child process, so it has to print to stderr instead.
+    /// Messages larger than 1 GiB are not sent: such a job is reported as crashed, as is a job whose output cannot be decoded.
+    #[cfg(all(not(target_arch = "wasm32"), feature = "process"))]
+    Process,
+    /// No background at all: each job is run synchronously once its output is requested
+    /// (e.g. [`Task::check`] or [`TaskPool::progress`]).
+    /// The notifier (see [`Task::set_notifier`]) is invoked as soon as a job is enqueued, as its output is ready then.
+    /// This is deterministic, so it is useful for tests and for targets without threads.
+    Inline,
+    /// No background at all: each job is only run by [`Task::step`] or [`TaskPool::step`], one at a time.
+    /// This is used to test the order of jobs deterministically, see [`crate::Simulation`] for background tasks.
+    Manual,
+}
+
+/// The result of a job, as reported by a backend
+#[derive(Debug)]
+pub(crate) enum Outcome<Output> {
+    Done(Output),
+    Crashed,
+}
+
+/// What a backend hands back for a job
+enum Finished<Output> {
+    Output(Output),
+    /// Standard only: the output of a closure, see [`TaskPool::spawn`]
+    #[cfg(not(target_arch = "wasm32"))]
+    Ran(Box<dyn std::any::Any + Send>),
+}
+
+/// A job to run
+enum Job<Input> {
+    Input(Input),
+    /// Standard only: a closure, see [`TaskPool::spawn`]
+    #[cfg(not(target_arch = "wasm32"))]
+    Closure(spawn::BoxedClosure),
+}
+impl<Input> std::fmt::Debug for Job<Input> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Job::Input(_) => f.write_str("Input"),
+            #[cfg(not(target_arch = "wasm32"))]
+            Job::Closure(_) => f.write_str("Closure"),
+        }
+    }
+}
+
+enum TaskBackend<F: AnyFunction> {
     #[cfg(not(target_arch = "wasm32"))]
-    task: std_task::TaskStd<F>,
+    Thread(std_task::TaskStd<F>),
+    #[cfg(all(not(target_arch = "wasm32"), feature = "process"))]
+    Process(process_task::TaskProcess<F>),
+    #[cfg(target_arch = "wasm32")]
+    Web(wasm_task::TaskWasm<F>),
     #[cfg(target_arch = "wasm32")]
-    task: wasm_task::TaskWasm<F>,
+    SharedWeb(wasm_task::TaskWasmShared<F>),
+    Inline(inline_task::TaskInline<F>),
+}
+impl<F: AnyFunction> TaskBackend<F> {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn thread(task_name: &str, notifier: &crate::notifier::Notifier) -> Self {
+        match std_task::TaskStd::new(task_name, notifier.clone()) {
+            Ok(task) => TaskBackend::Thread(task),
+            Err(_) => TaskBackend::Inline(inline_task::TaskInline::new(notifier.clone())),
+        }
+    }
+    fn enqueue(&mut self, msg: F::Input) {
+        match self {
+            #[cfg(not(target_arch = "wasm32"))]
+            TaskBackend::Thread(task) => task.enqueue(msg),
+            #[cfg(all(not(target_arch = "wasm32"), feature = "process"))]
+            TaskBackend::Process(task) => task.enqueue(msg),
+            #[cfg(target_arch = "wasm32")]
+            TaskBackend::Web(task) => task.enqueue(msg),
+            #[cfg(target_arch = "wasm32")]
+            TaskBackend::SharedWeb(task) => task.enqueue(msg),
+            TaskBackend::Inline(task) => task.enqueue(msg),
+        }
+    }
+    fn check(&mut self) -> Option<Outcome<F::Output>> {
+        match self {
+            #[cfg(not(target_arch = "wasm32"))]
+            TaskBackend::Thread(task) => task.check(),
+            #[cfg(all(not(target_arch = "wasm32"), feature = "process"))]
+            TaskBackend::Process(task) => task.check(),
+            #[cfg(target_arch = "wasm32")]
+            TaskBackend::Web(task) => task.check().map(Outcome::Done),
+            #[cfg(target_arch = "wasm32")]
+            TaskBackend::SharedWeb(task) => task.check(),
+            TaskBackend::Inline(task) => task.check().map(Outcome::Done),
+        }
+    }
+    /// The backend actually used, which differs from the requested one if spawning the worker failed
+    fn backend(&self, requested: Backend) -> Backend {
+        match self {
+            TaskBackend::Inline(_) if requested != Backend::Manual => Backend::Inline,
+            _ => requested,
+        }
+    }
+    fn step(&mut self) -> bool {
+        match self {
+            TaskBackend::Inline(task) => task.step(),
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }
+    /// Check if messages are passed without serialization
+    #[cfg(all(not(target_arch = "wasm32"), feature = "serde-parity"))]
+    fn is_unserialized(&self) -> bool {
+        match self {
+            #[cfg(feature = "process")]
+            TaskBackend::Process(_) => false,
+            _ => true,
+        }
+    }
 }
-impl<F: Function> std::fmt::Debug for Task<F> {
+
+/// This is a single task
+pub struct Task<F: AnyFunction> {
+    task_count: usize,
+    backend: Backend,
+    notifier: crate::notifier::Notifier,
+    task: TaskBackend<F>,
+    #[cfg(all(not(target_arch = "wasm32"), feature = "serde-parity"))]
+    parity: Option<Coders<F>>,
+    /// See [`Task::set_parity_warn_size`]
+    #[cfg(all(not(target_arch = "wasm32"), feature = "serde-parity"))]
+    parity_warn_size: Option<usize>,
+}
+impl<F: AnyFunction> std::fmt::Debug for Task<F> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Task")
             .field("task_count", &self.task_count)
+            .field("backend", &self.backend)
             .field("task", &"[Cannot be shown]")
             .finish()
     }
@@ -42,25 +249,132 @@ impl<F: Function> Task<F> {
     /// Start a new task in the background. Enqueue jobs to run in the background.
     #[must_use]
     pub fn new(task_name: &str) -> Self {
+        Self::with_backend(task_name, Backend::Worker)
+    }
+    /// Start a new task in the background, using the given backend.
+    #[must_use]
+    pub fn with_backend(task_name: &str, backend: Backend) -> Self {
+        Self::with_codec::<Bincode>(task_name, backend)
+    }
+    /// Start a new task in the background, using the given backend and codec.
+    /// Note that the worker has to use the same codec, see [`crate::Codec`].
+    /// The codec is used by web workers and child processes, threads move messages without serialization
+    /// (unless the feature `serde-parity` is enabled, see [`Task::set_parity_warn_size`]).
+    #[must_use]
+    pub fn with_codec<C: Codec>(task_name: &str, backend: Backend) -> Self {
+        let notifier = crate::notifier::Notifier::default();
+        let task = match backend {
+            #[cfg(not(target_arch = "wasm32"))]
+            Backend::Worker | Backend::SharedWorker => TaskBackend::thread(task_name, &notifier),
+            #[cfg(target_arch = "wasm32")]
+            Backend::Worker | Backend::SharedWorker if !workers_available() => {
+                TaskBackend::Inline(inline_task::TaskInline::new(notifier.clone()))
+            }
+            #[cfg(target_arch = "wasm32")]
+            Backend::Worker => {
+                TaskBackend::Web(wasm_task::TaskWasm::new::<C>(task_name, notifier.clone()))
+            }
+            #[cfg(target_arch = "wasm32")]
+            Backend::SharedWorker => TaskBackend::SharedWeb(wasm_task::TaskWasmShared::new::<C>(
+                task_name,
+                notifier.clone(),
+            )),
+            #[cfg(all(not(target_arch = "wasm32"), feature = "process"))]
+            Backend::Process => {
+                match process_task::TaskProcess::new::<C>(task_name, notifier.clone()) {
+                    Ok(task) => TaskBackend::Process(task),
+                    Err(_) => TaskBackend::Inline(inline_task::TaskInline::new(notifier.clone())),
+                }
+            }
+            Backend::Inline => TaskBackend::Inline(inline_task::TaskInline::new(notifier.clone())),
+            Backend::Manual => {
+                TaskBackend::Inline(inline_task::TaskInline::manual(notifier.clone()))
+            }
+        };
         Self {
             task_count: 0,
-            task: {
-                #[cfg(not(target_arch = "wasm32"))]
-                {
-                    std_task::TaskStd::new(task_name)
-                }
-                #[cfg(target_arch = "wasm32")]
-                {
-                    wasm_task::TaskWasm::new(task_name)
-                }
-            },
+            backend: task.backend(backend),
+            #[cfg(all(not(target_arch = "wasm32"), feature = "serde-parity"))]
+            parity: task
+                .is_unserialized()
+                .then(|| (Coder::new::<C>(), Coder::new::<C>())),
+            #[cfg(all(not(target_arch = "wasm32"), feature = "serde-parity"))]
+            parity_warn_size: None,
+            task,
+            notifier,
+        }
+    }
+}
+#[cfg(not(target_arch = "wasm32"))]
+impl<F: NativeFunction> Task<F> {
+    /// Standard only: Start a new thread, which moves inputs and outputs without serialization.
+    /// If the thread cannot be spawned, the jobs are run inline (see [`Backend::Inline`]).
+    #[must_use]
+    pub fn new_native(task_name: &str) -> Self {
+        let notifier = crate::notifier::Notifier::default();
+        let task = TaskBackend::thread(task_name, &notifier);
+        Self {
+            task_count: 0,
+            backend: task.backend(Backend::Worker),
+            task,
+            #[cfg(feature = "serde-parity")]
+            parity: None,
+            #[cfg(feature = "serde-parity")]
+            parity_warn_size: None,
+            notifier,
         }
     }
+}
+impl<F: AnyFunction> Task<F> {
+    /// Set a callback which is invoked (from the background) whenever a job is done.
+    /// Typically, this is used to request a repaint of the GUI or to wake a [`std::task::Waker`].
+    pub fn set_notifier(&mut self, notifier: impl Fn() + Send + Sync + 'static) {
+        self.notifier.set(Some(std::sync::Arc::new(notifier)));
+    }
+    /// Remove the callback set by [`Task::set_notifier`]
+    pub fn clear_notifier(&mut self) {
+        self.notifier.set(None);
+    }
+    /// The backend running the jobs. This is [`Backend::Inline`] if the requested worker could not be spawned.
+    #[must_use]
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+    /// Warn (using the `log` crate) about each input and output which is larger than `bytes` once serialized.
+    /// `None` disables the warning, which is the default.
+    ///
+    /// Only available with the feature `serde-parity`: on native, every message is then serialized and deserialized
+    /// using the same codec as the web worker bridge (see [`crate::Codec`]).
+    /// Messages of native functions (see [`Task::new_native`]) are not serialized, so they are never reported.
+    #[cfg(all(not(target_arch = "wasm32"), feature = "serde-parity"))]
+    pub fn set_parity_warn_size(&mut self, bytes: Option<usize>) {
+        self.parity_warn_size = bytes;
+    }
     /// Enqueue a new job.
     pub fn enqueue(&mut self, msg: F::Input) {
         self.task_count += 1;
+        #[cfg(all(not(target_arch = "wasm32"), feature = "serde-parity"))]
+        let msg = match &self.parity {
+            Some((coder, _)) => {
+                crate::parity::round_trip(coder, msg, "Input", self.parity_warn_size)
+            }
+            None => msg,
+        };
         self.task.enqueue(msg);
     }
+    fn start(&mut self, job: Job<F::Input>) {
+        match job {
+            Job::Input(input) => self.enqueue(input),
+            #[cfg(not(target_arch = "wasm32"))]
+            Job::Closure(closure) => self.enqueue_closure(closure),
+        }
+    }
+
+    /// Run the next job, if the task is stepped manually (see [`Backend::Manual`]).
+    /// Returns false if there was no job to run.
+    pub fn step(&mut self) -> bool {
+        self.task.step()
+    }
 
     /// Check if some job is ongoing
     #[must_use]
@@ -68,94 +382,496 @@ impl<F: Function> Task<F> {
         self.task_count > 0
     }
 
-    /// Check if the job is done (using First In, First Out)
+    /// Check if the job is done (using First In, First Out).
+    ///
+    /// Panics if the job crashed (see [`JobState::Crashed`]), use [`Task::check_job`] to handle crashes.
     #[must_use]
     pub fn check(&mut self) -> Option<F::Output> {
-        let output = self.task.check();
-        if output.is_some() {
+        self.check_job()
+            .map(|output| output.expect("Job crashed, use Task::check_job to handle crashes"))
+    }
+    /// Check if the job is done (using First In, First Out), like [`Task::check`].
+    /// A job which crashed (see [`JobState::Crashed`]) is reported as well, so outputs stay paired with their inputs.
+    #[must_use]
+    pub fn check_job(&mut self) -> Option<Result<F::Output, JobCrashed>> {
+        match self.poll()? {
+            Outcome::Done(output) => Some(Ok(output)),
+            Outcome::Crashed => Some(Err(JobCrashed)),
+        }
+    }
+
+    fn poll(&mut self) -> Option<Outcome<F::Output>> {
+        let outcome = self.task.check();
+        if outcome.is_some() {
             self.task_count -= 1;
         }
-        output
+        #[cfg(all(not(target_arch = "wasm32"), feature = "serde-parity"))]
+        let outcome = match (outcome, &self.parity) {
+            (Some(Outcome::Done(output)), Some((_, coder))) => Some(Outcome::Done(
+                crate::parity::round_trip(coder, output, "Output", self.parity_warn_size),
+            )),
+            (outcome, _) => outcome,
+        };
+        outcome
     }
 }
 
+/// A task of a pool, together with the job it is running
+#[derive(Debug)]
+struct PoolTask<F: AnyFunction> {
+    task: Task<F>,
+    /// The running job, and when it was started
+    job: Option<(Ticket, Instant)>,
+    /// Request of the running interactive job, which is not yet answered
+    request: Option<F::Output>,
+}
+
 /// Task Pool which can run several jobs in parallel.
 #[derive(Debug)]
-pub struct TaskPool<F: Function> {
-    tasks: Vec<(Option<Ticket>, Task<F>)>,
-    to_start: VecDeque<(Ticket, F::Input)>,
-    done: HashMap<Ticket, F::Output>,
+pub struct TaskPool<F: AnyFunction> {
+    tasks: Vec<PoolTask<F>>,
+    to_start: VecDeque<(Ticket, Job<F::Input>)>,
+    done: HashMap<Ticket, Outcome<F::Output>>,
     ticket_generator: ticket::TicketGenerator,
+    /// Index of the next ticket for [`TaskPool::drain_completed_in_order`]
+    next_in_order: usize,
+    retention: Retention,
+    /// Index of done jobs, in the order they finished, together with the time they finished
+    finished: VecDeque<(usize, Instant)>,
+    /// Jobs whose output was evicted
+    expired: retention::Expired,
+    /// Running jobs whose output is dropped
+    forgotten: HashSet<Ticket>,
+    /// Jobs are only started while fewer outputs are done, see [`Pipeline::then`]
+    max_done: usize,
+    /// Wakes [`TaskPool::wait_for`], shared by the notifiers of all tasks
+    #[cfg(not(target_arch = "wasm32"))]
+    wake: std::sync::Arc<crate::notifier::Wake>,
+    /// Tells requests of interactive jobs from outputs, set once an interactive job is enqueued (see [`Interactive`])
+    is_request: Option<fn(&F::Output) -> bool>,
+    #[cfg(not(target_arch = "wasm32"))]
+    spawned: spawn::Spawned,
 }
 impl<F: Function> TaskPool<F> {
     /// Create a new TaskPool.
     #[must_use]
     pub fn new(task_name: &str, task_count: usize) -> Self {
+        Self::with_backend(task_name, task_count, Backend::Worker)
+    }
+    /// Create a new TaskPool, using the given backend for all tasks.
+    #[must_use]
+    pub fn with_backend(task_name: &str, task_count: usize, backend: Backend) -> Self {
+        Self::with_codec::<Bincode>(task_name, task_count, backend)
+    }
+    /// Create a new TaskPool, using the given backend and codec for all tasks.
+    #[must_use]
+    pub fn with_codec<C: Codec>(task_name: &str, task_count: usize, backend: Backend) -> Self {
+        Self::from_tasks(
+            (0..task_count)
+                .map(|_| Task::with_codec::<C>(task_name, backend))
+                .collect(),
+        )
+    }
+}
+#[cfg(not(target_arch = "wasm32"))]
+impl<F: NativeFunction> TaskPool<F> {
+    /// Standard only: Create a new TaskPool, whose threads move inputs and outputs without serialization,
+    /// see [`Task::new_native`].
+    #[must_use]
+    pub fn new_native(task_name: &str, task_count: usize) -> Self {
+        Self::from_tasks(
+            (0..task_count)
+                .map(|_| Task::new_native(task_name))
+                .collect(),
+        )
+    }
+}
+impl<F: AnyFunction> TaskPool<F> {
+    fn from_tasks(tasks: Vec<Task<F>>) -> Self {
+        #[cfg(not(target_arch = "wasm32"))]
+        let wake = std::sync::Arc::<crate::notifier::Wake>::default();
+        #[cfg(not(target_arch = "wasm32"))]
+        for task in &tasks {
+            task.notifier.set_wake(wake.clone());
+        }
         Self {
-            tasks: (0..task_count)
-                .map(|_| (None, Task::new(task_name)))
+            tasks: tasks
+                .into_iter()
+                .map(|task| PoolTask {
+                    task,
+                    job: None,
+                    request: None,
+                })
                 .collect(),
             to_start: Default::default(),
             done: Default::default(),
             ticket_generator: Default::default(),
+            next_in_order: 0,
+            retention: Default::default(),
+            finished: Default::default(),
+            expired: Default::default(),
+            forgotten: Default::default(),
+            max_done: usize::MAX,
+            #[cfg(not(target_arch = "wasm32"))]
+            wake,
+            is_request: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            spawned: Default::default(),
+        }
+    }
+    /// Set a callback which is invoked whenever any job of this pool is done, see [`Task::set_notifier`].
+    pub fn set_notifier(&mut self, notifier: impl Fn() + Send + Sync + 'static) {
+        self.set_callback(Some(std::sync::Arc::new(notifier)));
+    }
+    /// Remove the callback set by [`TaskPool::set_notifier`]
+    pub fn clear_notifier(&mut self) {
+        self.set_callback(None);
+    }
+    /// Warn about large messages of all tasks, see [`Task::set_parity_warn_size`]
+    #[cfg(all(not(target_arch = "wasm32"), feature = "serde-parity"))]
+    pub fn set_parity_warn_size(&mut self, bytes: Option<usize>) {
+        for PoolTask { task, .. } in &mut self.tasks {
+            task.set_parity_warn_size(bytes);
+        }
+    }
+    /// The backend running the jobs, see [`Task::backend`].
+    /// This is [`Backend::Inline`] if any worker of the pool could not be spawned.
+    #[must_use]
+    pub fn backend(&self) -> Backend {
+        self.tasks
+            .iter()
+            .map(|PoolTask { task, .. }| task.backend)
+            .find(|backend| *backend == Backend::Inline)
+            .or_else(|| self.tasks.first().map(|PoolTask { task, .. }| task.backend))
+            .unwrap_or_default()
+    }
+    pub(crate) fn set_callback(&mut self, callback: Option<crate::notifier::Callback>) {
+        for PoolTask { task, .. } in &self.tasks {
+            task.notifier.set(callback.clone());
         }
     }
     /// Progress all enqueued jobs.
     pub fn progress(&mut self) {
-        for (ongoing, task) in self.tasks.iter_mut() {
-            if ongoing.is_some() {
-                if let Some(output) = task.check() {
-                    let ticket = std::mem::take(ongoing).unwrap();
-                    let r = self.done.insert(ticket, output);
-                    if r.is_some() {
-                        panic!("Ticket is already in list of done jobs")
-                    }
+        let mut finished = Vec::new();
+        #[cfg(not(target_arch = "wasm32"))]
+        let mut ran = Vec::new();
+        for PoolTask { task, job, request } in self.tasks.iter_mut() {
+            #[cfg(not(target_arch = "wasm32"))]
+            if job.is_some() {
+                if let Some(output) = task.poll_ran() {
+                    let (ticket, _) = std::mem::take(job).unwrap();
+                    ran.push((ticket, output));
+                }
+            }
+            if job.is_some() {
+                if let Some(output) = task.poll() {
+                    let output = match output {
+                        Outcome::Done(output)
+                            if self
+                                .is_request
+                                .is_some_and(|is_request| is_request(&output)) =>
+                        {
+                            // the task keeps running this job, until the request is answered
+                            *request = Some(output);
+                            continue;
+                        }
+                        output => output,
+                    };
+                    let (ticket, _) = std::mem::take(job).unwrap();
+                    finished.push((ticket, output));
                 }
             }
-            if ongoing.is_none() {
-                if let Some((ticket, input)) = self.to_start.pop_front() {
-                    *ongoing = Some(ticket);
-                    task.enqueue(input);
+            if job.is_none() && self.done.len() + finished.len() < self.max_done {
+                if let Some((ticket, next)) = self.to_start.pop_front() {
+                    *job = Some((ticket, Instant::now()));
+                    task.start(next);
                 }
             }
         }
+        for (ticket, output) in finished {
+            self.finish(ticket, output);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        for (ticket, output) in ran {
+            self.finish_spawned(ticket, output);
+        }
+        self.evict();
+    }
+    /// Run the next running job, if the pool is stepped manually (see [`Backend::Manual`]).
+    /// Jobs are run in the order they were started. Returns false if there was no job to run.
+    pub fn step(&mut self) -> bool {
+        self.progress();
+        let stepped = self
+            .tasks
+            .iter_mut()
+            .filter(|PoolTask { job, .. }| job.is_some())
+            .min_by_key(|PoolTask { job, .. }| job.as_ref().map(|(ticket, _)| ticket.index()))
+            .is_some_and(|PoolTask { task, .. }| task.step());
+        self.progress();
+        stepped
     }
     /// Enqueue a new job. Use the returned ticket to check later if the job is done.
     #[must_use]
     pub fn enqueue(&mut self, input: F::Input) -> Ticket {
         let (ticket, ticket_internal) = self.ticket_generator.next();
-        self.to_start.push_back((ticket_internal, input));
+        self.to_start
+            .push_back((ticket_internal, Job::Input(input)));
         self.progress();
         ticket
     }
-    /// Check if a job is done.
+    /// Check if a job is done. If so, its output is taken.
+    ///
+    /// A job whose output was already taken (e.g. by [`TaskPool::drain_completed`]) is [`JobState::Unknown`].
     #[must_use]
     pub fn check(&mut self, ticket: Ticket) -> JobState<F::Output> {
         self.progress();
-        if let Some(output) = self.done.remove(&ticket) {
-            JobState::Done(output)
-        } else {
-            JobState::Ongoing(ticket)
+        match self.done.remove(&ticket) {
+            Some(Outcome::Done(output)) => JobState::Done(output),
+            Some(Outcome::Crashed) => JobState::Crashed,
+            None if self.expired.remove(&ticket) => JobState::Expired,
+            None => self.status_of(&ticket).ongoing(ticket),
+        }
+    }
+    /// Get the status of a job, without taking its output.
+    #[must_use]
+    pub fn status(&mut self, ticket: &Ticket) -> JobStatus {
+        self.progress();
+        self.status_of(ticket)
+    }
+    fn status_of(&self, ticket: &Ticket) -> JobStatus {
+        if let Some(outcome) = self.done.get(ticket) {
+            return match outcome {
+                Outcome::Done(_) => JobStatus::Done,
+                Outcome::Crashed => JobStatus::Crashed,
+            };
+        }
+        if self.expired.contains(ticket) {
+            return JobStatus::Expired;
         }
+        if let Some(position) = self.to_start.iter().position(|(t, _)| t == ticket) {
+            return JobStatus::Queued { position };
+        }
+        self.tasks
+            .iter()
+            .enumerate()
+            .find_map(|(worker, PoolTask { job, .. })| match job {
+                Some((t, since)) if t == ticket => Some(JobStatus::Running {
+                    worker,
+                    since: *since,
+                }),
+                _ => None,
+            })
+            .unwrap_or(JobStatus::Unknown)
+    }
+
+    /// Take all finished jobs, ordered by their tickets.
+    /// Crashed jobs are kept, so they are still reported by [`TaskPool::check`].
+    pub fn drain_completed(&mut self) -> impl Iterator<Item = (Ticket, F::Output)> {
+        self.progress();
+        let mut tickets = self
+            .done
+            .iter()
+            .filter(|(_, outcome)| matches!(outcome, Outcome::Done(_)))
+            .map(|(ticket, _)| ticket.index())
+            .collect::<Vec<_>>();
+        tickets.sort_unstable();
+        let mut completed = Vec::with_capacity(tickets.len());
+        for i in tickets {
+            if let Some((ticket, Outcome::Done(output))) = self.done.remove_entry(&Ticket::nth(i)) {
+                completed.push((ticket, output));
+            }
+        }
+        completed.into_iter()
+    }
+    /// Take finished jobs strictly in enqueue order: this stops at the first job which is not yet done.
+    /// Jobs which were already taken (e.g. by [`TaskPool::check`]) or which crashed are skipped.
+    pub fn drain_completed_in_order(&mut self) -> impl Iterator<Item = (Ticket, F::Output)> {
+        self.progress();
+        let mut completed = Vec::new();
+        while self.next_in_order < self.ticket_generator.count() {
+            let ticket = Ticket::nth(self.next_in_order);
+            match self.done.remove(&ticket) {
+                Some(Outcome::Done(output)) => completed.push((ticket, output)),
+                Some(Outcome::Crashed) => {
+                    self.done.insert(ticket, Outcome::Crashed);
+                }
+                None if self.is_pending(&ticket) => break,
+                None => {}
+            }
+            self.next_in_order += 1;
+        }
+        completed.into_iter()
+    }
+    /// Number of jobs which are waiting or running.
+    /// Unlike counting the drained outputs, this is correct for jobs which crashed or were forgotten as well.
+    #[must_use]
+    pub fn ongoing_jobs(&self) -> usize {
+        self.to_start.len()
+            + self
+                .tasks
+                .iter()
+                .filter(|PoolTask { job, .. }| job.is_some())
+                .count()
+    }
+    /// Check if a job is waiting or running
+    fn is_pending(&self, ticket: &Ticket) -> bool {
+        matches!(
+            self.status_of(ticket),
+            JobStatus::Queued { .. } | JobStatus::Running { .. }
+        )
     }
 
     /// Wait for a job to finish
+    ///
+    /// Panics if the job crashed (see [`Backend::Process`]), expired (see [`Retention`]) or is unknown.
     #[must_use]
-    pub fn wait_for(&mut self, ticket: Ticket) -> F::Output {
-        match self.check(ticket) {
-            JobState::Ongoing(ticket) => self.wait_for(ticket),
-            JobState::Done(output) => output,
+    pub fn wait_for(&mut self, mut ticket: Ticket) -> F::Output {
+        loop {
+            let seen = self.notified();
+            match self.check(ticket) {
+                JobState::Queued { ticket: t, .. } | JobState::Running { ticket: t, .. } => {
+                    ticket = t;
+                    self.wait_notified(seen);
+                }
+                JobState::Done(output) => return output,
+                JobState::Crashed => panic!("Job crashed"),
+                JobState::Expired => panic!("Job expired"),
+                _ => panic!("Unknown job, its output was already taken"),
+            }
         }
     }
+    /// Number of notifications so far, see [`TaskPool::wait_notified`]
+    fn notified(&self) -> u64 {
+        #[cfg(not(target_arch = "wasm32"))]
+        return self.wake.notified();
+        #[cfg(target_arch = "wasm32")]
+        0
+    }
+    /// Standard: block until some task notified since `seen`, Wasm: return, as the main thread cannot block
+    fn wait_notified(&self, _seen: u64) {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.wake.wait(_seen);
+    }
 }
 
+/// The worker crashed while running a job, see [`Task::check_job`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JobCrashed;
+impl std::fmt::Display for JobCrashed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Worker crashed while running the job")
+    }
+}
+impl std::error::Error for JobCrashed {}
+
 /// This is the current state of a job.
 #[derive(Debug)]
-pub enum JobState<Output> {
-    /// The job is not yet done. Use this ticket to check later.
-    Ongoing(Ticket),
+#[non_exhaustive]
+pub enum JobState<Output, Handle = Ticket> {
+    /// The job waits for a free task, `position` jobs are ahead of it. Use this ticket to check later.
+    Queued {
+        /// Use this ticket to check later
+        ticket: Handle,
+        /// Number of jobs ahead of this job
+        position: usize,
+    },
+    /// The job is run by a task. Use this ticket to check later.
+    Running {
+        /// Use this ticket to check later
+        ticket: Handle,
+        /// Index of the task running the job
+        worker: usize,
+        /// When the job was handed to the task
+        since: Instant,
+    },
     /// The job is done, yielding output.
     Done(Output),
+    /// The worker crashed while running the job: the function panicked on a thread, or see [`Backend::Process`].
+    /// A thread runs the next job with a new function, i.e. [`Default`] again.
+    Crashed,
+    /// The output was not taken in time, see [`Retention`].
+    Expired,
+    /// The ticket is unknown, e.g. the output was already taken
+    Unknown(Handle),
+    /// The job is not yet done. Use this ticket to check later.
+    #[deprecated(note = "never returned: a job which is not yet done is `Queued` or `Running`")]
+    Ongoing(Handle),
+}
+impl<Output, Handle> JobState<Output, Handle> {
+    pub(crate) fn map<O, H>(
+        self,
+        output: impl FnOnce(Output) -> O,
+        ticket: impl FnOnce(Handle) -> H,
+    ) -> JobState<O, H> {
+        match self {
+            JobState::Queued {
+                ticket: t,
+                position,
+            } => JobState::Queued {
+                ticket: ticket(t),
+                position,
+            },
+            JobState::Running {
+                ticket: t,
+                worker,
+                since,
+            } => JobState::Running {
+                ticket: ticket(t),
+                worker,
+                since,
+            },
+            JobState::Done(o) => JobState::Done(output(o)),
+            JobState::Crashed => JobState::Crashed,
+            JobState::Expired => JobState::Expired,
+            JobState::Unknown(t) => JobState::Unknown(ticket(t)),
+            #[allow(deprecated)]
+            JobState::Ongoing(t) => JobState::Ongoing(ticket(t)),
+        }
+    }
+}
+
+/// This is the current status of a job, see [`TaskPool::status`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(variant_size_differences)] // Wasm: an instant is smaller than on standard
+#[non_exhaustive]
+pub enum JobStatus {
+    /// The job waits for a free task
+    Queued {
+        /// Number of jobs ahead of this job
+        position: usize,
+    },
+    /// The job is run by a task
+    Running {
+        /// Index of the task running the job
+        worker: usize,
+        /// When the job was handed to the task
+        since: Instant,
+    },
+    /// The job is done, its output is ready to be taken
+    Done,
+    /// The worker crashed while running the job: the function panicked on a thread, or see [`Backend::Process`].
+    /// A thread runs the next job with a new function, i.e. [`Default`] again.
+    Crashed,
+    /// The output was not taken in time, see [`Retention`].
+    Expired,
+    /// The job is unknown, e.g. its output was already taken
+    Unknown,
+}
+impl JobStatus {
+    /// The state of a job whose output is not stored
+    fn ongoing<Output>(self, ticket: Ticket) -> JobState<Output> {
+        match self {
+            JobStatus::Queued { position } => JobState::Queued { ticket, position },
+            JobStatus::Running { worker, since } => JobState::Running {
+                ticket,
+                worker,
+                since,
+            },
+            JobStatus::Done | JobStatus::Crashed | JobStatus::Expired | JobStatus::Unknown => {
+                JobState::Unknown(ticket)
+            }
+        }
+    }
 }
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -187,3 +903,170 @@ fn test_task_pool_std() {
         assert_eq!(i, v);
     }
 }
+
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn test_task_notifier_std() {
+    #[derive(Default)]
+    struct DummyFunction;
+    impl Function for DummyFunction {
+        type Input = u32;
+        type Output = u64;
+
+        fn call(&mut self, input: Self::Input) -> Self::Output {
+            input as _
+        }
+    }
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let mut task = Task::<DummyFunction>::new("dummy_thread");
+    let sender = std::sync::Mutex::new(sender);
+    task.set_notifier(move || sender.lock().unwrap().send(()).unwrap());
+    let n = 5;
+    for i in 0..n {
+        task.enqueue(i);
+    }
+    // the notifier is invoked after the output is sent
+    for _ in 0..n {
+        receiver
+            .recv_timeout(std::time::Duration::from_secs(10))
+            .expect("Notifier was not invoked");
+    }
+    for i in 0..n {
+        assert_eq!(task.check(), Some(i as u64));
+    }
+    assert!(receiver.try_recv().is_err());
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn test_task_pool_native() {
+    use std::sync::{mpsc::Sender, Arc};
+    #[derive(Default)]
+    struct SumFunction;
+    impl NativeFunction for SumFunction {
+        type Input = (Arc<Vec<u64>>, Sender<usize>);
+        type Output = Arc<Vec<u64>>;
+
+        fn call(&mut self, (data, sender): Self::Input) -> Self::Output {
+            sender.send(data.len()).unwrap();
+            Arc::new(vec![data.iter().sum()])
+        }
+    }
+
+    let mut task_pool = TaskPool::<SumFunction>::new_native("sum_thread", 2);
+    assert_eq!(task_pool.backend(), Backend::Worker);
+    let data = Arc::new((0..100).collect::<Vec<u64>>());
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let tickets = (0..4)
+        .map(|_| task_pool.enqueue((data.clone(), sender.clone())))
+        .collect::<Vec<_>>();
+    for ticket in tickets {
+        assert_eq!(*task_pool.wait_for(ticket), vec![4950]);
+        assert_eq!(receiver.recv().unwrap(), 100);
+    }
+    // the data was shared, not copied
+    assert_eq!(Arc::strong_count(&data), 1);
+}
+
+#[test]
+fn test_task_pool_drain() {
+    #[derive(Default)]
+    struct DummyFunction;
+    impl Function for DummyFunction {
+        type Input = u32;
+        type Output = u64;
+
+        fn call(&mut self, input: Self::Input) -> Self::Output {
+            input as _
+        }
+    }
+
+    let mut task_pool = TaskPool::<DummyFunction>::with_backend("dummy", 2, Backend::Inline);
+    assert_eq!(task_pool.backend(), Backend::Inline);
+    // the inline tasks run the jobs whenever the pool progresses, e.g. when enqueueing
+    let mut tickets = (0..5).map(|i| task_pool.enqueue(i)).collect::<Vec<_>>();
+    // in order: the job taken by check is skipped
+    assert!(matches!(
+        task_pool.check(tickets.remove(1)),
+        JobState::Done(1)
+    ));
+    let drained = task_pool.drain_completed_in_order().collect::<Vec<_>>();
+    assert_eq!(
+        drained.iter().map(|(_, v)| *v).collect::<Vec<_>>(),
+        vec![0, 2, 3, 4]
+    );
+    assert_eq!(drained[0].0, tickets[0]);
+
+    let ticket = task_pool.enqueue(5);
+    let mut drained = task_pool.drain_completed().collect::<Vec<_>>();
+    assert_eq!(drained.pop(), Some((Ticket::nth(ticket.index()), 5)));
+    assert!(drained.is_empty());
+    assert_eq!(task_pool.drain_completed_in_order().count(), 0);
+    // the output was taken
+    assert_eq!(task_pool.status(&ticket), JobStatus::Unknown);
+    assert!(matches!(task_pool.check(ticket), JobState::Unknown(_)));
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn test_task_pool_status() {
+    use std::sync::mpsc::{channel, Receiver};
+    #[derive(Default)]
+    struct BlockingFunction;
+    impl NativeFunction for BlockingFunction {
+        type Input = Receiver<u32>;
+        type Output = u32;
+
+        fn call(&mut self, input: Self::Input) -> Self::Output {
+            input.recv().unwrap()
+        }
+    }
+
+    let mut task_pool = TaskPool::<BlockingFunction>::new_native("blocking_thread", 1);
+    let (senders, tickets): (Vec<_>, Vec<_>) = (0..3)
+        .map(|_| {
+            let (sender, receiver) = channel();
+            (sender, task_pool.enqueue(receiver))
+        })
+        .unzip();
+    assert!(matches!(
+        task_pool.status(&tickets[0]),
+        JobStatus::Running { worker: 0, .. }
+    ));
+    assert_eq!(
+        task_pool.status(&tickets[2]),
+        JobStatus::Queued { position: 1 }
+    );
+    let mut tickets = tickets.into_iter();
+    let ticket = tickets.next().unwrap();
+    let ticket = match task_pool.check(ticket) {
+        JobState::Running { ticket, since, .. } => {
+            assert!(since <= Instant::now());
+            ticket
+        }
+        state => panic!("Unexpected state {state:?}"),
+    };
+    let (notify, notified) = channel();
+    let notify = std::sync::Mutex::new(notify);
+    task_pool.set_notifier(move || {
+        let _ = notify.lock().unwrap().send(());
+    });
+    senders[0].send(7).unwrap();
+    notified
+        .recv_timeout(std::time::Duration::from_secs(10))
+        .expect("Timeout");
+    assert_eq!(task_pool.status(&ticket), JobStatus::Done);
+    assert_eq!(task_pool.wait_for(ticket), 7);
+
+    let ticket = tickets.next().unwrap();
+    assert!(matches!(
+        task_pool.status(&ticket),
+        JobStatus::Running { .. }
+    ));
+    for (sender, i) in senders.iter().zip(0..).skip(1) {
+        sender.send(i).unwrap();
+    }
+    assert_eq!(task_pool.wait_for(ticket), 1);
+    assert_eq!(task_pool.wait_for(tickets.next().unwrap()), 2);
+}
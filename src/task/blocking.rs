@@ -0,0 +1,185 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use super::{Interaction, InteractiveFunction};
+
+/// Standard only: This trait abstracts a function, which can ask its owner for more data while running,
+/// blocking until the owner answers (see [`Requester::request`]).
+///
+/// It is run as the [`InteractiveFunction`] [`Blocking`], e.g. `TaskPool<Interactive<Blocking<F>>>`,
+/// so the owner answers requests the same way (see [`super::TaskPool::respond`]).
+/// The job is run on a thread of its own next to the worker, which waits for the next request or the output.
+/// Wasm: a web worker cannot block, use [`InteractiveFunction`] instead.
+pub trait BlockingFunction: 'static + Default + Sized {
+    /// Input type of function
+    type Input: serde::Serialize + serde::de::DeserializeOwned + Send;
+    /// Request type, which is sent to the owner
+    type Request: serde::Serialize + serde::de::DeserializeOwned + Send;
+    /// Reply type, which is sent back by the owner
+    type Reply: serde::Serialize + serde::de::DeserializeOwned + Send;
+    /// Output type of function
+    type Output: serde::Serialize + serde::de::DeserializeOwned + Send;
+    /// Function to run, which may ask the owner using the requester
+    fn call(&mut self, input: Self::Input, requester: &mut Requester<Self>) -> Self::Output;
+}
+
+/// Standard only: Sends the requests of a running [`BlockingFunction`] to the owner
+pub struct Requester<F: BlockingFunction> {
+    steps: Sender<Interaction<F::Request, F::Output>>,
+    replies: Receiver<F::Reply>,
+}
+impl<F: BlockingFunction> std::fmt::Debug for Requester<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Requester").finish_non_exhaustive()
+    }
+}
+impl<F: BlockingFunction> Requester<F> {
+    /// Send a request to the owner, and wait for its reply
+    pub fn request(&mut self, request: F::Request) -> F::Reply {
+        let reply = self
+            .steps
+            .send(Interaction::Request(request))
+            .ok()
+            .and_then(|()| self.replies.recv().ok());
+        // the owner is gone, so the job is abandoned without reporting a panic
+        reply.unwrap_or_else(|| std::panic::resume_unwind(Box::new("Owner is gone")))
+    }
+}
+
+/// The thread running the jobs of a [`BlockingFunction`]
+struct Runner<F: BlockingFunction> {
+    inputs: Sender<F::Input>,
+    replies: Sender<F::Reply>,
+    steps: Receiver<Interaction<F::Request, F::Output>>,
+}
+impl<F: BlockingFunction> Runner<F> {
+    fn spawn() -> Self {
+        let (inputs, input_receiver) = channel();
+        let (replies, reply_receiver) = channel();
+        let (step_sender, steps) = channel();
+        std::thread::Builder::new()
+            .name("task_simple_blocking".into())
+            .spawn(move || {
+                let mut function = F::default();
+                let mut requester = Requester {
+                    steps: step_sender.clone(),
+                    replies: reply_receiver,
+                };
+                while let Ok(input) = input_receiver.recv() {
+                    let output = function.call(input, &mut requester);
+                    if step_sender.send(Interaction::Done(output)).is_err() {
+                        return;
+                    }
+                }
+            })
+            .expect("Cannot spawn thread for blocking function");
+        Self {
+            inputs,
+            replies,
+            steps,
+        }
+    }
+}
+
+/// This wraps a [`BlockingFunction`] into an [`InteractiveFunction`], see [`super::Interactive`]
+pub struct Blocking<F: BlockingFunction> {
+    runner: Option<Runner<F>>,
+}
+impl<F: BlockingFunction> Default for Blocking<F> {
+    fn default() -> Self {
+        Self { runner: None }
+    }
+}
+impl<F: BlockingFunction> std::fmt::Debug for Blocking<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Blocking")
+            .field("running", &self.runner.is_some())
+            .finish()
+    }
+}
+impl<F: BlockingFunction> Blocking<F> {
+    /// Wait for the next request or the output of the running job.
+    /// If the job panicked, the panic is passed on, and the next job is run on a new thread.
+    fn next(&mut self) -> Interaction<F::Request, F::Output> {
+        let step = self
+            .runner
+            .as_ref()
+            .and_then(|runner| runner.steps.recv().ok());
+        step.unwrap_or_else(|| {
+            self.runner = None;
+            panic!("Blocking function panicked");
+        })
+    }
+}
+impl<F: BlockingFunction> InteractiveFunction for Blocking<F> {
+    type Input = F::Input;
+    type Request = F::Request;
+    type Reply = F::Reply;
+    type Output = F::Output;
+    fn call(&mut self, input: Self::Input) -> Interaction<Self::Request, Self::Output> {
+        let runner = self.runner.get_or_insert_with(Runner::spawn);
+        // if the runner is gone, this is reported by `next`
+        let _ = runner.inputs.send(input);
+        self.next()
+    }
+    fn reply(&mut self, reply: Self::Reply) -> Interaction<Self::Request, Self::Output> {
+        if let Some(runner) = &self.runner {
+            let _ = runner.replies.send(reply);
+        }
+        self.next()
+    }
+}
+
+#[test]
+fn test_blocking() {
+    use super::{Backend, Interactive, JobState, TaskPool};
+    /// Sums up numbers, asking the owner for the next one until it gets zero
+    #[derive(Default)]
+    struct Sum;
+    impl BlockingFunction for Sum {
+        type Input = u64;
+        type Request = u64;
+        type Reply = u64;
+        type Output = u64;
+        fn call(&mut self, input: Self::Input, requester: &mut Requester<Self>) -> Self::Output {
+            let mut sum = input;
+            loop {
+                match requester.request(sum) {
+                    0 => return sum,
+                    reply => sum += reply,
+                }
+            }
+        }
+    }
+
+    for backend in [Backend::Worker, Backend::Inline] {
+        let mut task_pool =
+            TaskPool::<Interactive<Blocking<Sum>>>::with_backend("blocking_sum", 2, backend);
+        let mut tickets = [1, 10].map(|input| Some(task_pool.enqueue_interactive(input)));
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        let mut outputs = [None, None];
+        while tickets.iter().any(Option::is_some) {
+            assert!(std::time::Instant::now() < deadline, "Timeout");
+            let replies = task_pool
+                .pending_requests()
+                .map(|(ticket, request)| {
+                    let i = tickets.iter().position(|t| t.as_ref() == Some(ticket));
+                    (i.unwrap(), if *request < 3 { 1 } else { 0 })
+                })
+                .collect::<Vec<_>>();
+            for (i, reply) in replies {
+                task_pool.respond(tickets[i].as_ref().unwrap(), reply);
+            }
+            for (ticket, output) in tickets.iter_mut().zip(&mut outputs) {
+                if let Some(t) = ticket.take() {
+                    match task_pool.check_interactive(t) {
+                        JobState::Done(done) => *output = Some(done),
+                        JobState::Queued { ticket: t, .. }
+                        | JobState::Running { ticket: t, .. } => *ticket = Some(t),
+                        _ => panic!("Job failed"),
+                    }
+                }
+            }
+        }
+        assert_eq!(outputs, [Some(3), Some(10)]);
+    }
+}
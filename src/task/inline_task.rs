@@ -0,0 +1,178 @@
+use std::collections::VecDeque;
+
+#[cfg(not(target_arch = "wasm32"))]
+use super::spawn::BoxedClosure;
+use super::{AnyFunction, Finished, Job};
+use crate::notifier::Notifier;
+
+/// Runs the function synchronously, once the output is requested.
+/// If stepped manually (see [`super::Backend::Manual`]), a job only runs on request.
+pub(super) struct TaskInline<F: AnyFunction> {
+    function: F,
+    input: VecDeque<Job<F::Input>>,
+    /// Outputs of jobs run manually, `None` if jobs run once their output is requested
+    output: Option<VecDeque<Finished<F::Output>>>,
+    /// The next finished job, if it was run but not yet taken
+    peeked: Option<Finished<F::Output>>,
+    notifier: Notifier,
+}
+impl<F: AnyFunction> TaskInline<F> {
+    pub(super) fn new(notifier: Notifier) -> Self {
+        Self {
+            function: F::default(),
+            input: Default::default(),
+            output: None,
+            peeked: None,
+            notifier,
+        }
+    }
+    pub(super) fn manual(notifier: Notifier) -> Self {
+        Self {
+            output: Some(Default::default()),
+            ..Self::new(notifier)
+        }
+    }
+    pub(super) fn enqueue(&mut self, msg: F::Input) {
+        self.enqueue_job(Job::Input(msg));
+    }
+    /// Queue a closure, which is run like a job, see [`super::TaskPool::spawn`]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(super) fn enqueue_closure(&mut self, closure: BoxedClosure) {
+        self.enqueue_job(Job::Closure(closure));
+    }
+    /// The output is available as soon as it is requested, so the notifier is invoked right away
+    fn enqueue_job(&mut self, job: Job<F::Input>) {
+        self.input.push_back(job);
+        if self.output.is_none() {
+            self.notifier.notify();
+        }
+    }
+    /// The output of the next finished job, unless it is a closure
+    pub(super) fn check(&mut self) -> Option<F::Output> {
+        match self.next()? {
+            Finished::Output(output) => Some(output),
+            #[cfg(not(target_arch = "wasm32"))]
+            ran => {
+                self.peeked = Some(ran);
+                None
+            }
+        }
+    }
+    /// The output of the next finished job, if it is a closure
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(super) fn check_ran(&mut self) -> Option<Box<dyn std::any::Any + Send>> {
+        match self.next()? {
+            Finished::Ran(output) => Some(output),
+            output => {
+                self.peeked = Some(output);
+                None
+            }
+        }
+    }
+    fn next(&mut self) -> Option<Finished<F::Output>> {
+        if let Some(finished) = self.peeked.take() {
+            return Some(finished);
+        }
+        if let Some(output) = &mut self.output {
+            return output.pop_front();
+        }
+        let job = self.input.pop_front()?;
+        Some(self.run(job))
+    }
+    fn run(&mut self, job: Job<F::Input>) -> Finished<F::Output> {
+        match job {
+            Job::Input(input) => Finished::Output(self.function.call(input)),
+            #[cfg(not(target_arch = "wasm32"))]
+            Job::Closure(closure) => Finished::Ran(closure()),
+        }
+    }
+    /// Run the next job, if stepped manually. Returns false if there was no job to run.
+    pub(super) fn step(&mut self) -> bool {
+        if self.output.is_none() {
+            return false;
+        }
+        let Some(job) = self.input.pop_front() else {
+            return false;
+        };
+        let finished = self.run(job);
+        if let Some(output) = &mut self.output {
+            output.push_back(finished);
+        }
+        self.notifier.notify();
+        true
+    }
+}
+
+#[test]
+fn test_task_pool_inline() {
+    use super::{Backend, Function, JobState, TaskPool};
+    #[derive(Default)]
+    struct DummyFunction;
+    impl Function for DummyFunction {
+        type Input = u32;
+        type Output = u64;
+        fn call(&mut self, input: Self::Input) -> Self::Output {
+            (input + 1) as _
+        }
+    }
+
+    let mut task_pool = TaskPool::<DummyFunction>::with_backend("dummy", 3, Backend::Inline);
+    let notified = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let counter = notified.clone();
+    task_pool.set_notifier(move || {
+        counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    });
+    let n = 10;
+    let tickets = (0..n).map(|i| task_pool.enqueue(i)).collect::<Vec<_>>();
+    // no waiting necessary: each check progresses the pool, which runs all started jobs
+    for (i, ticket) in tickets.into_iter().enumerate() {
+        let i = (i + 1) as u64;
+        assert!(matches!(task_pool.check(ticket), JobState::Done(v) if v == i));
+    }
+    // each job was announced once it was handed to a task
+    assert_eq!(
+        notified.load(std::sync::atomic::Ordering::SeqCst),
+        n as usize
+    );
+}
+
+#[test]
+fn test_task_pool_manual() {
+    use super::{Backend, Function, JobState, JobStatus, TaskPool};
+    #[derive(Default)]
+    struct DummyFunction;
+    impl Function for DummyFunction {
+        type Input = u32;
+        type Output = u64;
+        fn call(&mut self, input: Self::Input) -> Self::Output {
+            (input + 1) as _
+        }
+    }
+
+    let mut task_pool = TaskPool::<DummyFunction>::with_backend("dummy", 2, Backend::Manual);
+    assert_eq!(task_pool.backend(), Backend::Manual);
+    let [first, second, third] = [0, 1, 2].map(|i| task_pool.enqueue(i));
+    // nothing runs unless a step is requested
+    assert!(matches!(
+        task_pool.status(&first),
+        JobStatus::Running { .. }
+    ));
+    assert!(matches!(task_pool.status(&third), JobStatus::Queued { .. }));
+    assert_eq!(task_pool.ongoing_jobs(), 3);
+    assert!(task_pool.step());
+    assert!(matches!(task_pool.check(first), JobState::Done(1)));
+    assert!(matches!(
+        task_pool.status(&second),
+        JobStatus::Running { .. }
+    ));
+    assert!(matches!(
+        task_pool.status(&third),
+        JobStatus::Running { .. }
+    ));
+    assert!(task_pool.step());
+    assert!(task_pool.step());
+    assert!(!task_pool.step());
+    assert_eq!(task_pool.ongoing_jobs(), 0);
+    assert!(matches!(task_pool.check(second), JobState::Done(2)));
+    assert!(matches!(task_pool.check(third), JobState::Done(3)));
+}
@@ -0,0 +1,326 @@
+use std::collections::VecDeque;
+
+use super::{Backend, Function, JobCrashed, JobState, Task, TaskPool, Ticket};
+
+/// The result of a step of an [`InteractiveFunction`]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Interaction<Request, Output> {
+    /// The job needs more data: the owner has to answer using [`TaskPool::respond`]
+    Request(Request),
+    /// The job is done, yielding output.
+    Done(Output),
+}
+
+/// This trait abstracts a function, which can ask its owner for more data while running.
+/// For example, on the web only the main thread can access some browser APIs.
+///
+/// Since a web worker cannot block, the function is split into steps (Standard: see [`super::BlockingFunction`] to block instead):
+/// [`InteractiveFunction::call`] starts the job, and each reply of the owner continues it via [`InteractiveFunction::reply`].
+/// The task keeps running the same job until it is done.
+/// To run it, use an [`InteractiveTask`] or a [`TaskPool`] of [`Interactive`]
+/// (Wasm: the web worker is `WebWorker<Interactive<F>>`).
+pub trait InteractiveFunction: 'static + Default + Sized {
+    /// Input type of function
+    type Input: serde::Serialize + serde::de::DeserializeOwned + Send;
+    /// Request type, which is sent to the owner
+    type Request: serde::Serialize + serde::de::DeserializeOwned + Send;
+    /// Reply type, which is sent back by the owner
+    type Reply: serde::Serialize + serde::de::DeserializeOwned + Send;
+    /// Output type of function
+    type Output: serde::Serialize + serde::de::DeserializeOwned + Send;
+    /// Start a job
+    fn call(&mut self, input: Self::Input) -> Interaction<Self::Request, Self::Output>;
+    /// Continue the job, using the reply to the last request
+    fn reply(&mut self, reply: Self::Reply) -> Interaction<Self::Request, Self::Output>;
+}
+
+/// A message sent to the worker of an [`InteractiveFunction`]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum InteractiveStep<Input, Reply> {
+    /// Start a new job
+    Input(Input),
+    /// Continue the current job
+    Reply(Reply),
+}
+
+/// This wraps an [`InteractiveFunction`] into a [`Function`], each call being a single step
+#[derive(Debug, Default)]
+pub struct Interactive<F>(F);
+impl<F: InteractiveFunction> Function for Interactive<F> {
+    type Input = InteractiveStep<F::Input, F::Reply>;
+    type Output = Interaction<F::Request, F::Output>;
+    fn call(&mut self, input: Self::Input) -> Self::Output {
+        match input {
+            InteractiveStep::Input(input) => self.0.call(input),
+            InteractiveStep::Reply(reply) => self.0.reply(reply),
+        }
+    }
+}
+
+/// Tells requests of running jobs from outputs. Only [`Interactive`] sends requests, so it is the only implementation.
+trait Requests: Function {
+    fn is_request(output: &Self::Output) -> bool;
+}
+impl<F: InteractiveFunction> Requests for Interactive<F> {
+    fn is_request(output: &Self::Output) -> bool {
+        matches!(output, Interaction::Request(_))
+    }
+}
+
+/// A single task running interactive jobs, see [`InteractiveFunction`].
+/// Jobs are run one at a time: jobs enqueued while a job is running are started once it is done.
+pub struct InteractiveTask<F: InteractiveFunction> {
+    task: Task<Interactive<F>>,
+    /// Jobs waiting for the running job to be done
+    waiting: VecDeque<F::Input>,
+    running: bool,
+    /// Request of the running job, which is not yet answered
+    request: Option<F::Request>,
+    /// Jobs which are done, but not yet checked
+    done: VecDeque<Result<F::Output, JobCrashed>>,
+}
+impl<F: InteractiveFunction> std::fmt::Debug for InteractiveTask<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InteractiveTask")
+            .field("task", &self.task)
+            .field("waiting", &self.waiting.len())
+            .field("running", &self.running)
+            .field("request", &self.request.is_some())
+            .finish()
+    }
+}
+impl<F: InteractiveFunction> InteractiveTask<F> {
+    /// Start a new task in the background, see [`Task::new`]
+    #[must_use]
+    pub fn new(task_name: &str) -> Self {
+        Self::with_backend(task_name, Backend::Worker)
+    }
+    /// Start a new task in the background, using the given backend, see [`Task::with_backend`]
+    #[must_use]
+    pub fn with_backend(task_name: &str, backend: Backend) -> Self {
+        Self {
+            task: Task::with_backend(task_name, backend),
+            waiting: Default::default(),
+            running: false,
+            request: None,
+            done: Default::default(),
+        }
+    }
+    /// Set a callback which is invoked (from the background) whenever a job is done or sends a request,
+    /// see [`Task::set_notifier`]
+    pub fn set_notifier(&mut self, notifier: impl Fn() + Send + Sync + 'static) {
+        self.task.set_notifier(notifier);
+    }
+    /// Remove the callback set by [`InteractiveTask::set_notifier`]
+    pub fn clear_notifier(&mut self) {
+        self.task.clear_notifier();
+    }
+    /// Enqueue a new job
+    pub fn enqueue(&mut self, input: F::Input) {
+        self.waiting.push_back(input);
+        self.progress();
+    }
+    /// Check if some job is running or waiting
+    #[must_use]
+    pub fn task_is_ongoing(&self) -> bool {
+        self.running || !self.waiting.is_empty()
+    }
+    /// The request of the running job, which is not yet answered
+    pub fn pending_request(&mut self) -> Option<&F::Request> {
+        self.progress();
+        self.request.as_ref()
+    }
+    /// Answer the pending request of the running job, so the job continues.
+    ///
+    /// Panics if the job has no pending request.
+    pub fn respond(&mut self, reply: F::Reply) {
+        self.request.take().expect("Job has no pending request");
+        self.task.enqueue(InteractiveStep::Reply(reply));
+    }
+    /// Check if the next job is done (using First In, First Out).
+    ///
+    /// Panics if the job crashed (see [`Backend::Process`]), use [`InteractiveTask::check_job`] to handle crashes.
+    #[must_use]
+    pub fn check(&mut self) -> Option<F::Output> {
+        self.check_job().map(|output| {
+            output.expect("Job crashed, use InteractiveTask::check_job to handle crashes")
+        })
+    }
+    /// Check if the next job is done (using First In, First Out), like [`Task::check_job`]
+    #[must_use]
+    pub fn check_job(&mut self) -> Option<Result<F::Output, JobCrashed>> {
+        self.progress();
+        self.done.pop_front()
+    }
+    fn progress(&mut self) {
+        loop {
+            if !self.running {
+                let Some(input) = self.waiting.pop_front() else {
+                    return;
+                };
+                self.running = true;
+                self.task.enqueue(InteractiveStep::Input(input));
+            }
+            if self.request.is_some() {
+                return;
+            }
+            match self.task.check_job() {
+                None => return,
+                Some(Ok(Interaction::Request(request))) => self.request = Some(request),
+                Some(Ok(Interaction::Done(output))) => {
+                    self.running = false;
+                    self.done.push_back(Ok(output));
+                }
+                Some(Err(crashed)) => {
+                    self.running = false;
+                    self.done.push_back(Err(crashed));
+                }
+            }
+        }
+    }
+}
+
+/// In a pool of interactive jobs, requests are not handed out as output, instead the task waits for [`TaskPool::respond`].
+/// So outputs are always [`Interaction::Done`], use [`TaskPool::check_interactive`] to unwrap them.
+impl<F: InteractiveFunction> TaskPool<Interactive<F>> {
+    /// Create a new TaskPool for interactive jobs, same as [`TaskPool::new`]
+    #[must_use]
+    pub fn new_interactive(task_name: &str, task_count: usize) -> Self {
+        Self::new(task_name, task_count)
+    }
+    /// Enqueue a new interactive job. Use the returned ticket to answer requests and to check if the job is done.
+    #[must_use]
+    pub fn enqueue_interactive(&mut self, input: F::Input) -> Ticket {
+        self.is_request = Some(<Interactive<F> as Requests>::is_request);
+        self.enqueue(InteractiveStep::Input(input))
+    }
+    /// Check if an interactive job is done, like [`TaskPool::check`]. If so, its output is taken.
+    #[must_use]
+    pub fn check_interactive(&mut self, ticket: Ticket) -> JobState<F::Output> {
+        self.check(ticket).map(
+            |output| match output {
+                Interaction::Done(output) => output,
+                Interaction::Request(_) => unreachable!("Requests are not handed out as output"),
+            },
+            |ticket| ticket,
+        )
+    }
+    /// All requests of running jobs, which are not yet answered
+    pub fn pending_requests(&mut self) -> impl Iterator<Item = (&Ticket, &F::Request)> {
+        self.progress();
+        self.tasks
+            .iter()
+            .filter_map(|task| match (&task.job, &task.request) {
+                (Some((ticket, _)), Some(Interaction::Request(request))) => Some((ticket, request)),
+                _ => None,
+            })
+    }
+    /// Answer the pending request of a job, so the job continues.
+    ///
+    /// Panics if the job has no pending request.
+    pub fn respond(&mut self, ticket: &Ticket, reply: F::Reply) {
+        let index = self
+            .tasks
+            .iter()
+            .position(|task| {
+                task.request.is_some() && matches!(&task.job, Some((t, _)) if t == ticket)
+            })
+            .expect("Job has no pending request");
+        self.tasks[index].request = None;
+        self.tasks[index]
+            .task
+            .enqueue(InteractiveStep::Reply(reply));
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn test_interactive_std() {
+    use super::JobState;
+    /// Sums up numbers, asking the owner for the next one until it gets zero
+    #[derive(Default)]
+    struct Sum(u64);
+    impl InteractiveFunction for Sum {
+        type Input = u64;
+        type Request = u64;
+        type Reply = u64;
+        type Output = u64;
+        fn call(&mut self, input: Self::Input) -> Interaction<Self::Request, Self::Output> {
+            self.0 = input;
+            Interaction::Request(self.0)
+        }
+        fn reply(&mut self, reply: Self::Reply) -> Interaction<Self::Request, Self::Output> {
+            if reply == 0 {
+                Interaction::Done(self.0)
+            } else {
+                self.0 += reply;
+                Interaction::Request(self.0)
+            }
+        }
+    }
+
+    let mut task_pool = TaskPool::<Interactive<Sum>>::new_interactive("sum_thread", 2);
+    let mut tickets = (0..3)
+        .map(|i| Some(task_pool.enqueue_interactive(i * 100)))
+        .collect::<Vec<_>>();
+    while tickets.iter().any(Option::is_some) {
+        let replies = task_pool
+            .pending_requests()
+            .map(|(ticket, request)| {
+                let i = tickets
+                    .iter()
+                    .position(|t| t.as_ref() == Some(ticket))
+                    .unwrap();
+                // each job gets one additional number
+                let reply = if *request == i as u64 * 100 {
+                    i as u64
+                } else {
+                    0
+                };
+                (i, reply)
+            })
+            .collect::<Vec<_>>();
+        for (i, reply) in replies {
+            task_pool.respond(tickets[i].as_ref().unwrap(), reply);
+        }
+        for (i, ticket) in tickets.iter_mut().enumerate() {
+            if let Some(t) = ticket.take() {
+                match task_pool.check_interactive(t) {
+                    JobState::Queued { ticket: t, .. } | JobState::Running { ticket: t, .. } => {
+                        *ticket = Some(t)
+                    }
+                    JobState::Done(output) => assert_eq!(output, i as u64 * 101),
+                    _ => panic!("Job failed"),
+                }
+            }
+        }
+    }
+
+    // a pool created by `TaskPool::with_backend` handles requests as well
+    let mut task_pool = TaskPool::<Interactive<Sum>>::with_backend("sum", 1, Backend::Inline);
+    let ticket = task_pool.enqueue_interactive(5);
+    let (pending, request) = task_pool.pending_requests().next().unwrap();
+    assert_eq!((pending, *request), (&ticket, 5));
+    task_pool.respond(&ticket, 0);
+    assert!(matches!(
+        task_pool.check_interactive(ticket),
+        JobState::Done(5)
+    ));
+
+    // a single task runs one job at a time
+    let mut task = InteractiveTask::<Sum>::new("sum_thread");
+    task.enqueue(1);
+    task.enqueue(10);
+    let mut outputs = Vec::new();
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    while outputs.len() < 2 {
+        assert!(std::time::Instant::now() < deadline, "Timeout");
+        if let Some(request) = task.pending_request() {
+            let reply = if *request < 3 { 1 } else { 0 };
+            task.respond(reply);
+        }
+        outputs.extend(task.check());
+    }
+    assert_eq!(outputs, [3, 10]);
+    assert!(!task.task_is_ongoing());
+}
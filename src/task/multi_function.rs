@@ -0,0 +1,204 @@
+use super::Function;
+use crate::codec::{Bincode, Codec, CodecError};
+
+/// A message to or from a worker hosting several functions, see [`MultiFunction`].
+/// It consists of the name of the function (see [`Function::NAME`]) and the input/output encoded by the codec.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Envelope {
+    function: String,
+    payload: Vec<u8>,
+}
+impl Envelope {
+    #[cfg(any(test, target_arch = "wasm32"))]
+    pub(crate) fn new<F: Function>(payload: Vec<u8>) -> Self {
+        Self {
+            function: F::NAME.into(),
+            payload,
+        }
+    }
+    #[cfg(any(test, target_arch = "wasm32"))]
+    pub(crate) fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+}
+
+/// A set of functions hosted by a single worker: a tuple of [`Function`]s, e.g. `(Doubling, FileSize)`
+pub trait FunctionSet: 'static + Default {
+    /// The names of the functions, see [`Function::NAME`]
+    const NAMES: &'static [&'static str];
+    /// Run the function with the given name.
+    /// Returns `None` if the function is not part of this set.
+    fn call<C: Codec>(
+        &mut self,
+        function: &str,
+        payload: &[u8],
+    ) -> Option<Result<Vec<u8>, CodecError>>;
+}
+impl FunctionSet for () {
+    const NAMES: &'static [&'static str] = &[];
+    fn call<C: Codec>(
+        &mut self,
+        _function: &str,
+        _payload: &[u8],
+    ) -> Option<Result<Vec<u8>, CodecError>> {
+        None
+    }
+}
+macro_rules! impl_function_set {
+    ($($f:ident $i:tt),+) => {
+        impl<$($f: Function),+> FunctionSet for ($($f,)+) {
+            const NAMES: &'static [&'static str] = &[$($f::NAME),+];
+            fn call<C: Codec>(
+                &mut self,
+                function: &str,
+                payload: &[u8],
+            ) -> Option<Result<Vec<u8>, CodecError>> {
+                $(
+                    if function == $f::NAME {
+                        return Some(C::decode::<<$f as Function>::Input>(payload).and_then(|input| {
+                            C::encode(&Function::call(&mut self.$i, input))
+                        }));
+                    }
+                )+
+                None
+            }
+        }
+    };
+}
+impl_function_set!(F0 0);
+impl_function_set!(F0 0, F1 1);
+impl_function_set!(F0 0, F1 1, F2 2);
+impl_function_set!(F0 0, F1 1, F2 2, F3 3);
+impl_function_set!(F0 0, F1 1, F2 2, F3 3, F4 4);
+impl_function_set!(F0 0, F1 1, F2 2, F3 3, F4 4, F5 5);
+impl_function_set!(F0 0, F1 1, F2 2, F3 3, F4 4, F5 5, F6 6);
+impl_function_set!(F0 0, F1 1, F2 2, F3 3, F4 4, F5 5, F6 6, F7 7);
+
+/// Check that the names are not empty and unique
+const fn valid_names(names: &[&str]) -> bool {
+    let mut i = 0;
+    while i < names.len() {
+        if names[i].is_empty() {
+            return false;
+        }
+        let mut j = 0;
+        while j < i {
+            if str_eq(names[i], names[j]) {
+                return false;
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    true
+}
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// This is a function hosting several functions, so a single worker binary can run all of them.
+/// Each message is dispatched to the function named in its [`Envelope`], see [`Function::NAME`].
+/// The names have to be unique, which is checked at compile time.
+/// If the function is unknown or its message cannot be decoded, the error is returned, so the job crashed.
+///
+/// The payload is encoded using the codec `C`, which has to be the codec of the tasks, see [`super::Task::with_codec`].
+/// The envelope itself is always framed by [`Bincode`], so the payload is not encoded twice.
+///
+/// Wasm: The worker binary registers `WebWorker<MultiFunction<(A, B, ...), C>>` using the default encoding
+/// (e.g. via [`crate::worker_main`]), and tasks use [`super::Backend::SharedWorker`].
+pub struct MultiFunction<Functions, C = Bincode> {
+    functions: Functions,
+    _codec: std::marker::PhantomData<C>,
+}
+impl<Functions: std::fmt::Debug, C> std::fmt::Debug for MultiFunction<Functions, C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MultiFunction")
+            .field("functions", &self.functions)
+            .finish()
+    }
+}
+impl<Functions: FunctionSet, C> MultiFunction<Functions, C> {
+    const VALID_NAMES: () = assert!(
+        valid_names(Functions::NAMES),
+        "The functions of a MultiFunction need unique names, see Function::NAME"
+    );
+}
+impl<Functions: FunctionSet, C> Default for MultiFunction<Functions, C> {
+    fn default() -> Self {
+        let () = Self::VALID_NAMES;
+        Self {
+            functions: Default::default(),
+            _codec: Default::default(),
+        }
+    }
+}
+impl<Functions: FunctionSet, C: Codec> Function for MultiFunction<Functions, C> {
+    type Input = Envelope;
+    /// The output, or the error if the function failed
+    type Output = Result<Envelope, String>;
+    fn call(&mut self, input: Self::Input) -> Self::Output {
+        let Envelope { function, payload } = input;
+        match self.functions.call::<C>(&function, &payload) {
+            Some(Ok(payload)) => Ok(Envelope { function, payload }),
+            Some(Err(e)) => Err(format!("Function {function} failed: {e}")),
+            None => Err(format!("Function {function} is not hosted by this worker")),
+        }
+    }
+}
+
+#[test]
+fn test_multi_function() {
+    #[derive(Default, Debug)]
+    struct Double;
+    impl Function for Double {
+        type Input = u32;
+        type Output = u64;
+        const NAME: &'static str = "Double";
+        fn call(&mut self, input: Self::Input) -> Self::Output {
+            input as u64 * 2
+        }
+    }
+    #[derive(Default, Debug)]
+    struct Format;
+    impl Function for Format {
+        type Input = u64;
+        type Output = String;
+        const NAME: &'static str = "Format";
+        fn call(&mut self, input: Self::Input) -> Self::Output {
+            input.to_string()
+        }
+    }
+    #[derive(Default, Debug)]
+    struct Unnamed;
+    impl Function for Unnamed {
+        type Input = ();
+        type Output = ();
+        fn call(&mut self, _input: Self::Input) -> Self::Output {}
+    }
+
+    let mut worker = MultiFunction::<(Double, Format)>::default();
+    let output = worker.call(Envelope::new::<Format>(Bincode::encode(&7u64).unwrap()));
+    let output: String = Bincode::decode(output.unwrap().payload()).unwrap();
+    assert_eq!(output, "7");
+    let output = worker.call(Envelope::new::<Double>(Bincode::encode(&7u32).unwrap()));
+    let output: u64 = Bincode::decode(output.unwrap().payload()).unwrap();
+    assert_eq!(output, 14);
+    // unknown functions and bad payloads are errors, the worker keeps running
+    assert!(worker.call(Envelope::new::<Unnamed>(Vec::new())).is_err());
+    assert!(worker.call(Envelope::new::<Double>(Vec::new())).is_err());
+
+    assert!(valid_names(<(Double, Format)>::NAMES));
+    assert!(!valid_names(<(Double, Unnamed)>::NAMES));
+    assert!(!valid_names(<(Double, Double)>::NAMES));
+}
@@ -0,0 +1,523 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use super::{ticket, Function, JobState, JobStatus, Outcome, TaskPool, Ticket};
+use crate::notifier::{Callback, Notifier};
+
+/// Standard: stages are progressed by the threads which finished a job, so they have to be [`Send`]
+#[cfg(not(target_arch = "wasm32"))]
+trait SendIfNative: Send {}
+#[cfg(not(target_arch = "wasm32"))]
+impl<T: Send> SendIfNative for T {}
+/// Wasm: stages are only progressed by the owner
+#[cfg(target_arch = "wasm32")]
+trait SendIfNative {}
+#[cfg(target_arch = "wasm32")]
+impl<T> SendIfNative for T {}
+
+/// A single stage of a pipeline: jobs go in together with their (original) ticket, outputs come out
+trait Stage: SendIfNative {
+    type Input;
+    type Output;
+    /// Check if the stage accepts another job
+    fn has_capacity(&self) -> bool;
+    /// Hand a job to the stage
+    fn push(&mut self, ticket: Ticket, input: Self::Input);
+    /// Progress all jobs of this stage (and all stages before)
+    fn progress(&mut self);
+    /// Take some finished (or crashed) job
+    fn pop_done(&mut self) -> Option<(Ticket, Outcome<Self::Output>)>;
+    /// Stop starting jobs of the last stage while `limit` of its outputs are not taken
+    fn limit_outputs(&mut self, limit: usize);
+    /// The status of a job in this stage (or some stage before), if the job is there.
+    /// A job waiting for the next stage is queued.
+    fn status(&self, ticket: &Ticket) -> Option<JobStatus>;
+    /// Set the notification callback of all tasks
+    fn set_callback(&mut self, callback: Option<Callback>);
+}
+
+/// A task pool as pipeline stage
+struct PoolStage<F: Function> {
+    pool: TaskPool<F>,
+    buffer_size: usize,
+    /// Tickets of the pool, together with the original ticket, in enqueue order
+    tickets: VecDeque<(Ticket, Ticket)>,
+}
+impl<F: Function + Send> Stage for PoolStage<F> {
+    type Input = F::Input;
+    type Output = F::Output;
+
+    fn has_capacity(&self) -> bool {
+        self.pool.to_start.len() < self.buffer_size
+    }
+    fn push(&mut self, ticket: Ticket, input: Self::Input) {
+        let pool_ticket = self.pool.enqueue(input);
+        self.tickets.push_back((pool_ticket, ticket));
+    }
+    fn progress(&mut self) {
+        self.pool.progress();
+    }
+    fn pop_done(&mut self) -> Option<(Ticket, Outcome<Self::Output>)> {
+        let position = self
+            .tickets
+            .iter()
+            .position(|(pool_ticket, _)| self.pool.done.contains_key(pool_ticket))?;
+        let (pool_ticket, ticket) = self.tickets.remove(position)?;
+        let output = self.pool.done.remove(&pool_ticket)?;
+        Some((ticket, output))
+    }
+    fn limit_outputs(&mut self, limit: usize) {
+        self.pool.max_done = limit;
+    }
+    fn status(&self, ticket: &Ticket) -> Option<JobStatus> {
+        let index = self.tickets.iter().position(|(_, t)| t == ticket)?;
+        let is_done = |(pool_ticket, _): &(Ticket, Ticket)| {
+            matches!(
+                self.pool.status_of(pool_ticket),
+                JobStatus::Done | JobStatus::Crashed
+            )
+        };
+        Some(if is_done(&self.tickets[index]) {
+            JobStatus::Queued {
+                position: self.tickets.range(..index).filter(|t| is_done(t)).count(),
+            }
+        } else {
+            self.pool.status_of(&self.tickets[index].0)
+        })
+    }
+    fn set_callback(&mut self, callback: Option<Callback>) {
+        self.pool.set_callback(callback);
+    }
+}
+
+/// Two connected stages
+struct Chain<I, M, O> {
+    first: Box<dyn Stage<Input = I, Output = M>>,
+    second: Box<dyn Stage<Input = M, Output = O>>,
+    /// Jobs which crashed in the first stage
+    crashed: VecDeque<Ticket>,
+}
+impl<I, M, O> Stage for Chain<I, M, O> {
+    type Input = I;
+    type Output = O;
+
+    fn has_capacity(&self) -> bool {
+        self.first.has_capacity()
+    }
+    fn push(&mut self, ticket: Ticket, input: Self::Input) {
+        self.first.push(ticket, input);
+    }
+    fn progress(&mut self) {
+        self.first.progress();
+        // jobs of the second stage may be done, which makes room for outputs of the first stage
+        self.second.progress();
+        let mut moved = false;
+        while self.second.has_capacity() {
+            match self.first.pop_done() {
+                Some((ticket, Outcome::Done(intermediate))) => {
+                    self.second.push(ticket, intermediate)
+                }
+                Some((ticket, Outcome::Crashed)) => self.crashed.push_back(ticket),
+                None => break,
+            }
+            moved = true;
+        }
+        if moved {
+            // the first stage starts the jobs held back by its output limit
+            self.first.progress();
+        }
+    }
+    fn pop_done(&mut self) -> Option<(Ticket, Outcome<Self::Output>)> {
+        match self.crashed.pop_front() {
+            Some(ticket) => Some((ticket, Outcome::Crashed)),
+            None => self.second.pop_done(),
+        }
+    }
+    fn limit_outputs(&mut self, limit: usize) {
+        self.second.limit_outputs(limit);
+    }
+    fn status(&self, ticket: &Ticket) -> Option<JobStatus> {
+        self.first
+            .status(ticket)
+            .or_else(|| self.crashed.contains(ticket).then_some(JobStatus::Crashed))
+            .or_else(|| self.second.status(ticket))
+    }
+    fn set_callback(&mut self, callback: Option<Callback>) {
+        self.first.set_callback(callback.clone());
+        self.second.set_callback(callback);
+    }
+}
+
+/// The stages of a pipeline, shared with the callbacks of their tasks
+struct Forwarder<I, O> {
+    stages: Mutex<Box<dyn Stage<Input = I, Output = O>>>,
+    /// Set whenever a job is done, so the stages are progressed again by whoever holds them
+    pending: AtomicBool,
+    /// See [`Pipeline::set_notifier`]
+    notifier: Notifier,
+}
+impl<I: 'static, O: 'static> Forwarder<I, O> {
+    /// Share the stages, whose tasks notify the forwarder
+    fn new(stages: Box<dyn Stage<Input = I, Output = O>>, notifier: Notifier) -> Arc<Self> {
+        let forwarder = Arc::new(Self {
+            stages: Mutex::new(stages),
+            pending: AtomicBool::new(false),
+            notifier,
+        });
+        #[cfg(not(target_arch = "wasm32"))]
+        let callback = {
+            let forwarder = Arc::downgrade(&forwarder);
+            move || {
+                if let Some(forwarder) = forwarder.upgrade() {
+                    forwarder.pending.store(true, Ordering::SeqCst);
+                    forwarder.forward();
+                    forwarder.notifier.notify();
+                }
+            }
+        };
+        #[cfg(target_arch = "wasm32")]
+        let callback = {
+            let notifier = forwarder.notifier.clone();
+            move || notifier.notify()
+        };
+        forwarder
+            .stages
+            .lock()
+            .unwrap()
+            .set_callback(Some(Arc::new(callback)));
+        forwarder
+    }
+    /// Take the stages back, which is only possible while no job was enqueued
+    fn into_stages(self: Arc<Self>) -> Box<dyn Stage<Input = I, Output = O>> {
+        Arc::into_inner(self)
+            .expect("Stages are not shared before jobs are enqueued")
+            .stages
+            .into_inner()
+            .unwrap()
+    }
+    /// Progress the stages while jobs were done. If another thread holds the stages, it progresses them instead.
+    fn forward(&self) {
+        while self.pending.load(Ordering::SeqCst) {
+            let Ok(mut stages) = self.stages.try_lock() else {
+                return;
+            };
+            self.progress(stages.as_mut());
+        }
+    }
+    fn progress(&self, stages: &mut dyn Stage<Input = I, Output = O>) {
+        while self.pending.swap(false, Ordering::SeqCst) {
+            stages.progress();
+        }
+    }
+}
+
+/// Several task pools connected one after another.
+/// The output of each stage is the input of the next stage.
+/// Only the output of the last stage is handed back, using the ticket returned by [`Pipeline::enqueue`].
+///
+/// Standard: outputs are handed to the next stage by the thread which finished the job,
+/// so the stages keep running even if the pipeline is not used.
+/// Wasm: outputs are handed to the next stage only while the pipeline is used
+/// (e.g. [`Pipeline::progress`], [`Pipeline::check`]).
+/// Use [`Pipeline::set_notifier`] to be woken up whenever a stage finished a job, and progress the pipeline then.
+pub struct Pipeline<Input, Output> {
+    forwarder: Arc<Forwarder<Input, Output>>,
+    stage_count: usize,
+    done: HashMap<Ticket, Outcome<Output>>,
+    ticket_generator: ticket::TicketGenerator,
+}
+impl<Input, Output> std::fmt::Debug for Pipeline<Input, Output> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pipeline")
+            .field("stage_count", &self.stage_count)
+            .field("done", &self.done.len())
+            .field("ticket_generator", &self.ticket_generator)
+            .finish()
+    }
+}
+impl<Input: 'static, Output: 'static> Pipeline<Input, Output> {
+    /// Create a new pipeline, consisting of a single stage.
+    /// Jobs enqueued to the first stage are not limited.
+    #[must_use]
+    pub fn new<F: Function<Input = Input, Output = Output> + Send>(
+        task_name: &str,
+        task_count: usize,
+    ) -> Self {
+        Self {
+            forwarder: Forwarder::new(
+                Box::new(PoolStage {
+                    pool: TaskPool::<F>::new(task_name, task_count),
+                    buffer_size: usize::MAX,
+                    tickets: Default::default(),
+                }),
+                Notifier::default(),
+            ),
+            stage_count: 1,
+            done: Default::default(),
+            ticket_generator: Default::default(),
+        }
+    }
+    /// Append another stage, which runs on `task_count` tasks.
+    /// At most `buffer_size` outputs of the previous stage wait for a free task of this stage.
+    /// Once another `buffer_size` outputs wait in the previous stage, it does not start further jobs.
+    ///
+    /// Panics if `buffer_size` is zero or if jobs were enqueued already.
+    #[must_use]
+    pub fn then<F: Function<Input = Output> + Send>(
+        self,
+        task_name: &str,
+        task_count: usize,
+        buffer_size: usize,
+    ) -> Pipeline<Input, F::Output> {
+        let Self {
+            forwarder,
+            stage_count,
+            done: _,
+            ticket_generator,
+        } = self;
+        assert!(buffer_size > 0, "Buffer size must not be zero");
+        assert!(
+            ticket_generator.count() == 0,
+            "Stages have to be added before enqueueing jobs"
+        );
+        let notifier = forwarder.notifier.clone();
+        let mut stages = forwarder.into_stages();
+        stages.limit_outputs(buffer_size);
+        let forwarder = Forwarder::new(
+            Box::new(Chain {
+                first: stages,
+                second: Box::new(PoolStage {
+                    pool: TaskPool::<F>::new(task_name, task_count),
+                    buffer_size,
+                    tickets: Default::default(),
+                }),
+                crashed: Default::default(),
+            }),
+            notifier,
+        );
+        Pipeline {
+            forwarder,
+            stage_count: stage_count + 1,
+            done: Default::default(),
+            ticket_generator,
+        }
+    }
+    /// Set a callback which is invoked whenever a job of any stage is done.
+    /// Wasm: outputs are only handed to the next stage by [`Pipeline::progress`],
+    /// so the callback should trigger a call to it (e.g. by requesting a repaint of the GUI).
+    pub fn set_notifier(&mut self, notifier: impl Fn() + Send + Sync + 'static) {
+        self.forwarder.notifier.set(Some(Arc::new(notifier)));
+    }
+    /// Remove the callback set by [`Pipeline::set_notifier`]
+    pub fn clear_notifier(&mut self) {
+        self.forwarder.notifier.set(None);
+    }
+    /// Progress all stages.
+    pub fn progress(&mut self) {
+        self.forwarder.pending.store(true, Ordering::SeqCst);
+        let mut stages = self.forwarder.stages.lock().unwrap();
+        self.forwarder.progress(stages.as_mut());
+        while let Some((ticket, output)) = stages.pop_done() {
+            let r = self.done.insert(ticket, output);
+            if r.is_some() {
+                panic!("Ticket is already in list of done jobs")
+            }
+        }
+        drop(stages);
+        // jobs which were done meanwhile
+        self.forwarder.forward();
+    }
+    /// Enqueue a new job to the first stage. Use the returned ticket to check later if the job passed all stages.
+    #[must_use]
+    pub fn enqueue(&mut self, input: Input) -> Ticket {
+        let (ticket, ticket_internal) = self.ticket_generator.next();
+        self.forwarder
+            .stages
+            .lock()
+            .unwrap()
+            .push(ticket_internal, input);
+        self.progress();
+        ticket
+    }
+    /// Check if a job is done. If so, its output is taken.
+    /// For jobs not yet done, the position or task refers to the stage the job is in.
+    ///
+    /// A job whose output was already taken is [`JobState::Unknown`].
+    #[must_use]
+    pub fn check(&mut self, ticket: Ticket) -> JobState<Output> {
+        self.progress();
+        match self.done.remove(&ticket) {
+            Some(Outcome::Done(output)) => JobState::Done(output),
+            Some(Outcome::Crashed) => JobState::Crashed,
+            None => self.status_of(&ticket).ongoing(ticket),
+        }
+    }
+    /// Get the status of a job, without taking its output, see [`Pipeline::check`].
+    #[must_use]
+    pub fn status(&mut self, ticket: &Ticket) -> JobStatus {
+        self.progress();
+        self.status_of(ticket)
+    }
+    fn status_of(&self, ticket: &Ticket) -> JobStatus {
+        match self.done.get(ticket) {
+            Some(Outcome::Done(_)) => JobStatus::Done,
+            Some(Outcome::Crashed) => JobStatus::Crashed,
+            None => self
+                .forwarder
+                .stages
+                .lock()
+                .unwrap()
+                .status(ticket)
+                .unwrap_or(JobStatus::Unknown),
+        }
+    }
+
+    /// Wait for a job to pass all stages
+    ///
+    /// Panics if the job crashed in some stage.
+    #[must_use]
+    pub fn wait_for(&mut self, mut ticket: Ticket) -> Output {
+        loop {
+            match self.check(ticket) {
+                JobState::Queued { ticket: t, .. } | JobState::Running { ticket: t, .. } => {
+                    ticket = t
+                }
+                JobState::Done(output) => return output,
+                JobState::Crashed => panic!("Job crashed"),
+                JobState::Expired => panic!("Job expired"),
+                _ => panic!("Unknown job, its output was already taken"),
+            }
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn test_pipeline_std() {
+    #[derive(Default)]
+    struct Increment;
+    impl Function for Increment {
+        type Input = u32;
+        type Output = u64;
+        fn call(&mut self, input: Self::Input) -> Self::Output {
+            (input + 1) as _
+        }
+    }
+    #[derive(Default)]
+    struct Double;
+    impl Function for Double {
+        type Input = u64;
+        type Output = u64;
+        fn call(&mut self, input: Self::Input) -> Self::Output {
+            input * 2
+        }
+    }
+    #[derive(Default)]
+    struct Format;
+    impl Function for Format {
+        type Input = u64;
+        type Output = String;
+        fn call(&mut self, input: Self::Input) -> Self::Output {
+            input.to_string()
+        }
+    }
+
+    let mut pipeline = Pipeline::new::<Increment>("increment_thread", 2)
+        .then::<Double>("double_thread", 3, 1)
+        .then::<Format>("format_thread", 1, 2);
+    let n = 10;
+    let mut tickets = Vec::new();
+    for i in 0..n {
+        tickets.push(pipeline.enqueue(i));
+    }
+    for (i, ticket) in tickets.into_iter().enumerate() {
+        let v = pipeline.wait_for(ticket);
+        assert_eq!(((i + 1) * 2).to_string(), v);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn test_pipeline_forwarding() {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Condvar;
+    use std::time::Duration;
+
+    static STARTED: AtomicUsize = AtomicUsize::new(0);
+    static FINISHED: AtomicUsize = AtomicUsize::new(0);
+    static RELEASED: (Mutex<bool>, Condvar) = (Mutex::new(false), Condvar::new());
+    #[derive(Default)]
+    struct Produce;
+    impl Function for Produce {
+        type Input = u32;
+        type Output = u32;
+        fn call(&mut self, input: Self::Input) -> Self::Output {
+            STARTED.fetch_add(1, Ordering::SeqCst);
+            input
+        }
+    }
+    #[derive(Default)]
+    struct Consume;
+    impl Function for Consume {
+        type Input = u32;
+        type Output = u32;
+        fn call(&mut self, input: Self::Input) -> Self::Output {
+            let (released, condvar) = &RELEASED;
+            let _released = condvar
+                .wait_while(released.lock().unwrap(), |released| !*released)
+                .unwrap();
+            FINISHED.fetch_add(1, Ordering::SeqCst);
+            input * 10
+        }
+    }
+
+    let mut pipeline =
+        Pipeline::new::<Produce>("produce_thread", 1).then::<Consume>("consume_thread", 1, 1);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let sender = Mutex::new(sender);
+    pipeline.set_notifier(move || sender.lock().unwrap().send(()).unwrap());
+    let wait_until = |count: &AtomicUsize, n| {
+        while count.load(Ordering::SeqCst) < n {
+            receiver
+                .recv_timeout(Duration::from_secs(10))
+                .expect("Stages did not progress");
+        }
+    };
+    let n = 6;
+    let tickets = (0..n).map(|i| pipeline.enqueue(i)).collect::<Vec<_>>();
+    // the consumer runs one job and buffers another one, so the producer holds back after a single output
+    wait_until(&STARTED, 3);
+    assert_eq!(
+        pipeline.status(&tickets[3]),
+        JobStatus::Queued { position: 0 }
+    );
+    let (released, condvar) = &RELEASED;
+    *released.lock().unwrap() = true;
+    condvar.notify_all();
+    // the pipeline is not used, the stages hand over outputs by themselves
+    wait_until(&FINISHED, n as usize);
+    assert_eq!(STARTED.load(Ordering::SeqCst), n as usize);
+    for (i, ticket) in tickets.into_iter().enumerate() {
+        assert_eq!(pipeline.wait_for(ticket), i as u32 * 10);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+#[should_panic(expected = "Stages have to be added before enqueueing jobs")]
+fn test_pipeline_stage_after_enqueue() {
+    #[derive(Default)]
+    struct Identity;
+    impl Function for Identity {
+        type Input = u32;
+        type Output = u32;
+        fn call(&mut self, input: Self::Input) -> Self::Output {
+            input
+        }
+    }
+    let mut pipeline = Pipeline::new::<Identity>("identity_thread", 1);
+    // the job is not yet done, but it is enqueued
+    let _ticket = pipeline.enqueue(1);
+    let _pipeline = pipeline.then::<Identity>("identity_thread", 1, 1);
+}
@@ -0,0 +1,241 @@
+use std::{
+    collections::VecDeque,
+    io::{Read, Write},
+    process::{ChildStdin, Command, Stdio},
+    sync::mpsc::{channel, Receiver, TryRecvError},
+};
+
+use super::{Function, NativeFunction, Outcome};
+use crate::codec::{Bincode, Codec, Coder};
+use crate::notifier::Notifier;
+
+/// Environment variable, which tells a process that it was started as worker process
+const WORKER_ENV: &str = "TASK_SIMPLE_PROCESS_WORKER";
+/// Largest message which is sent or received, so a corrupted length cannot exhaust the memory
+pub(crate) const MAX_FRAME_SIZE: usize = 1 << 30;
+/// Starts each frame, so output which is not a frame (e.g. printed by the worker) is detected
+const FRAME_MAGIC: [u8; 4] = *b"TSF1";
+
+enum Message {
+    Output(Vec<u8>),
+    Exited,
+}
+
+/// A running child process
+struct Child {
+    process: std::process::Child,
+    input: ChildStdin,
+    output: Receiver<Message>,
+}
+
+pub(super) struct TaskProcess<F: NativeFunction> {
+    task_name: String,
+    /// The child process, `None` if it could not be restarted
+    child: Option<Child>,
+    /// Encoded inputs, which are sent to the child but not yet answered.
+    /// `None` if the input is too large to be sent, so the job crashes without the child.
+    pending: VecDeque<Option<Vec<u8>>>,
+    notifier: Notifier,
+    input_coder: Coder<F::Input>,
+    output_coder: Coder<F::Output>,
+}
+impl<F: Function> TaskProcess<F> {
+    pub(super) fn new<C: Codec>(task_name: &str, notifier: Notifier) -> std::io::Result<Self> {
+        let child = spawn(task_name, notifier.clone())?;
+        Ok(Self {
+            task_name: task_name.into(),
+            child: Some(child),
+            pending: Default::default(),
+            notifier,
+            input_coder: Coder::new::<C>(),
+            output_coder: Coder::new::<C>(),
+        })
+    }
+}
+impl<F: NativeFunction> TaskProcess<F> {
+    pub(super) fn enqueue(&mut self, msg: F::Input) {
+        let msg = self.input_coder.encode(&msg, "Input");
+        if msg.len() > MAX_FRAME_SIZE {
+            // the child would reject the input, so the job is reported as crashed right away
+            self.pending.push_back(None);
+            self.notifier.notify();
+            return;
+        }
+        if self.child.is_none() {
+            self.restart();
+        }
+        match &mut self.child {
+            // if the child crashed, this fails - the input is resent after the restart
+            Some(child) => {
+                let _ = write_frame(&mut child.input, &msg);
+            }
+            // the job is reported as crashed right away
+            None => self.notifier.notify(),
+        }
+        self.pending.push_back(Some(msg));
+    }
+    pub(super) fn check(&mut self) -> Option<Outcome<F::Output>> {
+        if let Some(None) = self.pending.front() {
+            self.pending.pop_front();
+            return Some(Outcome::Crashed);
+        }
+        let Some(child) = &self.child else {
+            // the child could not be restarted, so all jobs crash
+            return self.pending.pop_front().map(|_| Outcome::Crashed);
+        };
+        match child.output.try_recv() {
+            Ok(Message::Output(output)) => {
+                self.pending.pop_front();
+                match self.output_coder.try_decode(&output) {
+                    Ok(output) => Some(Outcome::Done(output)),
+                    Err(_) => {
+                        // the child does not talk the expected protocol, so it is not trusted further
+                        self.restart();
+                        Some(Outcome::Crashed)
+                    }
+                }
+            }
+            Ok(Message::Exited) | Err(TryRecvError::Disconnected) => {
+                let crashed = self.pending.pop_front();
+                self.restart();
+                crashed.map(|_| Outcome::Crashed)
+            }
+            Err(TryRecvError::Empty) => None,
+        }
+    }
+    /// Start a new child process, resending all pending inputs.
+    /// If this fails, the task is left without child process, and is restarted with the next job.
+    fn restart(&mut self) {
+        if let Some(mut child) = self.child.take() {
+            let _ = child.process.kill();
+            let _ = child.process.wait();
+        }
+        if let Ok(mut child) = spawn(&self.task_name, self.notifier.clone()) {
+            for msg in self.pending.iter().flatten() {
+                let _ = write_frame(&mut child.input, msg);
+            }
+            self.child = Some(child);
+        }
+    }
+}
+impl<F: NativeFunction> Drop for TaskProcess<F> {
+    fn drop(&mut self) {
+        if let Some(child) = &mut self.child {
+            let _ = child.process.kill();
+            let _ = child.process.wait();
+        }
+    }
+}
+
+fn spawn(task_name: &str, notifier: Notifier) -> std::io::Result<Child> {
+    let mut process = Command::new(std::env::current_exe()?)
+        .env(WORKER_ENV, task_name)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let input = process.stdin.take().unwrap();
+    let mut stdout = process.stdout.take().unwrap();
+    let (output_sender, output_receiver) = channel();
+    std::thread::Builder::new()
+        .name(format!("{task_name}_reader"))
+        .spawn(move || {
+            // a broken frame (e.g. the worker printed to stdout) is handled like a crash, so the child is restarted
+            while let Ok(output) = read_frame(&mut stdout) {
+                if output_sender.send(Message::Output(output)).is_err() {
+                    return;
+                }
+                notifier.notify();
+            }
+            if output_sender.send(Message::Exited).is_ok() {
+                notifier.notify();
+            }
+        })?;
+    Ok(Child {
+        process,
+        input,
+        output: output_receiver,
+    })
+}
+
+fn write_frame(writer: &mut impl Write, msg: &[u8]) -> std::io::Result<()> {
+    if msg.len() > MAX_FRAME_SIZE {
+        return Err(std::io::ErrorKind::InvalidInput.into());
+    }
+    writer.write_all(&FRAME_MAGIC)?;
+    writer.write_all(&(msg.len() as u64).to_le_bytes())?;
+    writer.write_all(msg)?;
+    writer.flush()
+}
+fn read_frame(reader: &mut impl Read) -> std::io::Result<Vec<u8>> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != FRAME_MAGIC {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "Received something which is not a message",
+        ));
+    }
+    let mut length = [0; 8];
+    reader.read_exact(&mut length)?;
+    let length = u64::from_le_bytes(length);
+    if length > MAX_FRAME_SIZE as u64 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Message of {length} bytes is too large"),
+        ));
+    }
+    let mut msg = vec![0; length as usize];
+    reader.read_exact(&mut msg)?;
+    Ok(msg)
+}
+
+/// This is a worker process running the Function F::call, see [`super::Backend::Process`].
+/// The worker process talks to its owner via stdin/stdout, so it must not print to stdout itself, use stderr instead.
+/// Anything else on stdout breaks the next message, whose job is then reported as crashed.
+#[derive(Debug)]
+pub struct ProcessWorker<F>(F);
+impl<F: Function> ProcessWorker<F> {
+    /// If this executable was started as worker process for `task_name`,
+    /// run all jobs and exit the process once the owner is gone. Otherwise, return immediately.
+    /// Call this at the very beginning of `main`, once for each function used with [`super::Backend::Process`].
+    pub fn serve_if_requested(task_name: &str) {
+        Self::serve_with_codec_if_requested::<Bincode>(task_name)
+    }
+    /// Same as [`ProcessWorker::serve_if_requested`], for tasks created using [`super::Task::with_codec`]
+    pub fn serve_with_codec_if_requested<C: Codec>(task_name: &str) {
+        if std::env::var_os(WORKER_ENV).is_some_and(|name| name == task_name) {
+            Self(F::default()).serve::<C>();
+            std::process::exit(0);
+        }
+    }
+    fn serve<C: Codec>(mut self) {
+        let input_coder = Coder::<F::Input>::new::<C>();
+        let output_coder = Coder::<F::Output>::new::<C>();
+        let mut stdin = std::io::stdin().lock();
+        let mut stdout = std::io::stdout();
+        while let Ok(input) = read_frame(&mut stdin) {
+            let input = input_coder.decode(&input, "Input");
+            let output = self.0.call(input);
+            let output = output_coder.encode(&output, "Output");
+            // an output which is too large is not sent, so the owner reports the job as crashed
+            if write_frame(&mut stdout, &output).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+#[test]
+fn test_frame_size() {
+    let mut frame = Vec::new();
+    write_frame(&mut frame, b"message").unwrap();
+    assert_eq!(read_frame(&mut frame.as_slice()).unwrap(), b"message");
+    // a corrupted length is rejected instead of allocated
+    let corrupted = [FRAME_MAGIC.as_slice(), &u64::MAX.to_le_bytes()].concat();
+    let error = read_frame(&mut corrupted.as_slice()).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    // so is output which is not a frame
+    let printed = [b"Hello\n".as_slice(), &frame].concat();
+    let error = read_frame(&mut printed.as_slice()).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+}
@@ -0,0 +1,236 @@
+use std::collections::{HashSet, VecDeque};
+use std::time::Duration;
+
+use super::{AnyFunction, Outcome, PoolTask, TaskPool, Ticket};
+use web_time::Instant;
+
+/// This limits how many outputs of finished jobs a [`TaskPool`] keeps, if they are not taken.
+/// Evicted jobs are reported as [`super::JobState::Expired`]. This is remembered within the same limits,
+/// i.e. for another `max_age` or for at most `max_results` evicted jobs; afterwards they are unknown.
+/// Outputs are evicted whenever the pool progresses, e.g. on [`TaskPool::enqueue`] or [`TaskPool::check`].
+/// By default, all outputs are kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Retention {
+    /// Keep at most this number of outputs, evicting the oldest ones
+    pub max_results: Option<usize>,
+    /// Keep outputs at most this long (after the job is done)
+    pub max_age: Option<Duration>,
+}
+impl Retention {
+    /// Check if an entry is to be dropped, given the number of entries and when it was added
+    fn exceeded(&self, len: usize, since: Instant, now: Instant) -> bool {
+        self.max_results
+            .is_some_and(|max_results| len > max_results)
+            || self
+                .max_age
+                .is_some_and(|max_age| now.duration_since(since) > max_age)
+    }
+}
+
+/// Jobs whose output was evicted, see [`Retention`]
+#[derive(Debug, Default)]
+pub(super) struct Expired {
+    tickets: HashSet<Ticket>,
+    /// Index of expired jobs, in the order they expired, together with the time they expired
+    order: VecDeque<(usize, Instant)>,
+}
+impl Expired {
+    pub(super) fn contains(&self, ticket: &Ticket) -> bool {
+        self.tickets.contains(ticket)
+    }
+    pub(super) fn remove(&mut self, ticket: &Ticket) -> bool {
+        self.tickets.remove(ticket)
+    }
+    fn insert(&mut self, ticket: Ticket, now: Instant) {
+        self.order.push_back((ticket.index(), now));
+        self.tickets.insert(ticket);
+    }
+    fn evict(&mut self, retention: &Retention, now: Instant) {
+        while let Some(&(index, expired)) = self.order.front() {
+            let ticket = Ticket::nth(index);
+            if self.tickets.contains(&ticket) {
+                if !retention.exceeded(self.tickets.len(), expired, now) {
+                    break;
+                }
+                self.tickets.remove(&ticket);
+            }
+            self.order.pop_front();
+        }
+        compact(&mut self.order, self.tickets.len(), |index| {
+            self.tickets.contains(&Ticket::nth(index))
+        });
+    }
+}
+
+/// Drop the entries of jobs which were taken, once they are the majority.
+/// So the queue stays proportional to the number of jobs kept, even if the oldest job is never taken.
+fn compact(order: &mut VecDeque<(usize, Instant)>, kept: usize, is_kept: impl Fn(usize) -> bool) {
+    if order.len() > 2 * kept {
+        order.retain(|&(index, _)| is_kept(index));
+    }
+}
+
+impl<F: AnyFunction> TaskPool<F> {
+    /// Set the retention policy for outputs which are not taken, see [`Retention`]
+    pub fn set_retention(&mut self, retention: Retention) {
+        self.retention = retention;
+        self.evict();
+    }
+    /// Forget a job: its output is dropped (now or once the job is done).
+    /// If the job is not yet started, it is not run at all.
+    pub fn forget(&mut self, ticket: Ticket) {
+        if self.drop_output(&ticket) || self.expired.remove(&ticket) {
+            return;
+        }
+        if let Some(position) = self.to_start.iter().position(|(t, _)| t == &ticket) {
+            self.to_start.remove(position);
+        } else if self
+            .tasks
+            .iter()
+            .any(|PoolTask { job, .. }| matches!(job, Some((t, _)) if t == &ticket))
+        {
+            self.forgotten.insert(ticket);
+        }
+    }
+    /// Store the outcome of a job, which is done
+    pub(super) fn finish(&mut self, ticket: Ticket, outcome: Outcome<F::Output>) {
+        if self.forgotten.remove(&ticket) {
+            return;
+        }
+        self.finished.push_back((ticket.index(), Instant::now()));
+        let r = self.done.insert(ticket, outcome);
+        if r.is_some() {
+            panic!("Ticket is already in list of done jobs")
+        }
+    }
+    /// Evict outputs according to the retention policy
+    pub(super) fn evict(&mut self) {
+        self.evict_at(Instant::now());
+    }
+    fn evict_at(&mut self, now: Instant) {
+        while let Some(&(index, finished)) = self.finished.front() {
+            let ticket = Ticket::nth(index);
+            if self.is_kept(&ticket) {
+                if !self.retention.exceeded(self.kept(), finished, now) {
+                    break;
+                }
+                self.drop_output(&ticket);
+                self.expired.insert(ticket, now);
+            }
+            self.finished.pop_front();
+        }
+        let mut finished = std::mem::take(&mut self.finished);
+        compact(&mut finished, self.kept(), |index| {
+            self.is_kept(&Ticket::nth(index))
+        });
+        self.finished = finished;
+        self.expired.evict(&self.retention, now);
+    }
+    /// Number of outputs kept, of jobs and of closures (see [`TaskPool::spawn`])
+    fn kept(&self) -> usize {
+        #[cfg(not(target_arch = "wasm32"))]
+        return self.done.len() + self.spawned.len();
+        #[cfg(target_arch = "wasm32")]
+        return self.done.len();
+    }
+    fn is_kept(&self, ticket: &Ticket) -> bool {
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.spawned.contains(ticket) {
+            return true;
+        }
+        self.done.contains_key(ticket)
+    }
+    /// Drop the output of a job or a closure. Returns false if it is not kept.
+    fn drop_output(&mut self, ticket: &Ticket) -> bool {
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.spawned.remove(ticket) {
+            return true;
+        }
+        self.done.remove(ticket).is_some()
+    }
+}
+
+#[test]
+fn test_retention() {
+    use super::{Backend, Function, JobState, JobStatus};
+    #[derive(Default)]
+    struct DummyFunction;
+    impl Function for DummyFunction {
+        type Input = u32;
+        type Output = u64;
+        fn call(&mut self, input: Self::Input) -> Self::Output {
+            input as _
+        }
+    }
+
+    // the inline task runs the jobs whenever the pool progresses, e.g. when enqueueing
+    let mut task_pool = TaskPool::<DummyFunction>::with_backend("dummy", 1, Backend::Inline);
+    task_pool.set_retention(Retention {
+        max_results: Some(2),
+        max_age: None,
+    });
+    let mut tickets = (0..5).map(|i| task_pool.enqueue(i)).collect::<Vec<_>>();
+    // the last job is still running, its output is dropped once it is done
+    task_pool.forget(tickets.remove(4));
+    assert_eq!(task_pool.status(&tickets[0]), JobStatus::Expired);
+    assert!(matches!(
+        task_pool.check(tickets.remove(0)),
+        JobState::Expired
+    ));
+    assert!(matches!(
+        task_pool.check(tickets.remove(0)),
+        JobState::Expired
+    ));
+    assert!(matches!(
+        task_pool.check(tickets.remove(0)),
+        JobState::Done(2)
+    ));
+    assert!(matches!(
+        task_pool.check(tickets.remove(0)),
+        JobState::Done(3)
+    ));
+    assert_eq!(task_pool.drain_completed().count(), 0);
+
+    // expired jobs are remembered within the same limits
+    let tickets = (0..5).map(|i| task_pool.enqueue(i)).collect::<Vec<_>>();
+    assert_eq!(task_pool.status(&tickets[0]), JobStatus::Unknown);
+    assert_eq!(task_pool.status(&tickets[1]), JobStatus::Expired);
+    assert_eq!(task_pool.status(&tickets[4]), JobStatus::Done);
+    assert!(task_pool.expired.order.len() <= 2);
+
+    task_pool.set_retention(Retention {
+        max_results: None,
+        max_age: Some(Duration::from_secs(60)),
+    });
+    let ticket = task_pool.enqueue(5);
+    assert_eq!(task_pool.status(&ticket), JobStatus::Done);
+    let now = Instant::now();
+    task_pool.evict_at(now + Duration::from_secs(61));
+    assert_eq!(task_pool.status(&ticket), JobStatus::Expired);
+    task_pool.evict_at(now + Duration::from_secs(122));
+    assert_eq!(task_pool.status(&ticket), JobStatus::Unknown);
+}
+
+#[test]
+fn test_retention_bounded() {
+    use super::{Backend, Function};
+    #[derive(Default)]
+    struct DummyFunction;
+    impl Function for DummyFunction {
+        type Input = u32;
+        type Output = u64;
+        fn call(&mut self, input: Self::Input) -> Self::Output {
+            input as _
+        }
+    }
+
+    // by default, all outputs are kept: the oldest one is never taken, the others are
+    let mut task_pool = TaskPool::<DummyFunction>::with_backend("dummy", 1, Backend::Inline);
+    let _oldest = task_pool.enqueue(0);
+    for i in 1..100 {
+        let ticket = task_pool.enqueue(i);
+        let _ = task_pool.check(ticket);
+    }
+    assert_eq!(task_pool.done.len(), 1);
+    assert!(task_pool.finished.len() <= 2);
+}
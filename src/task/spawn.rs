@@ -0,0 +1,312 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::{Job, JobState, NativeFunction, PoolTask, Task, TaskBackend, TaskPool, Ticket};
+use web_time::Instant;
+
+/// A closure to run, which returns its output boxed
+pub(super) type BoxedClosure = Box<dyn FnOnce() -> Box<dyn Any + Send> + Send>;
+
+/// Standard only: a function without jobs of its own, for a pool which only runs closures, see [`TaskPool::spawn`]
+#[derive(Debug, Default)]
+pub struct Closure;
+impl NativeFunction for Closure {
+    type Input = std::convert::Infallible;
+    type Output = std::convert::Infallible;
+    fn call(&mut self, input: Self::Input) -> Self::Output {
+        input
+    }
+}
+
+/// A ticket of a closure, which knows the output type of the closure
+pub struct JobHandle<T> {
+    /// The pool which runs the closure
+    pool: usize,
+    ticket: Ticket,
+    _output: std::marker::PhantomData<fn() -> T>,
+}
+impl<T> std::fmt::Debug for JobHandle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JobHandle")
+            .field("pool", &self.pool)
+            .field("ticket", &self.ticket)
+            .finish()
+    }
+}
+
+/// The closures of a pool, see [`TaskPool::spawn`]
+#[derive(Debug)]
+pub(super) struct Spawned {
+    /// Identifies the pool, so handles of other pools are rejected
+    pool: usize,
+    /// Outputs of closures which are done
+    ran: HashMap<Ticket, Box<dyn Any + Send>>,
+}
+impl Default for Spawned {
+    fn default() -> Self {
+        static NEXT_POOL: AtomicUsize = AtomicUsize::new(0);
+        Self {
+            pool: NEXT_POOL.fetch_add(1, Ordering::Relaxed),
+            ran: HashMap::new(),
+        }
+    }
+}
+
+impl Spawned {
+    pub(super) fn contains(&self, ticket: &Ticket) -> bool {
+        self.ran.contains_key(ticket)
+    }
+    pub(super) fn remove(&mut self, ticket: &Ticket) -> bool {
+        self.ran.remove(ticket).is_some()
+    }
+    pub(super) fn len(&self) -> usize {
+        self.ran.len()
+    }
+}
+
+/// Standard only: the pool cannot run closures, as some of its tasks run in a child process
+/// (see [`super::Backend::Process`]). This is returned by [`TaskPool::spawn`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClosuresUnsupported;
+impl std::fmt::Display for ClosuresUnsupported {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("The backend of the pool cannot run closures")
+    }
+}
+impl std::error::Error for ClosuresUnsupported {}
+
+impl<F: NativeFunction> TaskBackend<F> {
+    /// Check if the backend can run closures, which cannot be sent to a child process
+    fn runs_closures(&self) -> bool {
+        match self {
+            #[cfg(feature = "process")]
+            TaskBackend::Process(_) => false,
+            _ => true,
+        }
+    }
+}
+
+impl<F: NativeFunction> Task<F> {
+    pub(super) fn enqueue_closure(&mut self, closure: BoxedClosure) {
+        self.task_count += 1;
+        match &mut self.task {
+            TaskBackend::Thread(task) => task.enqueue_closure(closure),
+            TaskBackend::Inline(task) => task.enqueue_closure(closure),
+            #[cfg(feature = "process")]
+            TaskBackend::Process(_) => unreachable!("Closures are rejected by TaskPool::spawn"),
+        }
+    }
+    pub(super) fn poll_ran(&mut self) -> Option<Box<dyn Any + Send>> {
+        let ran = match &mut self.task {
+            TaskBackend::Thread(task) => task.check_ran(),
+            TaskBackend::Inline(task) => task.check_ran(),
+            #[cfg(feature = "process")]
+            TaskBackend::Process(_) => None,
+        };
+        if ran.is_some() {
+            self.task_count -= 1;
+        }
+        ran
+    }
+}
+
+impl<F: NativeFunction> TaskPool<F> {
+    /// Standard only: Enqueue a closure, which is run by some task of this pool, in turn with the jobs.
+    /// It is run like a job of the backend, e.g. [`super::Backend::Inline`] runs it once its output is requested.
+    /// Closures cannot be sent to a child process, so a pool using [`super::Backend::Process`] rejects them.
+    ///
+    /// Use the returned handle to check later if the closure is done, like the ticket of a job.
+    /// If the closure panics, it is reported as [`JobState::Crashed`].
+    /// Its output is evicted by [`super::Retention`] like the output of a job.
+    pub fn spawn<T: Send + 'static>(
+        &mut self,
+        f: impl FnOnce() -> T + Send + 'static,
+    ) -> Result<JobHandle<T>, ClosuresUnsupported> {
+        if !self
+            .tasks
+            .iter()
+            .all(|PoolTask { task, .. }| task.task.runs_closures())
+        {
+            return Err(ClosuresUnsupported);
+        }
+        let (ticket, ticket_internal) = self.ticket_generator.next();
+        let closure: BoxedClosure = Box::new(move || {
+            // a panic is caught, so the thread keeps running
+            Box::new(std::panic::catch_unwind(AssertUnwindSafe(f)).ok())
+        });
+        self.to_start
+            .push_back((ticket_internal, Job::Closure(closure)));
+        self.progress();
+        Ok(JobHandle {
+            pool: self.spawned.pool,
+            ticket,
+            _output: Default::default(),
+        })
+    }
+    /// Check if a closure is done, see [`TaskPool::check`].
+    /// A handle of another pool is [`JobState::Unknown`].
+    #[must_use]
+    pub fn check_spawned<T: 'static>(&mut self, handle: JobHandle<T>) -> JobState<T, JobHandle<T>> {
+        if handle.pool != self.spawned.pool {
+            return JobState::Unknown(handle);
+        }
+        self.progress();
+        let JobHandle {
+            pool,
+            ticket,
+            _output,
+        } = handle;
+        match self.spawned.ran.remove(&ticket) {
+            // the handle belongs to this pool, so the output type is fixed,
+            // unless the closure is handed back without output as its thread is gone
+            Some(output) => match output
+                .downcast::<Option<T>>()
+                .ok()
+                .and_then(|output| *output)
+            {
+                Some(output) => JobState::Done(output),
+                None => JobState::Crashed,
+            },
+            None if self.expired.remove(&ticket) => JobState::Expired,
+            None => self.status_of(&ticket).ongoing(ticket).map(
+                |output| output,
+                |ticket| JobHandle {
+                    pool,
+                    ticket,
+                    _output,
+                },
+            ),
+        }
+    }
+    /// Forget a closure, see [`TaskPool::forget`]. A handle of another pool is ignored.
+    pub fn forget_spawned<T>(&mut self, handle: JobHandle<T>) {
+        if handle.pool == self.spawned.pool {
+            self.forget(handle.ticket);
+        }
+    }
+    /// Wait for a closure to finish, see [`TaskPool::wait_for`].
+    ///
+    /// Panics if the closure panicked, expired (see [`super::Retention`]) or if the handle is unknown.
+    #[must_use]
+    pub fn wait_for_spawned<T: 'static>(&mut self, mut handle: JobHandle<T>) -> T {
+        loop {
+            let seen = self.notified();
+            match self.check_spawned(handle) {
+                JobState::Queued { ticket, .. } | JobState::Running { ticket, .. } => {
+                    handle = ticket;
+                    self.wait_notified(seen);
+                }
+                JobState::Done(output) => return output,
+                JobState::Crashed => panic!("Job crashed"),
+                JobState::Expired => panic!("Job expired"),
+                _ => panic!("Unknown job, its output was already taken"),
+            }
+        }
+    }
+    /// Store the output of a closure, which is done
+    pub(super) fn finish_spawned(&mut self, ticket: Ticket, output: Box<dyn Any + Send>) {
+        if self.forgotten.remove(&ticket) {
+            return;
+        }
+        self.finished.push_back((ticket.index(), Instant::now()));
+        self.spawned.ran.insert(ticket, output);
+    }
+}
+
+#[test]
+fn test_spawn() {
+    use super::{Backend, Function};
+    #[derive(Default)]
+    struct DummyFunction;
+    impl Function for DummyFunction {
+        type Input = u32;
+        type Output = u64;
+        fn call(&mut self, input: Self::Input) -> Self::Output {
+            input as _
+        }
+    }
+
+    let mut task_pool = TaskPool::<Closure>::new_native("closure_thread", 2);
+    let data = std::sync::Arc::new(vec![1u64, 2, 3]);
+    let shared = data.clone();
+    let sum = task_pool.spawn(move || shared.iter().sum::<u64>()).unwrap();
+    let text = task_pool.spawn(|| "done".to_string()).unwrap();
+    assert_eq!(task_pool.wait_for_spawned(text), "done");
+    assert_eq!(task_pool.wait_for_spawned(sum), 6);
+
+    // a panic is reported, and the thread keeps running
+    let crash = task_pool
+        .spawn(|| -> u32 { panic!("Closure panicked") })
+        .unwrap();
+    assert!(
+        std::panic::catch_unwind(AssertUnwindSafe(|| task_pool.wait_for_spawned(crash))).is_err()
+    );
+    let after = task_pool.spawn(|| 1).unwrap();
+    assert_eq!(task_pool.wait_for_spawned(after), 1);
+
+    // closures run in turn with the jobs of the pool, and handles of other pools are rejected
+    for backend in [Backend::Worker, Backend::Inline] {
+        let mut other = TaskPool::<DummyFunction>::with_backend("dummy", 1, backend);
+        let ticket = other.enqueue(7);
+        let handle = other.spawn(|| 8).unwrap();
+        let foreign = task_pool.spawn(|| 9).unwrap();
+        assert!(matches!(other.check_spawned(foreign), JobState::Unknown(_)));
+        assert_eq!(other.wait_for(ticket), 7);
+        assert_eq!(other.wait_for_spawned(handle), 8);
+    }
+}
+
+#[test]
+fn test_spawn_queued() {
+    use super::{Backend, Function, Retention};
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    #[derive(Default)]
+    struct DummyFunction;
+    impl Function for DummyFunction {
+        type Input = u32;
+        type Output = u32;
+        fn call(&mut self, input: Self::Input) -> Self::Output {
+            input
+        }
+    }
+
+    // an inline pool runs the closure once its output is requested, not when it is spawned
+    let mut task_pool = TaskPool::<DummyFunction>::with_backend("dummy", 1, Backend::Inline);
+    let ran = Arc::new(AtomicBool::new(false));
+    let flag = ran.clone();
+    let handle = task_pool
+        .spawn(move || flag.swap(true, Ordering::SeqCst))
+        .unwrap();
+    assert!(!ran.load(Ordering::SeqCst));
+    assert!(!task_pool.wait_for_spawned(handle));
+    assert!(ran.load(Ordering::SeqCst));
+
+    // a manual pool only runs it when stepped
+    let mut task_pool = TaskPool::<DummyFunction>::with_backend("dummy", 1, Backend::Manual);
+    task_pool.set_retention(Retention {
+        max_results: Some(1),
+        max_age: None,
+    });
+    let first = task_pool.spawn(|| 1).unwrap();
+    let second = task_pool.spawn(|| 2).unwrap();
+    let ran = Arc::new(AtomicBool::new(false));
+    let flag = ran.clone();
+    let forgotten = task_pool
+        .spawn(move || flag.store(true, Ordering::SeqCst))
+        .unwrap();
+    let JobState::Running { ticket: first, .. } = task_pool.check_spawned(first) else {
+        panic!("The first closure is started, but not run");
+    };
+    // a forgotten closure, which is not yet started, is not run at all
+    task_pool.forget_spawned(forgotten);
+    assert!(task_pool.step());
+    assert!(task_pool.step());
+    assert!(!task_pool.step());
+    assert!(!ran.load(Ordering::SeqCst));
+    // outputs of closures are evicted like outputs of jobs
+    assert!(matches!(task_pool.check_spawned(first), JobState::Expired));
+    assert_eq!(task_pool.wait_for_spawned(second), 2);
+}
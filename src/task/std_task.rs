@@ -1,48 +1,112 @@
 use std::{
-    sync::mpsc::{channel, Receiver, Sender},
+    any::Any,
+    collections::VecDeque,
+    panic::AssertUnwindSafe,
+    sync::mpsc::{channel, Receiver, Sender, TryRecvError},
     thread::JoinHandle,
 };
 
-use super::Function;
+use super::spawn::BoxedClosure;
+use super::{Finished, Job, NativeFunction, Outcome};
+use crate::notifier::Notifier;
 
-pub(super) struct TaskStd<F: Function> {
-    input: Sender<F::Input>,
-    output: Receiver<F::Output>,
+pub(super) struct TaskStd<F: NativeFunction> {
+    input: Sender<Job<F::Input>>,
+    output: Receiver<Finished<Outcome<F::Output>>>,
+    /// For each job handed to the thread, whose output is not yet received, whether it is a closure
+    sent: VecDeque<bool>,
+    /// The next finished job, if it was received but not yet taken
+    peeked: Option<Finished<Outcome<F::Output>>>,
     _thread: JoinHandle<()>,
 }
-impl<F: Function> TaskStd<F> {
-    pub(super) fn new(thread_name: &str) -> Self {
+impl<F: NativeFunction> TaskStd<F> {
+    pub(super) fn new(thread_name: &str, notifier: Notifier) -> std::io::Result<Self> {
         let (input_sender, input_receiver) = channel();
         let (output_sender, output_receiver) = channel();
         let thread = std::thread::Builder::new()
             .name(thread_name.into())
             .spawn(move || {
                 let mut function = F::default();
-                while let Ok(input) = input_receiver.recv() {
-                    let output = function.call(input);
-                    if output_sender.send(output).is_err() {
+                while let Ok(job) = input_receiver.recv() {
+                    let finished = match job {
+                        // a panic is caught, so the thread keeps running, with a new function like a restarted process
+                        Job::Input(input) => Finished::Output(
+                            match std::panic::catch_unwind(AssertUnwindSafe(|| {
+                                function.call(input)
+                            })) {
+                                Ok(output) => Outcome::Done(output),
+                                Err(_) => {
+                                    function = F::default();
+                                    Outcome::Crashed
+                                }
+                            },
+                        ),
+                        Job::Closure(closure) => Finished::Ran(closure()),
+                    };
+                    if output_sender.send(finished).is_err() {
                         break;
                     }
+                    notifier.notify();
                 }
-            })
-            .unwrap();
-        Self {
+            })?;
+        Ok(Self {
             input: input_sender,
             output: output_receiver,
+            sent: VecDeque::new(),
+            peeked: None,
             _thread: thread,
+        })
+    }
+    pub(super) fn enqueue(&mut self, msg: F::Input) {
+        // if the thread is gone, the job is reported as crashed by `next`
+        let _ = self.input.send(Job::Input(msg));
+        self.sent.push_back(false);
+    }
+    pub(super) fn enqueue_closure(&mut self, closure: BoxedClosure) {
+        let _ = self.input.send(Job::Closure(closure));
+        self.sent.push_back(true);
+    }
+    /// The outcome of the next finished job, unless it is a closure
+    pub(super) fn check(&mut self) -> Option<Outcome<F::Output>> {
+        match self.next()? {
+            Finished::Output(outcome) => Some(outcome),
+            ran => {
+                self.peeked = Some(ran);
+                None
+            }
         }
     }
-    pub(super) fn enqueue(&self, msg: F::Input) {
-        let r = self.input.send(msg);
-        assert!(r.is_ok());
+    /// The output of the next finished job, if it is a closure
+    pub(super) fn check_ran(&mut self) -> Option<Box<dyn Any + Send>> {
+        match self.next()? {
+            Finished::Ran(output) => Some(output),
+            outcome => {
+                self.peeked = Some(outcome);
+                None
+            }
+        }
     }
-    pub(super) fn check(&self) -> Option<F::Output> {
-        self.output.try_recv().ok()
+    fn next(&mut self) -> Option<Finished<Outcome<F::Output>>> {
+        if let Some(peeked) = self.peeked.take() {
+            return Some(peeked);
+        }
+        let finished = match self.output.try_recv() {
+            Ok(finished) => finished,
+            // the thread is gone, so the jobs it did not finish crashed
+            Err(TryRecvError::Disconnected) => match self.sent.front()? {
+                true => Finished::Ran(Box::new(())),
+                false => Finished::Output(Outcome::Crashed),
+            },
+            Err(TryRecvError::Empty) => return None,
+        };
+        self.sent.pop_front();
+        Some(finished)
     }
 }
 
 #[test]
 fn test_task_std() {
+    use super::Function;
     #[derive(Default)]
     struct DummyFunction;
     impl Function for DummyFunction {
@@ -56,7 +120,7 @@ fn test_task_std() {
         (x + 1) as _
     }
 
-    let task = TaskStd::<DummyFunction>::new("dummy_thread");
+    let mut task = TaskStd::<DummyFunction>::new("dummy_thread", Notifier::default()).unwrap();
     let n = 10;
     for i in 0..n {
         task.enqueue(i);
@@ -65,10 +129,37 @@ fn test_task_std() {
         let i = (i + 1) as u64;
         let v = loop {
             match task.check() {
-                Some(v) => break v,
+                Some(Outcome::Done(v)) => break v,
+                Some(Outcome::Crashed) => panic!("Job crashed"),
                 None => continue,
             }
         };
         assert_eq!(i, v);
     }
 }
+
+#[test]
+fn test_task_std_panic() {
+    use super::{Function, JobState, TaskPool};
+    /// Panics for zero, and counts its calls since it was created
+    #[derive(Default)]
+    struct PanickingFunction(u32);
+    impl Function for PanickingFunction {
+        type Input = u32;
+        type Output = u32;
+        fn call(&mut self, input: Self::Input) -> Self::Output {
+            assert_ne!(input, 0, "Zero");
+            self.0 += 1;
+            self.0
+        }
+    }
+
+    // the crash is reported, and the next job is run by a new function
+    let mut task_pool = TaskPool::<PanickingFunction>::new("panicking_thread", 1);
+    let before = task_pool.enqueue(1);
+    let crashing = task_pool.enqueue(0);
+    let after = task_pool.enqueue(1);
+    assert_eq!(task_pool.wait_for(before), 1);
+    assert_eq!(task_pool.wait_for(after), 1);
+    assert!(matches!(task_pool.check(crashing), JobState::Crashed));
+}
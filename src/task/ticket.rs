@@ -1,6 +1,15 @@
 /// Each enqueue job yields a ticket which can be used to check if a job is done.
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Ticket(usize);
+impl Ticket {
+    /// The ticket of the i-th job
+    pub(super) fn nth(i: usize) -> Self {
+        Self(i)
+    }
+    pub(super) fn index(&self) -> usize {
+        self.0
+    }
+}
 #[derive(Default, Debug)]
 pub(super) struct TicketGenerator(usize);
 impl TicketGenerator {
@@ -9,4 +18,8 @@ impl TicketGenerator {
         self.0 += 1;
         (Ticket(i), Ticket(i))
     }
+    /// Number of tickets handed out so far
+    pub(super) fn count(&self) -> usize {
+        self.0
+    }
 }
@@ -1,22 +1,47 @@
 use std::collections::VecDeque;
 
-use super::Function;
+use super::multi_function::{Envelope, MultiFunction};
+use super::{Function, Outcome};
+use crate::codec::{Bincode, Codec, Coder, GlooCodec};
+use crate::notifier::Notifier;
+
+/// Check if web workers can be spawned, as spawning a worker panics otherwise
+pub(crate) fn workers_available() -> bool {
+    js_sys::Reflect::has(
+        &js_sys::global(),
+        &wasm_bindgen::JsValue::from_str("Worker"),
+    )
+    .unwrap_or(false)
+}
+
+type Outputs<F> = std::rc::Rc<std::cell::Cell<VecDeque<<F as Function>::Output>>>;
+
+fn spawn<F: Function, C: Codec>(
+    javascript_name: &str,
+    notifier: Notifier,
+) -> (Outputs<F>, gloo_worker::WorkerBridge<WebWorker<F>>) {
+    let data_update = std::rc::Rc::new(std::cell::Cell::new(VecDeque::default()));
+    let sender = data_update.clone();
+    let bridge = <WebWorker<F> as gloo_worker::Spawnable>::spawner()
+        .callback(move |response| {
+            // TODO: this seems to be a data-race issue
+            let mut previous = sender.take();
+            previous.push_back(response);
+            sender.set(previous);
+            notifier.notify();
+        })
+        .encoding::<GlooCodec<C>>()
+        .spawn(&format!("./{javascript_name}.js"));
+    (data_update, bridge)
+}
+
 pub(super) struct TaskWasm<F: Function> {
-    data_update: std::rc::Rc<std::cell::Cell<VecDeque<F::Output>>>,
+    data_update: Outputs<F>,
     bridge: gloo_worker::WorkerBridge<WebWorker<F>>,
 }
 impl<F: Function> TaskWasm<F> {
-    pub(super) fn new(javascript_name: &str) -> Self {
-        let data_update = std::rc::Rc::new(std::cell::Cell::new(VecDeque::default()));
-        let sender = data_update.clone();
-        let bridge = <WebWorker<F> as gloo_worker::Spawnable>::spawner()
-            .callback(move |response| {
-                // TODO: this seems to be a data-race issue
-                let mut previous = sender.take();
-                previous.push_back(response);
-                sender.set(previous);
-            })
-            .spawn(&format!("./{javascript_name}.js"));
+    pub(super) fn new<C: Codec>(javascript_name: &str, notifier: Notifier) -> Self {
+        let (data_update, bridge) = spawn::<F, C>(javascript_name, notifier);
         Self {
             data_update,
             bridge,
@@ -27,9 +52,52 @@ impl<F: Function> TaskWasm<F> {
     }
     pub(super) fn check(&self) -> Option<F::Output> {
         let d = self.data_update.as_ref();
-        d.take().pop_front()
+        let mut outputs = d.take();
+        let output = outputs.pop_front();
+        d.set(outputs);
+        output
+    }
+}
+/// A task using a webworker, which hosts several functions, see [`MultiFunction`].
+/// Messages are wrapped into an [`Envelope`] framed by bincode, the payload being encoded by the codec.
+pub(super) struct TaskWasmShared<F: Function> {
+    data_update: Outputs<MultiFunction<()>>,
+    bridge: gloo_worker::WorkerBridge<WebWorker<MultiFunction<()>>>,
+    input_coder: Coder<F::Input>,
+    output_coder: Coder<F::Output>,
+}
+impl<F: Function> TaskWasmShared<F> {
+    pub(super) fn new<C: Codec>(javascript_name: &str, notifier: Notifier) -> Self {
+        assert!(
+            !F::NAME.is_empty(),
+            "A function run by a shared worker needs a name, see Function::NAME"
+        );
+        let (data_update, bridge) = spawn::<MultiFunction<()>, Bincode>(javascript_name, notifier);
+        Self {
+            data_update,
+            bridge,
+            input_coder: Coder::new::<C>(),
+            output_coder: Coder::new::<C>(),
+        }
+    }
+    pub(super) fn enqueue(&mut self, msg: F::Input) {
+        let payload = self.input_coder.encode(&msg, "Input");
+        self.bridge.send(Envelope::new::<F>(payload));
+    }
+    /// The outcome of the next job: if the worker failed to run it (e.g. it does not host the function),
+    /// the job crashed
+    pub(super) fn check(&self) -> Option<Outcome<F::Output>> {
+        let d = self.data_update.as_ref();
+        let mut outputs = d.take();
+        let output = outputs.pop_front();
+        d.set(outputs);
+        output.map(|output| match output {
+            Ok(output) => Outcome::Done(self.output_coder.decode(output.payload(), "Output")),
+            Err(_) => Outcome::Crashed,
+        })
     }
 }
+
 /// This is a webworker running the Function F::call
 #[derive(Debug)]
 pub struct WebWorker<F>(F);
@@ -0,0 +1,112 @@
+#![warn(missing_docs, rust_2018_idioms, unreachable_pub)]
+
+//! Procedural macros for task_simple. Use them via task_simple (feature `macros`).
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, spanned::Spanned, FnArg, Ident, ItemFn, ReturnType};
+
+/// Turn a plain function into a `task_simple::Function`.
+///
+/// This generates a unit struct, named after the function in UpperCamelCase followed by `Function`
+/// (e.g. `doubling` yields `DoublingFunction`). Use `#[function(name = MyName)]` to choose another name.
+/// The input of the function is its argument, several arguments are packed into a tuple.
+/// The name of the struct is the `Function::NAME`, which tags its messages to a shared worker.
+#[proc_macro_attribute]
+pub fn function(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut name = None;
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("name") {
+            name = Some(meta.value()?.parse::<Ident>()?);
+            Ok(())
+        } else {
+            Err(meta.error("unsupported argument, expected `name = ...`"))
+        }
+    });
+    parse_macro_input!(attr with parser);
+    let item = parse_macro_input!(item as ItemFn);
+    expand_function(name, item)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand_function(name: Option<Ident>, item: ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    let signature = &item.sig;
+    if let Some(asyncness) = signature.asyncness {
+        return Err(syn::Error::new(
+            asyncness.span(),
+            "async functions are not supported",
+        ));
+    }
+    if !signature.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            signature.generics.span(),
+            "generic functions are not supported",
+        ));
+    }
+    let types = signature
+        .inputs
+        .iter()
+        .map(|input| match input {
+            FnArg::Typed(input) => Ok(&input.ty),
+            FnArg::Receiver(receiver) => Err(syn::Error::new(
+                receiver.span(),
+                "methods are not supported",
+            )),
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    let function = &signature.ident;
+    let name = name.unwrap_or_else(|| struct_name(function));
+    let visibility = &item.vis;
+    let doc = format!("Function running [`{function}`], generated by `task_simple::function`");
+    let output = match &signature.output {
+        ReturnType::Default => quote!(()),
+        ReturnType::Type(_, output) => quote!(#output),
+    };
+    let (input, call) = if let [input] = types.as_slice() {
+        (quote!(#input), quote!(#function(input)))
+    } else {
+        let arguments = (0..types.len())
+            .map(|i| format_ident!("argument_{i}"))
+            .collect::<Vec<_>>();
+        (
+            quote!((#(#types,)*)),
+            quote! {
+                let (#(#arguments,)*) = input;
+                #function(#(#arguments),*)
+            },
+        )
+    };
+    Ok(quote! {
+        #item
+        #[doc = #doc]
+        #[derive(Debug, Default, Clone, Copy)]
+        #visibility struct #name;
+        impl ::task_simple::Function for #name {
+            type Input = #input;
+            type Output = #output;
+            const NAME: &'static str = ::core::stringify!(#name);
+            fn call(&mut self, input: Self::Input) -> Self::Output {
+                #call
+            }
+        }
+    })
+}
+
+/// `snake_case` function name to `UpperCamelCaseFunction`
+fn struct_name(function: &Ident) -> Ident {
+    let mut name = function
+        .to_string()
+        .split('_')
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect::<String>();
+    name.push_str("Function");
+    Ident::new(&name, Span::call_site())
+}
@@ -0,0 +1,36 @@
+use task_simple::{Backend, Function, Task};
+
+/// Doubling a number
+#[task_simple::function]
+fn doubling(x: f32) -> f64 {
+    x as f64 * 2.
+}
+
+#[task_simple::function(name = Joined)]
+fn join_words(first: String, second: String, count: usize) -> String {
+    format!("{first} {second} ").repeat(count).trim_end().into()
+}
+
+#[task_simple::function]
+fn answer() -> u8 {
+    42
+}
+
+fn run<F: Function>(input: F::Input) -> F::Output {
+    let mut task = Task::<F>::with_backend("macro_task", Backend::Inline);
+    task.enqueue(input);
+    task.check().unwrap()
+}
+
+#[test]
+fn test_function_macro() {
+    assert_eq!(run::<DoublingFunction>(1.5), 3.);
+    assert_eq!(
+        run::<Joined>(("a".into(), "b".into(), 2)),
+        "a b a b".to_string()
+    );
+    assert_eq!(run::<AnswerFunction>(()), 42);
+    assert_eq!(<Joined as Function>::NAME, "Joined");
+    // the plain function is still available
+    assert_eq!(doubling(2.), 4.);
+}
@@ -0,0 +1,141 @@
+//! The process backend re-invokes the current executable, so this test runs without the test harness:
+//! the same `main` is used for the owner and for the worker processes.
+#![cfg_attr(target_arch = "wasm32", allow(dead_code, unused_imports))]
+use task_simple::{Backend, Function, JobCrashed, JobState, Task, TaskPool, Ticket};
+#[cfg(not(target_arch = "wasm32"))]
+use task_simple::{ClosuresUnsupported, ProcessWorker};
+
+#[derive(Default)]
+struct DoublingFunction;
+impl Function for DoublingFunction {
+    type Input = u32;
+    type Output = u64;
+    fn call(&mut self, input: Self::Input) -> Self::Output {
+        // messages pass stdout, so the worker prints to stderr
+        eprintln!("Doubling {input}");
+        input as u64 * 2
+    }
+}
+
+/// Prints to stdout for zero, which breaks the message carrying its output
+#[derive(Default)]
+struct PrintingFunction;
+impl Function for PrintingFunction {
+    type Input = u32;
+    type Output = u32;
+    fn call(&mut self, input: Self::Input) -> Self::Output {
+        if input == 0 {
+            println!("Printed to stdout");
+        }
+        input
+    }
+}
+
+#[derive(Default)]
+struct CrashingFunction;
+impl Function for CrashingFunction {
+    type Input = u32;
+    type Output = u32;
+    fn call(&mut self, input: Self::Input) -> Self::Output {
+        if input == 0 {
+            std::process::abort();
+        }
+        input
+    }
+}
+
+/// Deserializing zero fails, so the owner cannot decode such an output
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+#[serde(try_from = "u32")]
+struct NonZero(u32);
+impl TryFrom<u32> for NonZero {
+    type Error = &'static str;
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Err("zero"),
+            value => Ok(Self(value)),
+        }
+    }
+}
+
+#[derive(Default)]
+struct UndecodableFunction;
+impl Function for UndecodableFunction {
+    type Input = u32;
+    type Output = NonZero;
+    fn call(&mut self, input: Self::Input) -> Self::Output {
+        NonZero(input)
+    }
+}
+
+fn wait<F: Function>(task_pool: &mut TaskPool<F>, mut ticket: Ticket) -> JobState<F::Output> {
+    loop {
+        match task_pool.check(ticket) {
+            JobState::Queued { ticket: t, .. } | JobState::Running { ticket: t, .. } => ticket = t,
+            state => return state,
+        }
+    }
+}
+
+/// Wait for the next job of a single task
+fn wait_job<F: Function>(task: &mut Task<F>) -> Result<F::Output, JobCrashed> {
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    loop {
+        if let Some(output) = task.check_job() {
+            return output;
+        }
+        assert!(std::time::Instant::now() < deadline, "Job timed out");
+        std::thread::yield_now();
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn main() {}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    ProcessWorker::<DoublingFunction>::serve_if_requested("doubling_process");
+    ProcessWorker::<CrashingFunction>::serve_if_requested("crashing_process");
+    ProcessWorker::<UndecodableFunction>::serve_if_requested("undecodable_process");
+    ProcessWorker::<PrintingFunction>::serve_if_requested("printing_process");
+
+    let mut task_pool =
+        TaskPool::<DoublingFunction>::with_backend("doubling_process", 3, Backend::Process);
+    let tickets = (0..10).map(|i| task_pool.enqueue(i)).collect::<Vec<_>>();
+    for (i, ticket) in tickets.into_iter().enumerate() {
+        assert_eq!(task_pool.wait_for(ticket), i as u64 * 2);
+    }
+    // closures cannot be sent to a child process
+    assert_eq!(task_pool.spawn(|| 1).unwrap_err(), ClosuresUnsupported);
+
+    let mut task_pool =
+        TaskPool::<CrashingFunction>::with_backend("crashing_process", 1, Backend::Process);
+    let before = task_pool.enqueue(1);
+    let crashing = task_pool.enqueue(0);
+    let after = task_pool.enqueue(2);
+    assert!(matches!(wait(&mut task_pool, before), JobState::Done(1)));
+    assert!(matches!(wait(&mut task_pool, crashing), JobState::Crashed));
+    assert!(matches!(wait(&mut task_pool, after), JobState::Done(2)));
+
+    // a single task reports the crash in place, so outputs stay paired with their inputs
+    let mut task = Task::<CrashingFunction>::with_backend("crashing_process", Backend::Process);
+    task.enqueue(0);
+    task.enqueue(3);
+    assert_eq!(wait_job(&mut task), Err(JobCrashed));
+    assert_eq!(wait_job(&mut task), Ok(3));
+
+    // an output which cannot be decoded crashes the job, and the child is restarted for the next one
+    let mut task =
+        Task::<UndecodableFunction>::with_backend("undecodable_process", Backend::Process);
+    task.enqueue(0);
+    task.enqueue(4);
+    assert_eq!(wait_job(&mut task), Err(JobCrashed));
+    assert_eq!(wait_job(&mut task), Ok(NonZero(4)));
+
+    // printing to stdout breaks the message, which is detected instead of misread
+    let mut task = Task::<PrintingFunction>::with_backend("printing_process", Backend::Process);
+    task.enqueue(0);
+    task.enqueue(5);
+    assert_eq!(wait_job(&mut task), Err(JobCrashed));
+    assert_eq!(wait_job(&mut task), Ok(5));
+}